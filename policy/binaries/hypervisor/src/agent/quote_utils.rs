@@ -1,4 +1,5 @@
 use anyhow::{Context, Result};
+use attest::report_data::ReportData;
 use std::time::SystemTime;
 use tracing::{debug, info};
 
@@ -36,7 +37,10 @@ pub fn generate_compliance_quote(
     );
 
     // Generate the raw report with the compliance hash
-    let raw_report = crate::utils::attest::generate_raw_report_from_hash(compliance_hash);
+    let raw_report = ReportData::Compliance {
+        hash: compliance_hash,
+    }
+    .to_raw();
 
     // Get the actual TEE attestation quote (TDX/SGX)
     let quote = attest::get_quote(raw_report)
@@ -142,18 +146,25 @@ pub fn verify_compliance_quote_dummy(
     
     match quote_parsed {
         Ok(parsed_quote) => {
-            // Extract report_data from the quote
-            let report_data = parsed_quote.report_data();
-            let embedded_hash = &report_data[..32];
-            
-            // Verify the compliance hash matches what's in the quote
-            if embedded_hash != quote.compliance_hash {
-                debug!(
-                    expected = %const_hex::encode(quote.compliance_hash),
-                    actual = %const_hex::encode(embedded_hash),
-                    "Quote verification failed: compliance hash mismatch"
-                );
-                return Ok(false);
+            // Verify the quote binds this compliance hash, and not the same
+            // hash under another report data kind
+            let expected = ReportData::Compliance {
+                hash: quote.compliance_hash,
+            };
+            match parsed_quote.decode_report_data() {
+                Ok(report_data) if report_data == expected => {}
+                Ok(report_data) => {
+                    debug!(
+                        expected = ?expected,
+                        actual = ?report_data,
+                        "Quote verification failed: compliance hash mismatch"
+                    );
+                    return Ok(false);
+                }
+                Err(e) => {
+                    debug!(error = %e, "Quote verification failed: invalid report data");
+                    return Ok(false);
+                }
             }
             
             info!(
@@ -209,7 +220,7 @@ mod tests {
     }
 
     #[test]
-    #[ignore] // Requires TEE environment
+    #[cfg_attr(not(feature = "mock-attest"), ignore)] // Requires TEE environment or mock attestation
     fn test_generate_quote() {
         let quote = generate_compliance_quote(
             "PriceFeedTool",
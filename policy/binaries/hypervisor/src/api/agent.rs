@@ -1,5 +1,6 @@
 use aes_gcm_siv::aead::Aead;
 use anyhow::{anyhow, Context};
+use attest::report_data::ReportData;
 use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
 use serde::{Deserialize, Serialize};
 use tracing::{info, debug};
@@ -9,7 +10,7 @@ use crate::{
     agent::{AgentExecution, ComplianceChecker, ComplianceResult, CryptoAgent},
     error::HypervisorError,
     types::HypervisorState,
-    utils::crypto,
+    utils::{self, crypto},
 };
 
 pub(crate) fn api_register(router: Router<HypervisorState>) -> Router<HypervisorState> {
@@ -62,6 +63,9 @@ pub struct VerifiableAgentQueryResponse {
     pub execution_hash: String,
     /// TEE attestation quote (hex-encoded)
     pub quote: String,
+    /// Set with signed responses, `quote` then attests the response key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
     /// Compliance check result
     pub compliance: ComplianceResult,
     /// Full execution details (for hash verification)
@@ -197,6 +201,7 @@ async fn verifiable_query_agent(
 
     // Get session keypair
     let (session_sk, session_id) = state
+        .clone()
         .get_session_keypair(&user_pk)
         .ok_or(anyhow!("session not found"))
         .context(StatusCode::UNAUTHORIZED)?;
@@ -265,12 +270,13 @@ async fn verifiable_query_agent(
     // Hash the execution
     let execution_hash = hash_execution(&execution);
 
-    // Generate attestation quote
-    let quote = attest::get_quote(crate::utils::attest::generate_raw_report_from_hash(
-        execution_hash,
-    ))
-    .context("get agent query quote")
-    .context(StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Attest the execution hash
+    let report = ReportData::AgentExecution {
+        hash: execution_hash,
+    };
+    let attestation = utils::attest::attest_response(&state, report)
+        .context("attest agent query")
+        .context(StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Encrypt the response
     let response_nonce = crypto::derive_msg_nonce(execution.final_response.as_bytes());
@@ -298,7 +304,8 @@ async fn verifiable_query_agent(
         response_nonce: const_hex::encode(response_nonce),
         execution_time_ms: execution.execution_time_ms,
         execution_hash: const_hex::encode(execution_hash),
-        quote: const_hex::encode(quote.to_bytes()),
+        quote: attestation.quote_hex(),
+        signature: attestation.signature_hex(),
         compliance,
         execution,
     }))
@@ -1,4 +1,5 @@
 use anyhow::Context;
+use attest::report_data::ReportData;
 use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -6,7 +7,7 @@ use uuid::Uuid;
 use crate::{
     error::HypervisorError,
     types::HypervisorState,
-    utils::{attest::generate_raw_report, crypto},
+    utils::{self, crypto},
 };
 
 pub(crate) fn api_register(router: Router<HypervisorState>) -> Router<HypervisorState> {
@@ -23,25 +24,31 @@ pub struct VerifiableCreateKeyPairResponse {
     pub session_pubkey: String,
     pub session_id: Uuid,
     pub quote: String,
+    /// Set with signed responses, `quote` then attests the response key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 async fn verifiable_create_keypair(
     state: State<HypervisorState>,
     req: Json<CreateKeyPairRequest>,
 ) -> Result<Json<VerifiableCreateKeyPairResponse>, HypervisorError> {
-    let Json(raw_resp) = create_keypair(state, req).await?;
+    let Json(raw_resp) = create_keypair(state.clone(), req).await?;
 
-    let session_pk = const_hex::decode(raw_resp.session_pubkey.as_str()).expect("impossible");
-    let report = generate_raw_report(&[session_pk.as_slice(), raw_resp.session_id.as_bytes()]);
+    let report = ReportData::SessionKey {
+        public_key: crypto::pk_from_hex(&raw_resp.session_pubkey).expect("impossible"),
+        session_id: raw_resp.session_id,
+    };
 
-    let quote = attest::get_quote(report)
-        .context("get create keypair quote")
+    let attestation = utils::attest::attest_response(&state, report)
+        .context("attest create keypair")
         .context(StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let verifiable_resp = VerifiableCreateKeyPairResponse {
         session_pubkey: raw_resp.session_pubkey,
         session_id: raw_resp.session_id,
-        quote: const_hex::encode(quote.to_bytes()),
+        quote: attestation.quote_hex(),
+        signature: attestation.signature_hex(),
     };
 
     Ok(Json(verifiable_resp))
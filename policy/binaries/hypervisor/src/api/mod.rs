@@ -1,6 +1,7 @@
 use axum::Router;
 
 pub mod agent;
+pub mod attestation;
 pub mod encrypt;
 pub mod openai;
 pub mod ping;
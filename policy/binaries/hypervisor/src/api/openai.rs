@@ -1,5 +1,6 @@
 use aes_gcm_siv::aead::Aead;
 use anyhow::{anyhow, Context};
+use attest::report_data::ReportData;
 use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
 use serde::{Deserialize, Serialize};
 use tracing::{info, debug};
@@ -55,19 +56,23 @@ pub struct VerifiableOpenAIQueryResponse {
     pub query_commitment: String,
     /// TEE attestation quote (hex-encoded)
     pub quote: String,
+    /// Set with signed responses, `quote` then attests the response key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 async fn verifiable_query_openai(
     state: State<HypervisorState>,
     req: Json<OpenAIQueryRequest>,
 ) -> Result<Json<VerifiableOpenAIQueryResponse>, HypervisorError> {
-    let Json(resp) = query_openai(state, req).await?;
+    let Json(resp) = query_openai(state.clone(), req).await?;
     
     let commitment: [u8; 32] =
         const_hex::decode_to_array(&resp.query_commitment).expect("impossible");
 
-    let quote = attest::get_quote(utils::attest::generate_raw_report_from_hash(commitment))
-        .context("get openai query quote")
+    let report = ReportData::ExecutionResult { commitment };
+    let attestation = utils::attest::attest_response(&state, report)
+        .context("attest openai query")
         .context(StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let verifiable_resp = VerifiableOpenAIQueryResponse {
@@ -76,7 +81,8 @@ async fn verifiable_query_openai(
         response_nonce: resp.response_nonce,
         model: resp.model,
         query_commitment: resp.query_commitment,
-        quote: const_hex::encode(quote.to_bytes()),
+        quote: attestation.quote_hex(),
+        signature: attestation.signature_hex(),
     };
 
     Ok(Json(verifiable_resp))
@@ -7,6 +7,13 @@ pub struct Config {
     pub executor_path: PathBuf,
     pub app_path: PathBuf,
     pub listening: SocketAddr,
+    /// Cached PCS collateral served at `/attestation/collateral`
+    #[serde(default)]
+    pub collateral_dir: Option<PathBuf>,
+    /// Sign `/verifiable/*` responses with a key quoted once at startup,
+    /// instead of quoting every response
+    #[serde(default)]
+    pub signed_responses: bool,
 }
 
 impl Default for Config {
@@ -15,6 +22,8 @@ impl Default for Config {
             executor_path: "./data/executor".parse().expect("executor path"),
             app_path: "./data/apps".parse().expect("app path"),
             listening: "0.0.0.0:3000".parse().expect("hypervisor listen address"),
+            collateral_dir: None,
+            signed_responses: false,
         }
     }
 }
@@ -1,9 +1,12 @@
+use attest::event_log::EventKind;
 use axum::http::HeaderValue;
 use axum::{http::Method, Router};
 use tower_http::cors::CorsLayer;
 
+use crate::agent::PolicyRegistry;
 use crate::api::{self, RouterRegister};
 use crate::types::{HypervisorState, ServerContext};
+use crate::utils::{self, attest::ResponseKey};
 use crate::Config;
 
 pub struct Server {
@@ -13,7 +16,13 @@ pub struct Server {
 
 impl Server {
     pub fn build(config: Config) -> anyhow::Result<Self> {
-        let state = HypervisorState::new(config);
+        let mut state = HypervisorState::new(config);
+        if state.config.signed_responses {
+            state = state.with_response_key(ResponseKey::generate()?);
+        }
+
+        let policy_set = serde_json::to_vec(PolicyRegistry::default_crypto_policy().policies())?;
+        utils::attest::measure_artifact(EventKind::PolicySet, "default crypto policy", &policy_set);
 
         let ctx = ServerContext {
             state: state.clone(),
@@ -21,6 +30,7 @@ impl Server {
 
         let app = Router::new()
             .register_api(api::ping::api_register)
+            .register_api(api::attestation::api_register)
             .register_api(api::encrypt::api_register)
             .register_api(api::openai::api_register)
             .register_api(api::agent::api_register)
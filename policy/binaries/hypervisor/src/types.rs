@@ -6,12 +6,13 @@ use k256::{
 };
 use uuid::Uuid;
 
-use crate::Config;
+use crate::{utils::attest::ResponseKey, Config};
 
 #[derive(Clone, Default)]
 pub(crate) struct HypervisorState {
     pub config: Config,
     session_key_pairs: SessionKeyPairs,
+    response_key: Option<Arc<ResponseKey>>,
 }
 
 impl HypervisorState {
@@ -22,6 +23,15 @@ impl HypervisorState {
         }
     }
 
+    pub fn with_response_key(mut self, response_key: ResponseKey) -> Self {
+        self.response_key = Some(Arc::new(response_key));
+        self
+    }
+
+    pub fn response_key(&self) -> Option<&ResponseKey> {
+        self.response_key.as_deref()
+    }
+
     #[cfg(test)]
     pub fn set_session_key_pairs(&mut self, session_key_pairs: SessionKeyPairs) {
         self.session_key_pairs = session_key_pairs;
@@ -1,5 +1,339 @@
+//! Legacy `/dev/tdx_guest` interface of the 5.x TDX guest kernels
+//!
+//! A TDREPORT is fetched with `TDX_CMD_GET_REPORT`, wrapped in a QGS get
+//! quote message and handed to the host with `TDX_CMD_GET_QUOTE`, which
+//! blocks until the quoting service has filled the shared buffer.
+
+use std::{fs::File, os::fd::AsRawFd, path::Path};
+
 use crate::{errors::AttestationError, types::RawReport};
 
+const TDX_REPORT_LEN: usize = 1024;
+/// Shared GetQuote buffer, large enough for a quote with its certification
+/// data
+const QUOTE_BUF_LEN: usize = 4 * 4 * 1024;
+
+const IOC_WRITE: u64 = 1;
+const IOC_READ: u64 = 2;
+
+const TDX_CMD_GET_REPORT: u64 = ioc(IOC_READ | IOC_WRITE, 1, size_of::<ReportReq>());
+const TDX_CMD_GET_QUOTE: u64 = ioc(IOC_READ, 2, size_of::<QuoteReq>());
+
+/// GHCI GetQuote buffer header, followed by a 4 byte big endian message
+/// length and the QGS message
+const GET_QUOTE_HEADER_LEN: usize = 24;
+const GET_QUOTE_VERSION: u64 = 1;
+const GET_QUOTE_SUCCESS: u64 = 0;
+const GET_QUOTE_IN_FLIGHT: u64 = 0xffff_ffff_ffff_ffff;
+const GET_QUOTE_ERROR: u64 = 0x8000_0000_0000_0000;
+const GET_QUOTE_SERVICE_UNAVAILABLE: u64 = 0x8000_0000_0000_0001;
+
+const QGS_MSG_HEADER_LEN: usize = 16;
+const QGS_MSG_VERSION_MAJOR: u16 = 1;
+const QGS_MSG_VERSION_MINOR: u16 = 0;
+const QGS_MSG_GET_QUOTE_REQ: u32 = 0;
+const QGS_MSG_GET_QUOTE_RESP: u32 = 1;
+
+#[repr(C)]
+struct ReportReq {
+    subtype: u8,
+    reportdata: u64,
+    rpd_len: u32,
+    tdreport: u64,
+    tdr_len: u32,
+}
+
+#[repr(C)]
+struct QuoteReq {
+    buf: u64,
+    len: u64,
+}
+
+const fn ioc(dir: u64, nr: u64, size: usize) -> u64 {
+    (dir << 30) | ((size as u64) << 16) | ((b'T' as u64) << 8) | nr
+}
+
+/// Commands of the TDX guest device, split out so the buffer handling can
+/// be exercised without a TD
+trait TdxGuest {
+    fn get_report(&self, report_data: &[u8; 64]) -> Result<[u8; TDX_REPORT_LEN], AttestationError>;
+
+    /// Send the GetQuote buffer, the response is written back in place
+    fn get_quote(&self, buf: &mut [u8]) -> Result<(), AttestationError>;
+}
+
+struct Device(File);
+
+impl Device {
+    fn open(path: impl AsRef<Path>) -> Result<Self, AttestationError> {
+        let path = path.as_ref();
+
+        File::options()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map(Device)
+            .map_err(|e| AttestationError::Ioctl(format!("open {} {e}", path.display())))
+    }
+
+    fn ioctl<T>(&self, cmd: u64, arg: &mut T) -> Result<(), AttestationError> {
+        // SAFETY: `arg` is a live `repr(C)` request matching `cmd`, whose
+        // pointers reference buffers of the advertised lengths
+        let ret = unsafe { libc::ioctl(self.0.as_raw_fd(), cmd as _, arg as *mut T) };
+        if ret < 0 {
+            return Err(AttestationError::Ioctl(format!(
+                "cmd {cmd:#x} {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl TdxGuest for Device {
+    fn get_report(&self, report_data: &[u8; 64]) -> Result<[u8; TDX_REPORT_LEN], AttestationError> {
+        let mut tdreport = [0u8; TDX_REPORT_LEN];
+        let mut req = ReportReq {
+            subtype: 0,
+            reportdata: report_data.as_ptr() as u64,
+            rpd_len: report_data.len() as u32,
+            tdreport: tdreport.as_mut_ptr() as u64,
+            tdr_len: TDX_REPORT_LEN as u32,
+        };
+        self.ioctl(TDX_CMD_GET_REPORT, &mut req)?;
+
+        Ok(tdreport)
+    }
+
+    fn get_quote(&self, buf: &mut [u8]) -> Result<(), AttestationError> {
+        let mut req = QuoteReq {
+            buf: buf.as_mut_ptr() as u64,
+            len: buf.len() as u64,
+        };
+
+        self.ioctl(TDX_CMD_GET_QUOTE, &mut req)
+    }
+}
+
 pub fn get_raw_quote(report: RawReport) -> Result<Vec<u8>, AttestationError> {
-    panic!("ioctl is deprecated, please upgrade to new kernel")
+    let device = Device::open(crate::IOCTL_DEVICE_PATH)?;
+
+    get_raw_quote_with(&device, report)
+}
+
+fn get_raw_quote_with(
+    device: &impl TdxGuest,
+    report: RawReport,
+) -> Result<Vec<u8>, AttestationError> {
+    let tdreport = device.get_report(&report.to_bytes())?;
+
+    let mut buf = encode_quote_request(&tdreport);
+    device.get_quote(&mut buf)?;
+
+    decode_quote_response(&buf)
+}
+
+fn encode_quote_request(tdreport: &[u8]) -> Vec<u8> {
+    let msg_len = QGS_MSG_HEADER_LEN + 8 + tdreport.len();
+
+    let mut buf = Vec::with_capacity(QUOTE_BUF_LEN);
+    buf.extend(GET_QUOTE_VERSION.to_le_bytes());
+    buf.extend(0u64.to_le_bytes()); // status
+    buf.extend(((msg_len + 4) as u32).to_le_bytes()); // in_len
+    buf.extend(0u32.to_le_bytes()); // out_len
+
+    buf.extend((msg_len as u32).to_be_bytes());
+    buf.extend(QGS_MSG_VERSION_MAJOR.to_le_bytes());
+    buf.extend(QGS_MSG_VERSION_MINOR.to_le_bytes());
+    buf.extend(QGS_MSG_GET_QUOTE_REQ.to_le_bytes());
+    buf.extend((msg_len as u32).to_le_bytes());
+    buf.extend(0u32.to_le_bytes()); // error_code
+    buf.extend((tdreport.len() as u32).to_le_bytes());
+    buf.extend(0u32.to_le_bytes()); // id_list_size
+    buf.extend(tdreport);
+
+    buf.resize(QUOTE_BUF_LEN, 0);
+    buf
+}
+
+fn decode_quote_response(buf: &[u8]) -> Result<Vec<u8>, AttestationError> {
+    let err = AttestationError::Ioctl;
+    let u32_at = |off: usize| u32::from_le_bytes(buf[off..off + 4].try_into().expect("4 bytes"));
+
+    if buf.len() < GET_QUOTE_HEADER_LEN + 4 {
+        return Err(err(format!("quote buffer too small {}", buf.len())));
+    }
+
+    let status = u64::from_le_bytes(buf[8..16].try_into().expect("8 bytes"));
+    match status {
+        GET_QUOTE_SUCCESS => (),
+        GET_QUOTE_IN_FLIGHT => return Err(err("quote still in flight".to_string())),
+        GET_QUOTE_SERVICE_UNAVAILABLE => return Err(err("quote service unavailable".to_string())),
+        GET_QUOTE_ERROR => return Err(err("quote generation failed".to_string())),
+        status => return Err(err(format!("unknown quote status {status:#x}"))),
+    }
+
+    let out_len = u32_at(20) as usize;
+    let msg_len = u32::from_be_bytes(buf[24..28].try_into().expect("4 bytes")) as usize;
+    if out_len != msg_len + 4 || GET_QUOTE_HEADER_LEN + out_len > buf.len() {
+        return Err(err(format!("invalid out_len {out_len} msg_len {msg_len}")));
+    }
+
+    let msg = &buf[GET_QUOTE_HEADER_LEN + 4..GET_QUOTE_HEADER_LEN + out_len];
+    if msg.len() < QGS_MSG_HEADER_LEN + 8 {
+        return Err(err(format!("qgs message too small {}", msg.len())));
+    }
+
+    let msg_u32 = |off: usize| u32::from_le_bytes(msg[off..off + 4].try_into().expect("4 bytes"));
+    let (msg_type, size, error_code) = (msg_u32(4), msg_u32(8) as usize, msg_u32(12));
+    if msg_type != QGS_MSG_GET_QUOTE_RESP || size != msg.len() {
+        return Err(err(format!(
+            "unexpected qgs message type {msg_type} size {size}"
+        )));
+    }
+    if error_code != 0 {
+        return Err(err(format!("qgs error {error_code:#x}")));
+    }
+
+    let id_len = msg_u32(16) as usize;
+    let quote_len = msg_u32(20) as usize;
+    let quote_start = QGS_MSG_HEADER_LEN + 8 + id_len;
+    if quote_len == 0 || quote_start + quote_len > msg.len() {
+        return Err(err(format!("invalid quote size {quote_len}")));
+    }
+
+    Ok(msg[quote_start..quote_start + quote_len].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    /// Answers like a quoting service returning `quote`, or `status`
+    struct FakeGuest {
+        quote: Vec<u8>,
+        status: u64,
+        report_data: RefCell<Option<[u8; 64]>>,
+    }
+
+    impl FakeGuest {
+        fn new(quote: &[u8]) -> Self {
+            FakeGuest {
+                quote: quote.to_vec(),
+                status: GET_QUOTE_SUCCESS,
+                report_data: RefCell::new(None),
+            }
+        }
+    }
+
+    impl TdxGuest for FakeGuest {
+        fn get_report(
+            &self,
+            report_data: &[u8; 64],
+        ) -> Result<[u8; TDX_REPORT_LEN], AttestationError> {
+            *self.report_data.borrow_mut() = Some(*report_data);
+
+            let mut tdreport = [0u8; TDX_REPORT_LEN];
+            tdreport[128..192].copy_from_slice(report_data);
+            Ok(tdreport)
+        }
+
+        fn get_quote(&self, buf: &mut [u8]) -> Result<(), AttestationError> {
+            assert_eq!(buf.len(), QUOTE_BUF_LEN);
+            assert_eq!(&buf[0..8], &GET_QUOTE_VERSION.to_le_bytes());
+
+            let in_len = u32::from_le_bytes(buf[16..20].try_into().unwrap()) as usize;
+            let msg_len = u32::from_be_bytes(buf[24..28].try_into().unwrap()) as usize;
+            assert_eq!(in_len, msg_len + 4);
+
+            let msg = &buf[28..28 + msg_len];
+            assert_eq!(&msg[4..8], &QGS_MSG_GET_QUOTE_REQ.to_le_bytes());
+            assert_eq!(&msg[16..20], &(TDX_REPORT_LEN as u32).to_le_bytes());
+            let report_data = self.report_data.borrow().unwrap();
+            assert_eq!(&msg[24 + 128..24 + 192], &report_data);
+
+            let id = [0xaa; 16];
+            let resp_len = QGS_MSG_HEADER_LEN + 8 + id.len() + self.quote.len();
+            let mut resp = Vec::new();
+            resp.extend(QGS_MSG_VERSION_MAJOR.to_le_bytes());
+            resp.extend(QGS_MSG_VERSION_MINOR.to_le_bytes());
+            resp.extend(QGS_MSG_GET_QUOTE_RESP.to_le_bytes());
+            resp.extend((resp_len as u32).to_le_bytes());
+            resp.extend(0u32.to_le_bytes());
+            resp.extend((id.len() as u32).to_le_bytes());
+            resp.extend((self.quote.len() as u32).to_le_bytes());
+            resp.extend(id);
+            resp.extend(&self.quote);
+
+            buf[8..16].copy_from_slice(&self.status.to_le_bytes());
+            buf[20..24].copy_from_slice(&((resp_len + 4) as u32).to_le_bytes());
+            buf[24..28].copy_from_slice(&(resp_len as u32).to_be_bytes());
+            buf[28..28 + resp_len].copy_from_slice(&resp);
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ioctl_numbers() {
+        assert_eq!(size_of::<ReportReq>(), 40);
+        assert_eq!(TDX_CMD_GET_REPORT, 0xc028_5401);
+        assert_eq!(TDX_CMD_GET_QUOTE, 0x8010_5402);
+    }
+
+    #[test]
+    fn test_get_quote_buffers() {
+        let quote = vec![7u8; 4800];
+        let guest = FakeGuest::new(&quote);
+
+        let raw = get_raw_quote_with(&guest, RawReport::new([3u8; 64])).unwrap();
+        assert_eq!(raw, quote);
+        assert_eq!(*guest.report_data.borrow(), Some([3u8; 64]));
+    }
+
+    #[test]
+    fn test_get_quote_errors() {
+        let mut guest = FakeGuest::new(&[7u8; 64]);
+        guest.status = GET_QUOTE_SERVICE_UNAVAILABLE;
+        let err = get_raw_quote_with(&guest, RawReport::new([0u8; 64])).unwrap_err();
+        assert!(err.to_string().contains("service unavailable"));
+
+        let guest = FakeGuest::new(&[7u8; 64]);
+        guest.report_data.replace(Some([0u8; 64]));
+        let mut buf = encode_quote_request(&[0u8; TDX_REPORT_LEN]);
+        guest.get_quote(&mut buf).unwrap();
+        assert_eq!(decode_quote_response(&buf).unwrap(), [7u8; 64]);
+
+        let mut truncated = buf.clone();
+        truncated[28 + 20..28 + 24].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(decode_quote_response(&truncated).is_err());
+
+        let mut bad_len = buf.clone();
+        bad_len[20..24].copy_from_slice(&0u32.to_le_bytes());
+        assert!(decode_quote_response(&bad_len).is_err());
+
+        let mut qgs_error = buf.clone();
+        qgs_error[28 + 12] = 1;
+        assert!(decode_quote_response(&qgs_error).is_err());
+
+        assert!(decode_quote_response(&buf[..16]).is_err());
+    }
+
+    #[test]
+    fn test_fake_device_file() {
+        let path = std::env::temp_dir().join(format!("attest-tdx-guest-{}", std::process::id()));
+        std::fs::write(&path, []).unwrap();
+
+        // Regular files reject the ioctl with ENOTTY, which has to surface
+        // as an error instead of a crash
+        let device = Device::open(&path).unwrap();
+        let err = get_raw_quote_with(&device, RawReport::new([0u8; 64])).unwrap_err();
+        assert!(matches!(err, AttestationError::Ioctl(_)));
+
+        std::fs::remove_file(path).unwrap();
+        assert!(Device::open("/nonexistent/tdx_guest").is_err());
+    }
 }
@@ -0,0 +1,132 @@
+use std::sync::{Mutex, OnceLock};
+
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use sha2::{Digest, Sha256, Sha384};
+
+use crate::{
+    errors::AttestationError,
+    types::RawReport,
+    verify::quote::{
+        qe_report_data, ATTESTATION_KEY_TYPE_ECDSA_P256, CERT_DATA_TYPE_PCK_CHAIN,
+        CERT_DATA_TYPE_QE_REPORT, ENCLAVE_REPORT_LEN, TD10_REPORT_LEN, TEE_TYPE_TDX,
+    },
+};
+
+const INTEL_QE_VENDOR_ID: [u8; 16] = [
+    0x93, 0x9a, 0x72, 0x33, 0xf7, 0x9c, 0x4c, 0xa9, 0x94, 0x0a, 0x0d, 0xb3, 0x95, 0x7f, 0x06, 0x07,
+];
+
+static ATTESTATION_KEY: OnceLock<SigningKey> = OnceLock::new();
+
+/// Software RTMR3, starts zeroed like the hardware register
+static RTMR3: Mutex<[u8; 48]> = Mutex::new([0u8; 48]);
+
+fn attestation_key() -> &'static SigningKey {
+    ATTESTATION_KEY.get_or_init(|| SigningKey::random(&mut rand::rngs::OsRng))
+}
+
+/// Public key (`x || y`) signing every mock quote of this process
+pub fn attestation_pubkey() -> [u8; 64] {
+    let point = attestation_key().verifying_key().to_encoded_point(false);
+
+    point.as_bytes()[1..]
+        .try_into()
+        .expect("uncompressed p256 point")
+}
+
+/// Sealing key shared by every mock guest, stable across restarts like a
+/// derived key of an unchanged image
+pub fn sealing_key() -> [u8; 32] {
+    Sha256::digest(b"attest mock sealing key").into()
+}
+
+pub fn extend_rtmr3(digest: &[u8; 48]) {
+    let mut rtmr = RTMR3.lock().unwrap_or_else(|e| e.into_inner());
+
+    *rtmr = Sha384::new_with_prefix(*rtmr)
+        .chain_update(digest)
+        .finalize()
+        .into();
+}
+
+/// Build a V4 TDX quote carrying `report`, signed by a process local key
+///
+/// All measurements but the software RTMR3 are zero and there is no PCK
+/// certificate chain, the QE report is signed by the attestation key itself.
+pub fn get_raw_quote(report: RawReport) -> Result<Vec<u8>, AttestationError> {
+    let key = attestation_key();
+    let pubkey = attestation_pubkey();
+
+    let mut quote = Vec::new();
+    quote.extend(4u16.to_le_bytes());
+    quote.extend(ATTESTATION_KEY_TYPE_ECDSA_P256.to_le_bytes());
+    quote.extend(TEE_TYPE_TDX.to_le_bytes());
+    quote.extend([0u8; 4]);
+    quote.extend(INTEL_QE_VENDOR_ID);
+    quote.extend([0u8; 20]);
+
+    let mut body = [0u8; TD10_REPORT_LEN];
+    body[472..520].copy_from_slice(&*RTMR3.lock().unwrap_or_else(|e| e.into_inner()));
+    body[520..].copy_from_slice(&report.to_bytes());
+    quote.extend(body);
+
+    let quote_signature: Signature = key.sign(&quote);
+
+    let qe_auth_data: Vec<u8> = (0..32).collect();
+    let mut qe_report = [0u8; ENCLAVE_REPORT_LEN];
+    qe_report[320..].copy_from_slice(&qe_report_data(&pubkey, &qe_auth_data));
+    let qe_report_signature: Signature = key.sign(&qe_report);
+
+    let mut qe_cert_data = Vec::new();
+    qe_cert_data.extend(qe_report);
+    qe_cert_data.extend(qe_report_signature.to_bytes());
+    qe_cert_data.extend((qe_auth_data.len() as u16).to_le_bytes());
+    qe_cert_data.extend(&qe_auth_data);
+    qe_cert_data.extend(CERT_DATA_TYPE_PCK_CHAIN.to_le_bytes());
+    qe_cert_data.extend(0u32.to_le_bytes());
+
+    let mut signature_data = Vec::new();
+    signature_data.extend(quote_signature.to_bytes());
+    signature_data.extend(pubkey);
+    signature_data.extend(CERT_DATA_TYPE_QE_REPORT.to_le_bytes());
+    signature_data.extend((qe_cert_data.len() as u32).to_le_bytes());
+    signature_data.extend(qe_cert_data);
+
+    quote.extend((signature_data.len() as u32).to_le_bytes());
+    quote.extend(signature_data);
+
+    Ok(quote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        types::Quote,
+        verify::{MockVerifier, QuoteVerifier},
+    };
+
+    #[test]
+    fn test_mock_quote_verify() {
+        let report_data = [7u8; 64];
+        let raw = get_raw_quote(RawReport::new(report_data)).unwrap();
+
+        let quote = Quote::from_bytes(&raw).unwrap();
+        assert_eq!(quote.report_data(), report_data);
+
+        let verdict = { MockVerifier::new().with_attestation_key(attestation_pubkey()) }
+            .verify(&quote)
+            .unwrap();
+        assert!(verdict.is_up_to_date());
+
+        let mut tampered = raw.clone();
+        tampered[48 + 520] ^= 1;
+        let tampered = Quote::from_bytes(&tampered).unwrap();
+        assert!(MockVerifier::new().verify(&tampered).is_err());
+
+        let other_key = [1u8; 64];
+        assert!({ MockVerifier::new().with_attestation_key(other_key) }
+            .verify(&quote)
+            .is_err());
+    }
+}
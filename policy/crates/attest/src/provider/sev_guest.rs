@@ -0,0 +1,121 @@
+//! `/dev/sev-guest` derived keys, see MSG_KEY_REQ in the SEV-SNP firmware
+//! ABI specification
+//!
+//! configfs-tsm only exposes reports, the key request still goes through
+//! the `SNP_GET_DERIVED_KEY` ioctl.
+
+use std::{fs::File, os::fd::AsRawFd};
+
+use crate::errors::AttestationError;
+
+const IOC_WRITE: u64 = 1;
+const IOC_READ: u64 = 2;
+
+const SNP_GET_DERIVED_KEY: u64 = ioc(IOC_READ | IOC_WRITE, 1, size_of::<GuestRequest>());
+
+const MSG_VERSION: u8 = 1;
+/// Derive from the chip unique VCEK
+const ROOT_KEY_VCEK: u32 = 0;
+const FIELD_GUEST_POLICY: u64 = 1 << 0;
+const FIELD_MEASUREMENT: u64 = 1 << 3;
+
+#[repr(C)]
+struct GuestRequest {
+    msg_version: u8,
+    req_data: u64,
+    resp_data: u64,
+    exitinfo2: u64,
+}
+
+#[repr(C)]
+struct DerivedKeyReq {
+    root_key_select: u32,
+    rsvd: u32,
+    guest_field_select: u64,
+    vmpl: u32,
+    guest_svn: u32,
+    tcb_version: u64,
+}
+
+const fn ioc(dir: u64, nr: u64, size: usize) -> u64 {
+    (dir << 30) | ((size as u64) << 16) | ((b'S' as u64) << 8) | nr
+}
+
+/// Key bound to this chip, the launch measurement and the guest policy
+pub fn get_derived_key() -> Result<[u8; 32], AttestationError> {
+    let device = File::options()
+        .read(true)
+        .write(true)
+        .open(crate::SEV_GUEST_DEVICE_PATH)
+        .map_err(|e| AttestationError::Ioctl(format!("open sev-guest {e}")))?;
+
+    let mut key_req = DerivedKeyReq {
+        root_key_select: ROOT_KEY_VCEK,
+        rsvd: 0,
+        guest_field_select: FIELD_GUEST_POLICY | FIELD_MEASUREMENT,
+        vmpl: 0,
+        guest_svn: 0,
+        tcb_version: 0,
+    };
+    let mut resp = [0u8; 64];
+    let mut req = GuestRequest {
+        msg_version: MSG_VERSION,
+        req_data: &mut key_req as *mut DerivedKeyReq as u64,
+        resp_data: resp.as_mut_ptr() as u64,
+        exitinfo2: 0,
+    };
+
+    // SAFETY: `req` points at a live key request and a 64 byte response,
+    // the sizes `SNP_GET_DERIVED_KEY` expects
+    let ret = unsafe {
+        libc::ioctl(
+            device.as_raw_fd(),
+            SNP_GET_DERIVED_KEY as _,
+            &mut req as *mut GuestRequest,
+        )
+    };
+    if ret < 0 {
+        return Err(AttestationError::Ioctl(format!(
+            "derived key {} exitinfo2 {:#x}",
+            std::io::Error::last_os_error(),
+            req.exitinfo2
+        )));
+    }
+
+    decode_derived_key(&resp)
+}
+
+/// MSG_KEY_RSP, a status followed by the key at offset 0x20
+fn decode_derived_key(resp: &[u8; 64]) -> Result<[u8; 32], AttestationError> {
+    let status = u32::from_le_bytes(resp[..4].try_into().expect("4 bytes"));
+    if status != 0 {
+        return Err(AttestationError::Ioctl(format!(
+            "derived key status {status:#x}"
+        )));
+    }
+
+    Ok(resp[0x20..].try_into().expect("32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ioctl_numbers() {
+        assert_eq!(size_of::<GuestRequest>(), 32);
+        assert_eq!(size_of::<DerivedKeyReq>(), 32);
+        assert_eq!(SNP_GET_DERIVED_KEY, 0xc020_5301);
+    }
+
+    #[test]
+    fn test_decode_derived_key() {
+        let mut resp = [0u8; 64];
+        resp[0x20..].copy_from_slice(&[7u8; 32]);
+        assert_eq!(decode_derived_key(&resp).unwrap(), [7u8; 32]);
+
+        // Invalid parameters
+        resp[0] = 0x16;
+        assert!(decode_derived_key(&resp).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{errors::AttestationError, types::RawReport};
+
+/// configfs-tsm report interface, kernel 6.7 and later
+pub const TSM_REPORT_DIR: &str = "/sys/kernel/config/tsm/report";
+
+/// `provider` attribute of reports produced by the SEV guest driver
+pub const PROVIDER_SEV_GUEST: &str = "sev_guest";
+
+static ENTRY_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Report entry, removed from configfs on drop
+struct Entry(PathBuf);
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir(&self.0) {
+            tracing::warn!(%err, "remove tsm report entry {}", self.0.display());
+        }
+    }
+}
+
+pub fn get_raw_report(report: RawReport, provider: &str) -> Result<Vec<u8>, AttestationError> {
+    get_raw_report_in(Path::new(TSM_REPORT_DIR), report, provider)
+}
+
+fn get_raw_report_in(
+    root: &Path,
+    report: RawReport,
+    provider: &str,
+) -> Result<Vec<u8>, AttestationError> {
+    let tsm_err = |what: &str, e: std::io::Error| AttestationError::Tsm(format!("{what} {e}"));
+
+    let name = format!(
+        "attest-{}-{}",
+        std::process::id(),
+        ENTRY_SEQ.fetch_add(1, Ordering::Relaxed)
+    );
+    let entry = root.join(name);
+    fs::create_dir(&entry).map_err(|e| tsm_err("create report entry", e))?;
+    let entry = Entry(entry);
+
+    fs::write(entry.0.join("inblob"), report.to_bytes()).map_err(|e| tsm_err("write inblob", e))?;
+    let generation = read_attr(&entry.0, "generation")?;
+
+    let outblob = fs::read(entry.0.join("outblob")).map_err(|e| tsm_err("read outblob", e))?;
+
+    // Entries are shared configfs objects, another writer in between means
+    // the outblob may not belong to our inblob
+    if read_attr(&entry.0, "generation")? != generation {
+        return Err(AttestationError::Tsm(
+            "report entry written concurrently".to_string(),
+        ));
+    }
+
+    let actual = read_attr(&entry.0, "provider")?;
+    if actual != provider {
+        return Err(AttestationError::Tsm(format!(
+            "unexpected provider {actual}, want {provider}"
+        )));
+    }
+
+    Ok(outblob)
+}
+
+fn read_attr(entry: &Path, attr: &str) -> Result<String, AttestationError> {
+    let value = fs::read_to_string(entry.join(attr))
+        .map_err(|e| AttestationError::Tsm(format!("read {attr} {e}")))?;
+
+    Ok(value.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_tsm_dir() {
+        let root = std::env::temp_dir().join("attest-tsm-missing");
+
+        let err = get_raw_report_in(&root, RawReport::new([0u8; 64]), PROVIDER_SEV_GUEST);
+        assert!(matches!(err, Err(AttestationError::Tsm(_))));
+    }
+}
@@ -0,0 +1,237 @@
+//! Typed report data, telling a verifier what a quote attests to
+//!
+//! ```text
+//! [0]      version, currently 1
+//! [1]      kind
+//! [2..64]  payload, zero padded
+//! ```
+//!
+//! Session keys carry the compressed session public key followed by the
+//! session id, response keys only the compressed public key, every other
+//! kind a 32 byte hash computed by the caller.
+//!
+//! A quoted response key lets the hypervisor attest later responses with an
+//! ECDSA signature over their encoded report data, see
+//! [`ReportData::verify_signature`].
+
+use k256::ecdsa::{signature::Verifier as _, Signature, VerifyingKey};
+use uuid::Uuid;
+
+use crate::{
+    errors::{QuoteError, VerifyError},
+    types::RawReport,
+};
+
+pub const REPORT_DATA_VERSION: u8 = 1;
+
+const KIND_SESSION_KEY: u8 = 1;
+const KIND_EXECUTION_RESULT: u8 = 2;
+const KIND_COMPLIANCE: u8 = 3;
+const KIND_AGENT_EXECUTION: u8 = 4;
+const KIND_RESPONSE_KEY: u8 = 5;
+
+const PAYLOAD_OFFSET: usize = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReportData {
+    /// Session key pair created for a client
+    SessionKey {
+        public_key: VerifyingKey,
+        session_id: Uuid,
+    },
+    /// Result commitment of an executed workload
+    ExecutionResult { commitment: [u8; 32] },
+    /// Hash of a policy compliance decision
+    Compliance { hash: [u8; 32] },
+    /// Hash of an agent execution trace
+    AgentExecution { hash: [u8; 32] },
+    /// Long lived key signing responses in place of per response quotes
+    ResponseKey { public_key: VerifyingKey },
+}
+
+impl ReportData {
+    pub fn encode(&self) -> [u8; 64] {
+        let mut buf = [0u8; 64];
+        buf[0] = REPORT_DATA_VERSION;
+        buf[1] = self.kind();
+
+        let payload = &mut buf[PAYLOAD_OFFSET..];
+        match self {
+            ReportData::SessionKey {
+                public_key,
+                session_id,
+            } => {
+                payload[..33].copy_from_slice(&public_key.to_encoded_point(true).to_bytes());
+                payload[33..49].copy_from_slice(session_id.as_bytes());
+            }
+            ReportData::ResponseKey { public_key } => {
+                payload[..33].copy_from_slice(&public_key.to_encoded_point(true).to_bytes());
+            }
+            ReportData::ExecutionResult { commitment: hash }
+            | ReportData::Compliance { hash }
+            | ReportData::AgentExecution { hash } => payload[..32].copy_from_slice(hash),
+        }
+
+        buf
+    }
+
+    pub fn decode(raw: &[u8; 64]) -> Result<Self, QuoteError> {
+        if raw[0] != REPORT_DATA_VERSION {
+            return Err(QuoteError::ReportData(format!(
+                "unknown version {}",
+                raw[0]
+            )));
+        }
+
+        let payload = &raw[PAYLOAD_OFFSET..];
+        let report_data = match raw[1] {
+            KIND_SESSION_KEY => ReportData::SessionKey {
+                public_key: public_key(payload)?,
+                session_id: Uuid::from_slice(&payload[33..49]).expect("16 bytes"),
+            },
+            KIND_EXECUTION_RESULT => ReportData::ExecutionResult {
+                commitment: hash(payload),
+            },
+            KIND_COMPLIANCE => ReportData::Compliance {
+                hash: hash(payload),
+            },
+            KIND_AGENT_EXECUTION => ReportData::AgentExecution {
+                hash: hash(payload),
+            },
+            KIND_RESPONSE_KEY => ReportData::ResponseKey {
+                public_key: public_key(payload)?,
+            },
+            kind => return Err(QuoteError::ReportData(format!("unknown kind {kind}"))),
+        };
+
+        if payload[report_data.payload_len()..].iter().any(|b| *b != 0) {
+            return Err(QuoteError::ReportData("non zero padding".to_string()));
+        }
+
+        Ok(report_data)
+    }
+
+    pub fn to_raw(&self) -> RawReport {
+        RawReport::new(self.encode())
+    }
+
+    /// Check `signature` over the encoded report data, made by a response
+    /// key whose quote was verified beforehand
+    pub fn verify_signature(
+        &self,
+        key: &VerifyingKey,
+        signature: &[u8],
+    ) -> Result<(), VerifyError> {
+        let signature =
+            Signature::from_slice(signature).map_err(|e| VerifyError::Signature(e.to_string()))?;
+
+        key.verify(&self.encode(), &signature)
+            .map_err(|e| VerifyError::Signature(e.to_string()))
+    }
+
+    fn payload_len(&self) -> usize {
+        match self {
+            ReportData::SessionKey { .. } => 33 + 16,
+            ReportData::ResponseKey { .. } => 33,
+            _ => 32,
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            ReportData::SessionKey { .. } => KIND_SESSION_KEY,
+            ReportData::ExecutionResult { .. } => KIND_EXECUTION_RESULT,
+            ReportData::Compliance { .. } => KIND_COMPLIANCE,
+            ReportData::AgentExecution { .. } => KIND_AGENT_EXECUTION,
+            ReportData::ResponseKey { .. } => KIND_RESPONSE_KEY,
+        }
+    }
+}
+
+fn public_key(payload: &[u8]) -> Result<VerifyingKey, QuoteError> {
+    VerifyingKey::from_sec1_bytes(&payload[..33])
+        .map_err(|e| QuoteError::ReportData(format!("invalid public key {e}")))
+}
+
+fn hash(payload: &[u8]) -> [u8; 32] {
+    payload[..32].try_into().expect("32 bytes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_data_roundtrip() {
+        let sk = k256::ecdsa::SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let session_key = ReportData::SessionKey {
+            public_key: *sk.verifying_key(),
+            session_id: Uuid::from_bytes([9u8; 16]),
+        };
+        let encoded = session_key.encode();
+        assert_eq!(&encoded[..2], &[REPORT_DATA_VERSION, KIND_SESSION_KEY]);
+        assert_eq!(ReportData::decode(&encoded).unwrap(), session_key);
+
+        let response_key = ReportData::ResponseKey {
+            public_key: *sk.verifying_key(),
+        };
+        assert_eq!(
+            ReportData::decode(&response_key.encode()).unwrap(),
+            response_key
+        );
+
+        for report_data in [
+            ReportData::ExecutionResult {
+                commitment: [1u8; 32],
+            },
+            ReportData::Compliance { hash: [2u8; 32] },
+            ReportData::AgentExecution { hash: [3u8; 32] },
+        ] {
+            assert_eq!(
+                ReportData::decode(&report_data.encode()).unwrap(),
+                report_data
+            );
+        }
+
+        // Same hash under different kinds must not be interchangeable
+        assert_ne!(
+            ReportData::Compliance { hash: [2u8; 32] }.encode(),
+            ReportData::AgentExecution { hash: [2u8; 32] }.encode()
+        );
+    }
+
+    #[test]
+    fn test_report_data_signature() {
+        use k256::ecdsa::signature::Signer;
+
+        let sk = k256::ecdsa::SigningKey::from_slice(&[1u8; 32]).unwrap();
+        let report_data = ReportData::ExecutionResult {
+            commitment: [1u8; 32],
+        };
+        let signature: Signature = sk.sign(&report_data.encode());
+
+        report_data
+            .verify_signature(sk.verifying_key(), &signature.to_bytes())
+            .unwrap();
+
+        let other = ReportData::Compliance { hash: [1u8; 32] };
+        assert!(other
+            .verify_signature(sk.verifying_key(), &signature.to_bytes())
+            .is_err());
+    }
+
+    #[test]
+    fn test_report_data_decode_errors() {
+        let mut encoded = ReportData::Compliance { hash: [2u8; 32] }.encode();
+
+        // Untyped blake3 report data from older hypervisors
+        assert!(ReportData::decode(&[7u8; 64]).is_err());
+
+        encoded[63] = 1;
+        assert!(ReportData::decode(&encoded).is_err());
+
+        encoded[63] = 0;
+        encoded[1] = 0xff;
+        assert!(ReportData::decode(&encoded).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! AMD SEV-SNP attestation report, as returned by the `sev_guest` provider
+//!
+//! Offsets follow the ATTESTATION_REPORT structure of the SEV-SNP firmware
+//! ABI specification. Only the report is parsed here, checking its
+//! signature needs the VCEK certificate chain from AMD KDS.
+
+use crate::errors::QuoteError;
+
+pub const SNP_REPORT_LEN: usize = 0x4a0;
+
+const SIGNED_LEN: usize = 0x2a0;
+const SIGNATURE_ALGO_ECDSA_P384_SHA384: u32 = 1;
+const POLICY_DEBUG_BIT: u64 = 1 << 19;
+
+#[derive(Clone, Debug)]
+pub struct SnpReport {
+    raw: Vec<u8>,
+}
+
+/// SEV-SNP TCB version, in the Milan and Genoa layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TcbVersion {
+    pub bootloader: u8,
+    pub tee: u8,
+    pub snp: u8,
+    pub microcode: u8,
+}
+
+impl From<u64> for TcbVersion {
+    fn from(raw: u64) -> Self {
+        let b = raw.to_le_bytes();
+
+        TcbVersion {
+            bootloader: b[0],
+            tee: b[1],
+            snp: b[6],
+            microcode: b[7],
+        }
+    }
+}
+
+impl SnpReport {
+    pub fn from_bytes(bytes: &[u8]) -> Result<SnpReport, QuoteError> {
+        if bytes.len() != SNP_REPORT_LEN {
+            return Err(QuoteError::SnpReport(format!(
+                "invalid size {}",
+                bytes.len()
+            )));
+        }
+
+        let report = SnpReport {
+            raw: bytes.to_vec(),
+        };
+
+        if !(2..=5).contains(&report.version()) {
+            return Err(QuoteError::SnpReport(format!(
+                "unknown version {}",
+                report.version()
+            )));
+        }
+
+        if report.signature_algo() != SIGNATURE_ALGO_ECDSA_P384_SHA384 {
+            return Err(QuoteError::SnpReport(format!(
+                "unknown signature algo {}",
+                report.signature_algo()
+            )));
+        }
+
+        Ok(report)
+    }
+
+    /// SNP reports start with a 32 bit version, DCAP quotes with a 16 bit
+    /// version followed by a non zero attestation key type
+    pub fn is_snp_report(bytes: &[u8]) -> bool {
+        bytes.len() == SNP_REPORT_LEN && bytes[2..4] == [0, 0]
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.raw.clone()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.u32_at(0x00)
+    }
+
+    pub fn guest_svn(&self) -> u32 {
+        self.u32_at(0x04)
+    }
+
+    pub fn policy(&self) -> u64 {
+        self.u64_at(0x08)
+    }
+
+    /// Guest policy allows the hypervisor to debug the VM
+    pub fn is_debug(&self) -> bool {
+        self.policy() & POLICY_DEBUG_BIT != 0
+    }
+
+    pub fn family_id(&self) -> [u8; 16] {
+        self.array(0x10)
+    }
+
+    pub fn image_id(&self) -> [u8; 16] {
+        self.array(0x20)
+    }
+
+    pub fn vmpl(&self) -> u32 {
+        self.u32_at(0x30)
+    }
+
+    pub fn signature_algo(&self) -> u32 {
+        self.u32_at(0x34)
+    }
+
+    pub fn current_tcb(&self) -> TcbVersion {
+        self.u64_at(0x38).into()
+    }
+
+    pub fn platform_info(&self) -> u64 {
+        self.u64_at(0x40)
+    }
+
+    pub fn report_data(&self) -> [u8; 64] {
+        self.array(0x50)
+    }
+
+    /// Launch measurement of the guest
+    pub fn measurement(&self) -> [u8; 48] {
+        self.array(0x90)
+    }
+
+    pub fn host_data(&self) -> [u8; 32] {
+        self.array(0xc0)
+    }
+
+    pub fn id_key_digest(&self) -> [u8; 48] {
+        self.array(0xe0)
+    }
+
+    pub fn author_key_digest(&self) -> [u8; 48] {
+        self.array(0x110)
+    }
+
+    pub fn report_id(&self) -> [u8; 32] {
+        self.array(0x140)
+    }
+
+    pub fn reported_tcb(&self) -> TcbVersion {
+        self.u64_at(0x180).into()
+    }
+
+    pub fn chip_id(&self) -> [u8; 64] {
+        self.array(0x1a0)
+    }
+
+    pub fn committed_tcb(&self) -> TcbVersion {
+        self.u64_at(0x1e0).into()
+    }
+
+    pub fn launch_tcb(&self) -> TcbVersion {
+        self.u64_at(0x1f0).into()
+    }
+
+    /// Bytes covered by the report signature
+    pub fn signed_bytes(&self) -> &[u8] {
+        &self.raw[..SIGNED_LEN]
+    }
+
+    /// ECDSA P-384 signature as big endian `r || s`
+    ///
+    /// The report stores both scalars little endian, zero padded to 72
+    /// bytes.
+    pub fn signature(&self) -> [u8; 96] {
+        let mut sig = [0u8; 96];
+        sig[..48].copy_from_slice(&self.raw[SIGNED_LEN..SIGNED_LEN + 48]);
+        sig[48..].copy_from_slice(&self.raw[SIGNED_LEN + 72..SIGNED_LEN + 120]);
+        sig[..48].reverse();
+        sig[48..].reverse();
+
+        sig
+    }
+
+    fn array<const N: usize>(&self, offset: usize) -> [u8; N] {
+        self.raw[offset..offset + N]
+            .try_into()
+            .expect("fixed size slice")
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.array(offset))
+    }
+
+    fn u64_at(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.array(offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Quote, TeeType};
+
+    // Built field by field following the ABI layout, the signature doesn't
+    // verify
+    const REPORT_V3: &[u8] = include_bytes!("../fixtures/snp_report_v3.bin");
+
+    #[test]
+    fn test_parse_snp_report() {
+        let report = SnpReport::from_bytes(REPORT_V3).unwrap();
+
+        assert_eq!(report.version(), 3);
+        assert_eq!(report.guest_svn(), 1);
+        assert_eq!(report.policy(), 0x30000);
+        assert!(!report.is_debug());
+        assert_eq!(report.vmpl(), 0);
+        assert_eq!(
+            report.reported_tcb(),
+            TcbVersion {
+                bootloader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115,
+            }
+        );
+        assert_eq!(report.measurement(), [0xa5; 48]);
+        assert_eq!(report.chip_id(), [0xc1; 64]);
+        assert_eq!(&report.signature()[..2], &[0x11, 0x12]);
+        assert_eq!(&report.signature()[48..50], &[0x21, 0x22]);
+
+        let mut report_data = [0u8; 64];
+        report_data[..32].copy_from_slice(&[0x42; 32]);
+        assert_eq!(report.report_data(), report_data);
+
+        let quote = Quote::from_bytes(REPORT_V3).unwrap();
+        assert_eq!(quote.tee_type(), TeeType::SevSnp);
+        assert_eq!(quote.report_data(), report_data);
+        assert!(quote.quote_report().is_none());
+        assert_eq!(quote.to_bytes(), REPORT_V3);
+    }
+
+    #[test]
+    fn test_invalid_snp_report() {
+        assert!(SnpReport::from_bytes(&REPORT_V3[..SIGNED_LEN]).is_err());
+
+        let mut unknown_version = REPORT_V3.to_vec();
+        unknown_version[0] = 9;
+        assert!(SnpReport::from_bytes(&unknown_version).is_err());
+
+        let mut unknown_algo = REPORT_V3.to_vec();
+        unknown_algo[0x34] = 2;
+        assert!(SnpReport::from_bytes(&unknown_algo).is_err());
+    }
+}
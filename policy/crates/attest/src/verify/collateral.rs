@@ -0,0 +1,170 @@
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+
+use super::{
+    cert::{verify_raw_signature, CertChain},
+    TcbStatus,
+};
+use crate::errors::VerifyError;
+
+pub const TCB_INFO_FILE: &str = "tcb_info.json";
+pub const QE_IDENTITY_FILE: &str = "qe_identity.json";
+pub const TCB_SIGNING_CHAIN_FILE: &str = "tcb_signing_chain.pem";
+
+/// Verification collateral as served by Intel PCS or a local PCCS
+///
+/// `tcb_info` and `qe_identity` are the raw JSON bodies, signatures included.
+/// `tcb_signing_chain` is the PEM issuer chain returned alongside them.
+#[derive(Clone, Debug)]
+pub struct Collateral {
+    pub tcb_info: String,
+    pub qe_identity: String,
+    pub tcb_signing_chain: Vec<u8>,
+}
+
+impl Collateral {
+    /// Load collateral from `dir`, which must contain `tcb_info.json`,
+    /// `qe_identity.json` and `tcb_signing_chain.pem`
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, VerifyError> {
+        let dir = dir.as_ref();
+
+        Ok(Collateral {
+            tcb_info: fs::read_to_string(dir.join(TCB_INFO_FILE))?,
+            qe_identity: fs::read_to_string(dir.join(QE_IDENTITY_FILE))?,
+            tcb_signing_chain: fs::read(dir.join(TCB_SIGNING_CHAIN_FILE))?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Signed<'a> {
+    #[serde(borrow, alias = "tcbInfo", alias = "enclaveIdentity")]
+    body: &'a RawValue,
+    signature: String,
+}
+
+/// Parse a signed collateral body, checking its signature with the
+/// TCB signing key
+pub(crate) fn parse_signed<T: for<'de> Deserialize<'de>>(
+    json: &str,
+    signing_chain: &CertChain,
+) -> Result<T, VerifyError> {
+    let signed: Signed = serde_json::from_str(json)
+        .map_err(|e| VerifyError::Collateral(format!("invalid json {e}")))?;
+
+    let sig = const_hex::decode(&signed.signature)
+        .map_err(|e| VerifyError::Collateral(format!("invalid signature hex {e}")))?;
+
+    verify_raw_signature(
+        &signing_chain.leaf_public_key()?,
+        signed.body.get().as_bytes(),
+        &sig,
+    )
+    .map_err(|e| VerifyError::Collateral(format!("{e}")))?;
+
+    serde_json::from_str(signed.body.get())
+        .map_err(|e| VerifyError::Collateral(format!("invalid body {e}")))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TcbInfo {
+    pub id: Option<String>,
+    pub next_update: DateTime<Utc>,
+    #[serde(with = "const_hex")]
+    pub fmspc: Vec<u8>,
+    pub tdx_module: Option<TdxModule>,
+    #[serde(default)]
+    pub tdx_module_identities: Vec<TdxModuleIdentity>,
+    pub tcb_levels: Vec<TcbLevel>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TdxModule {
+    #[serde(with = "const_hex")]
+    pub mrsigner: Vec<u8>,
+    #[serde(with = "const_hex")]
+    pub attributes: Vec<u8>,
+    #[serde(with = "const_hex")]
+    pub attributes_mask: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TdxModuleIdentity {
+    pub id: String,
+    #[serde(with = "const_hex")]
+    pub mrsigner: Vec<u8>,
+    #[serde(with = "const_hex")]
+    pub attributes: Vec<u8>,
+    #[serde(with = "const_hex")]
+    pub attributes_mask: Vec<u8>,
+    pub tcb_levels: Vec<QeTcbLevel>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TcbLevel {
+    pub tcb: Tcb,
+    pub tcb_status: TcbStatus,
+    #[serde(default, rename = "advisoryIDs")]
+    pub advisory_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Tcb {
+    pub sgxtcbcomponents: Vec<TcbComponent>,
+    pub pcesvn: u16,
+    #[serde(default)]
+    pub tdxtcbcomponents: Vec<TcbComponent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TcbComponent {
+    pub svn: u8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QeIdentity {
+    pub next_update: DateTime<Utc>,
+    #[serde(with = "const_hex")]
+    pub miscselect: Vec<u8>,
+    #[serde(with = "const_hex")]
+    pub miscselect_mask: Vec<u8>,
+    #[serde(with = "const_hex")]
+    pub attributes: Vec<u8>,
+    #[serde(with = "const_hex")]
+    pub attributes_mask: Vec<u8>,
+    #[serde(with = "const_hex")]
+    pub mrsigner: Vec<u8>,
+    pub isvprodid: u16,
+    pub tcb_levels: Vec<QeTcbLevel>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QeTcbLevel {
+    pub tcb: QeTcb,
+    pub tcb_status: TcbStatus,
+    #[serde(default, rename = "advisoryIDs")]
+    pub advisory_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct QeTcb {
+    pub isvsvn: u16,
+}
+
+/// Compare `value` against `expected` under `mask`, byte by byte
+pub(crate) fn masked_eq(value: &[u8], expected: &[u8], mask: &[u8]) -> bool {
+    if value.len() != expected.len() || value.len() != mask.len() {
+        return false;
+    }
+
+    { value.iter().zip(expected).zip(mask) }.all(|((v, e), m)| v & m == e & m)
+}
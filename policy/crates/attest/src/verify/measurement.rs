@@ -0,0 +1,264 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Deserializer};
+
+use super::collateral::masked_eq;
+use crate::{
+    errors::VerifyError,
+    types::{Quote, QuoteReport},
+};
+
+/// Expected TD measurements, checked after the quote itself is verified
+///
+/// A quote is accepted when it matches at least one entry of `allowed`
+/// and every platform constraint. Fields left out aren't checked, an
+/// empty `allowed` list accepts any build.
+///
+/// ```toml
+/// min_tee_tcb_svn = "03000500000000000000000000000000"
+///
+/// # Reject debug TDs
+/// [td_attributes]
+/// value = "0000000000000000"
+/// mask = "0100000000000000"
+///
+/// [[allowed]]
+/// name = "hypervisor v0.1.0"
+/// mrtd = "a1b2..."
+/// rtmr1 = "c3d4..."
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MeasurementPolicy {
+    #[serde(default)]
+    pub allowed: Vec<ExpectedMeasurement>,
+    pub td_attributes: Option<MaskedValue>,
+    pub xfam: Option<MaskedValue>,
+    /// Component wise lower bound of TEE_TCB_SVN
+    #[serde(default, deserialize_with = "opt_hex")]
+    pub min_tee_tcb_svn: Option<[u8; 16]>,
+}
+
+/// Measurements of one accepted build
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpectedMeasurement {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "opt_hex")]
+    pub mrtd: Option<[u8; 48]>,
+    #[serde(default, deserialize_with = "opt_hex")]
+    pub rtmr0: Option<[u8; 48]>,
+    #[serde(default, deserialize_with = "opt_hex")]
+    pub rtmr1: Option<[u8; 48]>,
+    #[serde(default, deserialize_with = "opt_hex")]
+    pub rtmr2: Option<[u8; 48]>,
+    #[serde(default, deserialize_with = "opt_hex")]
+    pub rtmr3: Option<[u8; 48]>,
+}
+
+/// 8 byte attribute field compared under `mask`, which defaults to all
+/// bits set. Bytes are little endian as they appear in the TD report.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaskedValue {
+    #[serde(with = "const_hex")]
+    pub value: [u8; 8],
+    #[serde(default, deserialize_with = "opt_hex")]
+    pub mask: Option<[u8; 8]>,
+}
+
+impl MeasurementPolicy {
+    pub fn from_toml_str(s: &str) -> Result<Self, VerifyError> {
+        toml::from_str(s).map_err(|e| VerifyError::Policy(format!("invalid toml {e}")))
+    }
+
+    pub fn from_json_str(s: &str) -> Result<Self, VerifyError> {
+        serde_json::from_str(s).map_err(|e| VerifyError::Policy(format!("invalid json {e}")))
+    }
+
+    /// Load a policy file, `.json` files are read as JSON and anything
+    /// else as TOML
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, VerifyError> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json_str(&content),
+            _ => Self::from_toml_str(&content),
+        }
+    }
+
+    /// Check the measurements of `quote`, reporting every violation
+    pub fn check(&self, quote: &Quote) -> Result<(), VerifyError> {
+        let violations = match quote.quote_report() {
+            Some(report) => self.violations(report),
+            None => vec!["not a td quote".to_string()],
+        };
+        if !violations.is_empty() {
+            return Err(VerifyError::Measurement(violations));
+        }
+
+        Ok(())
+    }
+
+    fn violations(&self, report: &QuoteReport) -> Vec<String> {
+        if !report.is_td() {
+            return vec!["not a td quote".to_string()];
+        }
+
+        let mut violations = Vec::new();
+
+        if !self.allowed.is_empty() {
+            let mismatches = { self.allowed.iter().enumerate() }
+                .map(|(idx, expected)| {
+                    let name = { expected.name.clone() }.unwrap_or_else(|| format!("#{idx}"));
+                    (name, expected.mismatches(report))
+                })
+                .collect::<Vec<_>>();
+
+            if mismatches.iter().all(|(_, fields)| !fields.is_empty()) {
+                let detail = { mismatches.into_iter() }
+                    .map(|(name, fields)| format!("{name}: {}", fields.join(", ")))
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                violations.push(format!("no allowed measurement matches ({detail})"));
+            }
+        }
+
+        if let Some(expected) = &self.td_attributes {
+            let actual = report.td_attributes();
+            if !expected.matches(&actual) {
+                violations.push(format!("td attributes {}", const_hex::encode(actual)));
+            }
+        }
+
+        if let Some(expected) = &self.xfam {
+            let actual = report.xfam();
+            if !expected.matches(&actual) {
+                violations.push(format!("xfam {}", const_hex::encode(actual)));
+            }
+        }
+
+        if let Some(min) = &self.min_tee_tcb_svn {
+            let actual = report.tee_tcb_svn();
+            if { actual.iter().zip(min) }.any(|(svn, min)| svn < min) {
+                violations.push(format!(
+                    "tee tcb svn {} below {}",
+                    const_hex::encode(actual),
+                    const_hex::encode(min)
+                ));
+            }
+        }
+
+        violations
+    }
+}
+
+impl ExpectedMeasurement {
+    /// Names of the registers that differ from `report`
+    fn mismatches(&self, report: &QuoteReport) -> Vec<&'static str> {
+        let registers = [
+            ("mrtd", &self.mrtd, report.mrtd()),
+            ("rtmr0", &self.rtmr0, report.rtmr0()),
+            ("rtmr1", &self.rtmr1, report.rtmr1()),
+            ("rtmr2", &self.rtmr2, report.rtmr2()),
+            ("rtmr3", &self.rtmr3, report.rtmr3()),
+        ];
+
+        { registers.into_iter() }
+            .filter(|(_, expected, actual)| expected.is_some_and(|expected| expected != *actual))
+            .map(|(name, _, _)| name)
+            .collect()
+    }
+}
+
+impl MaskedValue {
+    fn matches(&self, actual: &[u8; 8]) -> bool {
+        masked_eq(actual, &self.value, &self.mask.unwrap_or([0xff; 8]))
+    }
+}
+
+fn opt_hex<'de, D, const N: usize>(deserializer: D) -> Result<Option<[u8; N]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| const_hex::decode_to_array(s).map_err(D::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY_TOML: &str = r#"
+        min_tee_tcb_svn = "00000000000000000000000000000000"
+
+        [td_attributes]
+        value = "0000000000000000"
+        mask = "0100000000000000"
+
+        [[allowed]]
+        name = "build-a"
+        mrtd = "0x000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000"
+
+        [[allowed]]
+        rtmr3 = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"
+    "#;
+
+    #[test]
+    fn test_policy_formats() {
+        let toml_policy = MeasurementPolicy::from_toml_str(POLICY_TOML).unwrap();
+        assert_eq!(toml_policy.allowed.len(), 2);
+        assert_eq!(toml_policy.allowed[0].mrtd, Some([0u8; 48]));
+        assert_eq!(toml_policy.allowed[1].rtmr3, Some([0xff; 48]));
+        assert_eq!(
+            toml_policy.td_attributes.unwrap().mask,
+            Some([1, 0, 0, 0, 0, 0, 0, 0])
+        );
+
+        let json_policy = MeasurementPolicy::from_json_str(
+            r#"{ "allowed": [{ "rtmr1": null }], "xfam": { "value": "e702060000000000" } }"#,
+        )
+        .unwrap();
+        assert_eq!(json_policy.allowed[0].rtmr1, None);
+        assert_eq!(
+            json_policy.xfam.unwrap().value,
+            [0xe7, 0x02, 6, 0, 0, 0, 0, 0]
+        );
+
+        assert!(MeasurementPolicy::from_toml_str("mrtd = \"00\"").is_err());
+        assert!(MeasurementPolicy::from_toml_str("[[allowed]]\nmrtd = \"00\"").is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_policy_check() {
+        use crate::{provider::mock::get_raw_quote, types::RawReport};
+
+        // Mock quotes carry zero MRTD, RTMR0 to RTMR2 and TCB SVN
+        let raw = get_raw_quote(RawReport::new([0u8; 64])).unwrap();
+        let quote = Quote::from_bytes(&raw).unwrap();
+
+        let policy = MeasurementPolicy::from_toml_str(POLICY_TOML).unwrap();
+        assert!(policy.check(&quote).is_ok());
+
+        let policy = MeasurementPolicy {
+            allowed: vec![ExpectedMeasurement {
+                name: Some("build-b".to_string()),
+                rtmr2: Some([1u8; 48]),
+                ..Default::default()
+            }],
+            min_tee_tcb_svn: Some([1u8; 16]),
+            ..Default::default()
+        };
+        let Err(VerifyError::Measurement(violations)) = policy.check(&quote) else {
+            panic!("expected measurement violations");
+        };
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].contains("build-b: rtmr2"));
+        assert!(violations[1].starts_with("tee tcb svn"));
+    }
+}
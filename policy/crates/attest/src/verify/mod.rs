@@ -0,0 +1,367 @@
+//! DCAP quote verification against locally provided collateral
+//!
+//! Checks the quote signature with the attestation key, the QE report
+//! signature and binding, the PCK certificate chain up to the pinned Intel
+//! SGX root, and evaluates TCB info and QE identity to a [`Verdict`].
+//! Revocation lists aren't consulted.
+//!
+//! Which TD builds are trusted is a separate decision, expressed as a
+//! [`MeasurementPolicy`] over MRTD, the RTMRs and TD attributes.
+
+mod cert;
+mod collateral;
+mod measurement;
+mod mock;
+pub(crate) mod quote;
+
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub use collateral::{Collateral, QE_IDENTITY_FILE, TCB_INFO_FILE, TCB_SIGNING_CHAIN_FILE};
+pub use measurement::{ExpectedMeasurement, MaskedValue, MeasurementPolicy};
+pub use mock::MockVerifier;
+
+use self::{
+    cert::{raw_public_key, verify_raw_signature, CertChain, PckExtension},
+    collateral::{masked_eq, parse_signed, QeIdentity, QeTcbLevel, TcbInfo},
+    quote::{EnclaveReport, QuoteParts},
+};
+use crate::{errors::VerifyError, types::Quote};
+
+/// Public key (`x || y`) of the Intel SGX Root CA, which anchors both the
+/// PCK and the TCB signing chains
+pub const INTEL_SGX_ROOT_CA_PUBKEY: [u8; 64] = [
+    0x0b, 0xa9, 0xc4, 0xc0, 0xc0, 0xc8, 0x61, 0x93, 0xa3, 0xfe, 0x23, 0xd6, 0xb0, 0x2c, 0xda, 0x10,
+    0xa8, 0xbb, 0xd4, 0xe8, 0x8e, 0x48, 0xb4, 0x45, 0x85, 0x61, 0xa3, 0x6e, 0x70, 0x55, 0x25, 0xf5,
+    0x67, 0x91, 0x8e, 0x2e, 0xdc, 0x88, 0xe4, 0x0d, 0x86, 0x0b, 0xd0, 0xcc, 0x4e, 0xe2, 0x6a, 0xac,
+    0xc9, 0x88, 0xe5, 0x05, 0xa9, 0x53, 0x55, 0x8c, 0x45, 0x3f, 0x6b, 0x09, 0x04, 0xae, 0x73, 0x94,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TcbStatus {
+    UpToDate,
+    #[serde(rename = "SWHardeningNeeded")]
+    SwHardeningNeeded,
+    ConfigurationNeeded,
+    #[serde(rename = "ConfigurationAndSWHardeningNeeded")]
+    ConfigurationAndSwHardeningNeeded,
+    OutOfDate,
+    OutOfDateConfigurationNeeded,
+    Revoked,
+}
+
+impl TcbStatus {
+    /// Fold a component status (QE or TDX module) into the platform status
+    fn converge(self, component: TcbStatus) -> TcbStatus {
+        use TcbStatus::*;
+
+        match (component, self) {
+            (Revoked, _) => Revoked,
+            (OutOfDate, UpToDate | SwHardeningNeeded) => OutOfDate,
+            (OutOfDate, ConfigurationNeeded | ConfigurationAndSwHardeningNeeded) => {
+                OutOfDateConfigurationNeeded
+            }
+            (_, platform) => platform,
+        }
+    }
+}
+
+/// Outcome of a successful cryptographic verification
+///
+/// Signatures and certificate chains are already checked at this point,
+/// whether the TCB level is acceptable is up to the caller.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verdict {
+    /// Platform status converged with the QE and TDX module statuses
+    pub status: TcbStatus,
+    pub platform_status: TcbStatus,
+    pub qe_status: TcbStatus,
+    pub advisory_ids: Vec<String>,
+    pub fmspc: [u8; 6],
+    /// TCB info or QE identity is past its `nextUpdate`
+    pub collateral_expired: bool,
+}
+
+impl Verdict {
+    pub fn is_up_to_date(&self) -> bool {
+        self.status == TcbStatus::UpToDate && !self.collateral_expired
+    }
+}
+
+pub trait QuoteVerifier {
+    fn verify(&self, quote: &Quote) -> Result<Verdict, VerifyError>;
+}
+
+#[derive(Clone, Debug)]
+pub struct Verifier {
+    collateral: Collateral,
+    root_pubkey: [u8; 64],
+}
+
+impl Verifier {
+    pub fn new(collateral: Collateral) -> Self {
+        Verifier {
+            collateral,
+            root_pubkey: INTEL_SGX_ROOT_CA_PUBKEY,
+        }
+    }
+
+    /// Pin a different root key, for non-production PCS deployments
+    pub fn with_root_pubkey(mut self, root_pubkey: [u8; 64]) -> Self {
+        self.root_pubkey = root_pubkey;
+        self
+    }
+
+    pub fn verify_at(&self, quote: &Quote, now: SystemTime) -> Result<Verdict, VerifyError> {
+        let raw = dcap_bytes(quote)?;
+        let parts = QuoteParts::parse(&raw)?;
+        verify_attestation_key(&parts)?;
+
+        // QE report is signed by the PCK, which chains to the Intel root
+        let pck_chain = CertChain::from_pem(parts.pck_chain)?;
+        pck_chain.verify(&self.root_pubkey, now)?;
+        verify_raw_signature(
+            &pck_chain.leaf_public_key()?,
+            parts.qe_report,
+            parts.qe_report_signature,
+        )?;
+
+        // Collateral is signed by the TCB signing key, which chains to the Intel root
+        let signing_chain = CertChain::from_pem(&self.collateral.tcb_signing_chain)?;
+        signing_chain.verify(&self.root_pubkey, now)?;
+        let tcb_info: TcbInfo = parse_signed(&self.collateral.tcb_info, &signing_chain)?;
+        let qe_identity: QeIdentity = parse_signed(&self.collateral.qe_identity, &signing_chain)?;
+
+        let pck = pck_chain.pck_extension()?;
+        if tcb_info.fmspc != pck.fmspc {
+            return Err(VerifyError::Collateral(format!(
+                "tcb info fmspc {} doesn't match pck fmspc {}",
+                const_hex::encode(&tcb_info.fmspc),
+                const_hex::encode(pck.fmspc)
+            )));
+        }
+        if parts.is_tdx() != (tcb_info.id.as_deref() == Some("TDX")) {
+            return Err(VerifyError::Collateral(format!(
+                "tcb info {:?} doesn't match quote tee type {:#x}",
+                tcb_info.id, parts.tee_type
+            )));
+        }
+
+        let qe_level = qe_identity_level(&qe_identity, &EnclaveReport(parts.qe_report))?;
+        let (platform_status, mut advisory_ids) = platform_tcb_status(&tcb_info, &pck, &parts)?;
+
+        let mut status = platform_status.converge(qe_level.tcb_status);
+        if let Some(module_status) = tdx_module_status(&tcb_info, &parts)? {
+            status = status.converge(module_status);
+        }
+
+        advisory_ids.extend(qe_level.advisory_ids.iter().cloned());
+        advisory_ids.sort();
+        advisory_ids.dedup();
+
+        let now: DateTime<Utc> = now.into();
+
+        Ok(Verdict {
+            status,
+            platform_status,
+            qe_status: qe_level.tcb_status,
+            advisory_ids,
+            fmspc: pck.fmspc,
+            collateral_expired: now > tcb_info.next_update || now > qe_identity.next_update,
+        })
+    }
+}
+
+impl QuoteVerifier for Verifier {
+    fn verify(&self, quote: &Quote) -> Result<Verdict, VerifyError> {
+        self.verify_at(quote, SystemTime::now())
+    }
+}
+
+/// Check the quote body is signed by the attestation key, and the key is
+/// bound into the QE report
+fn dcap_bytes(quote: &Quote) -> Result<Vec<u8>, VerifyError> {
+    match quote {
+        Quote::Dcap(quote) => Ok(quote.to_bytes()),
+        Quote::SevSnp(_) => Err(VerifyError::Unsupported("sev-snp report".to_string())),
+    }
+}
+
+fn verify_attestation_key(parts: &QuoteParts) -> Result<(), VerifyError> {
+    let attestation_key = raw_public_key(parts.attestation_key)?;
+    verify_raw_signature(&attestation_key, parts.signed, parts.quote_signature)?;
+
+    let binding = quote::qe_report_data(parts.attestation_key, parts.qe_auth_data);
+    if EnclaveReport(parts.qe_report).report_data() != binding {
+        return Err(VerifyError::Signature(
+            "attestation key isn't bound to qe report".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn qe_identity_level<'a>(
+    identity: &'a QeIdentity,
+    report: &EnclaveReport,
+) -> Result<&'a QeTcbLevel, VerifyError> {
+    let misc_select = u32::from_le_bytes(report.misc_select()).to_be_bytes();
+
+    let matches = identity.mrsigner == report.mr_signer()
+        && identity.isvprodid == report.isv_prod_id()
+        && masked_eq(
+            &misc_select,
+            &identity.miscselect,
+            &identity.miscselect_mask,
+        )
+        && masked_eq(
+            &report.attributes(),
+            &identity.attributes,
+            &identity.attributes_mask,
+        );
+    if !matches {
+        return Err(VerifyError::Collateral(
+            "qe report doesn't match qe identity".to_string(),
+        ));
+    }
+
+    { identity.tcb_levels.iter() }
+        .find(|l| l.tcb.isvsvn <= report.isv_svn())
+        .ok_or_else(|| VerifyError::Collateral("qe tcb level not supported".to_string()))
+}
+
+fn platform_tcb_status(
+    tcb_info: &TcbInfo,
+    pck: &PckExtension,
+    parts: &QuoteParts,
+) -> Result<(TcbStatus, Vec<String>), VerifyError> {
+    let tee_tcb_svn = parts.tee_tcb_svn();
+
+    let level = tcb_info.tcb_levels.iter().find(|level| {
+        let sgx_ok = level.tcb.pcesvn <= pck.pcesvn && { level.tcb.sgxtcbcomponents.iter() }
+            .zip(pck.cpusvn_components)
+            .all(|(c, svn)| c.svn <= svn);
+
+        let tdx_ok = match tee_tcb_svn {
+            None => true,
+            // Module major/minor SVNs are covered by the module identity
+            // once the module major version is non-zero
+            Some(svn) => {
+                let skip = if svn[1] > 0 { 2 } else { 0 };
+
+                { level.tcb.tdxtcbcomponents.iter().zip(svn) }
+                    .skip(skip)
+                    .all(|(c, svn)| c.svn <= svn)
+            }
+        };
+
+        sgx_ok && tdx_ok
+    });
+
+    let level = level
+        .ok_or_else(|| VerifyError::Collateral("platform tcb level not supported".to_string()))?;
+
+    Ok((level.tcb_status, level.advisory_ids.clone()))
+}
+
+fn tdx_module_status(
+    tcb_info: &TcbInfo,
+    parts: &QuoteParts,
+) -> Result<Option<TcbStatus>, VerifyError> {
+    let (Some(svn), Some(mr_signer_seam), Some(seam_attributes)) = (
+        parts.tee_tcb_svn(),
+        parts.mr_signer_seam(),
+        parts.seam_attributes(),
+    ) else {
+        return Ok(None);
+    };
+
+    if svn[1] == 0 {
+        let Some(module) = &tcb_info.tdx_module else {
+            return Ok(None);
+        };
+
+        let matches = module.mrsigner == mr_signer_seam
+            && masked_eq(
+                &seam_attributes,
+                &module.attributes,
+                &module.attributes_mask,
+            );
+        if !matches {
+            return Err(VerifyError::Collateral(
+                "tdx module doesn't match tcb info".to_string(),
+            ));
+        }
+
+        return Ok(None);
+    }
+
+    let id = format!("TDX_{:02X}", svn[1]);
+    let identity = { tcb_info.tdx_module_identities.iter() }
+        .find(|i| i.id == id)
+        .ok_or_else(|| VerifyError::Collateral(format!("tdx module identity {id} not found")))?;
+
+    let matches = identity.mrsigner == mr_signer_seam
+        && masked_eq(
+            &seam_attributes,
+            &identity.attributes,
+            &identity.attributes_mask,
+        );
+    if !matches {
+        return Err(VerifyError::Collateral(format!(
+            "tdx module doesn't match identity {id}"
+        )));
+    }
+
+    { identity.tcb_levels.iter() }
+        .find(|l| l.tcb.isvsvn <= svn[0] as u16)
+        .map(|l| Some(l.tcb_status))
+        .ok_or_else(|| VerifyError::Collateral(format!("tdx module {id} tcb level not supported")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcb_status_converge() {
+        use TcbStatus::*;
+
+        assert_eq!(UpToDate.converge(UpToDate), UpToDate);
+        assert_eq!(UpToDate.converge(OutOfDate), OutOfDate);
+        assert_eq!(
+            ConfigurationNeeded.converge(OutOfDate),
+            OutOfDateConfigurationNeeded
+        );
+        assert_eq!(SwHardeningNeeded.converge(Revoked), Revoked);
+        assert_eq!(OutOfDate.converge(UpToDate), OutOfDate);
+    }
+
+    #[test]
+    fn test_tcb_status_json() {
+        let status: TcbStatus = serde_json::from_str(r#""SWHardeningNeeded""#).unwrap();
+        assert_eq!(status, TcbStatus::SwHardeningNeeded);
+    }
+
+    #[test]
+    fn test_truncated_quote() {
+        let mut raw = vec![0u8; 48];
+        raw[0] = 4;
+        raw[2] = 2;
+        raw[4] = 0x81;
+
+        assert!(matches!(
+            QuoteParts::parse(&raw),
+            Err(VerifyError::MalformedQuote(_))
+        ));
+    }
+
+    #[test]
+    fn test_masked_eq() {
+        assert!(masked_eq(&[0x11, 0xff], &[0x11, 0x00], &[0xff, 0x00]));
+        assert!(!masked_eq(&[0x11, 0xff], &[0x10, 0x00], &[0xff, 0x00]));
+        assert!(!masked_eq(&[0x11], &[0x11, 0x00], &[0xff, 0x00]));
+    }
+}
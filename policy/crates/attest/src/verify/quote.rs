@@ -0,0 +1,207 @@
+use sha2::{Digest, Sha256};
+
+use crate::errors::VerifyError;
+
+pub(crate) const HEADER_LEN: usize = 48;
+pub(crate) const ENCLAVE_REPORT_LEN: usize = 384;
+pub(crate) const TD10_REPORT_LEN: usize = 584;
+pub(crate) const TD15_REPORT_LEN: usize = 648;
+
+pub(crate) const TEE_TYPE_SGX: u32 = 0x00;
+pub(crate) const TEE_TYPE_TDX: u32 = 0x81;
+
+pub(crate) const ATTESTATION_KEY_TYPE_ECDSA_P256: u16 = 2;
+pub(crate) const CERT_DATA_TYPE_PCK_CHAIN: u16 = 5;
+pub(crate) const CERT_DATA_TYPE_QE_REPORT: u16 = 6;
+
+/// Borrowed view over the signed and signature parts of a raw quote
+pub(crate) struct QuoteParts<'a> {
+    pub tee_type: u32,
+    /// Header and report body, the bytes covered by the quote signature
+    pub signed: &'a [u8],
+    pub body: &'a [u8],
+    pub quote_signature: &'a [u8],
+    pub attestation_key: &'a [u8],
+    pub qe_report: &'a [u8],
+    pub qe_report_signature: &'a [u8],
+    pub qe_auth_data: &'a [u8],
+    pub pck_chain: &'a [u8],
+}
+
+impl<'a> QuoteParts<'a> {
+    pub fn parse(raw: &'a [u8]) -> Result<Self, VerifyError> {
+        let mut r = Reader::new(raw);
+
+        let header = r.take(HEADER_LEN)?;
+        let version = u16::from_le_bytes([header[0], header[1]]);
+        let key_type = u16::from_le_bytes([header[2], header[3]]);
+        let tee_type = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+        if key_type != ATTESTATION_KEY_TYPE_ECDSA_P256 {
+            return Err(VerifyError::Unsupported(format!(
+                "attestation key type {key_type}"
+            )));
+        }
+
+        let body = match (version, tee_type) {
+            (3, TEE_TYPE_SGX) | (4, TEE_TYPE_SGX) => r.take(ENCLAVE_REPORT_LEN)?,
+            (4, TEE_TYPE_TDX) => r.take(TD10_REPORT_LEN)?,
+            (5, _) => {
+                let _body_type = r.u16()?;
+                let body_len = r.u32()? as usize;
+                r.take(body_len)?
+            }
+            _ => {
+                return Err(VerifyError::Unsupported(format!(
+                    "quote version {version} tee type {tee_type:#x}"
+                )))
+            }
+        };
+        let signed = &raw[..r.pos];
+
+        let sig_len = r.u32()? as usize;
+        let mut s = Reader::new(r.take(sig_len)?);
+
+        let quote_signature = s.take(64)?;
+        let attestation_key = s.take(64)?;
+
+        // V3 quotes inline the QE report, V4 and later wrap it in a typed
+        // certification data block
+        let mut qe = if version == 3 {
+            s
+        } else {
+            let cert_type = s.u16()?;
+            let cert_len = s.u32()? as usize;
+            if cert_type != CERT_DATA_TYPE_QE_REPORT {
+                return Err(VerifyError::Unsupported(format!(
+                    "certification data type {cert_type}"
+                )));
+            }
+            Reader::new(s.take(cert_len)?)
+        };
+
+        let qe_report = qe.take(ENCLAVE_REPORT_LEN)?;
+        let qe_report_signature = qe.take(64)?;
+        let auth_len = qe.u16()? as usize;
+        let qe_auth_data = qe.take(auth_len)?;
+
+        let cert_type = qe.u16()?;
+        let cert_len = qe.u32()? as usize;
+        if cert_type != CERT_DATA_TYPE_PCK_CHAIN {
+            return Err(VerifyError::Unsupported(format!(
+                "qe certification data type {cert_type}"
+            )));
+        }
+        let pck_chain = qe.take(cert_len)?;
+
+        Ok(QuoteParts {
+            tee_type,
+            signed,
+            body,
+            quote_signature,
+            attestation_key,
+            qe_report,
+            qe_report_signature,
+            qe_auth_data,
+            pck_chain,
+        })
+    }
+
+    pub fn is_tdx(&self) -> bool {
+        matches!(self.body.len(), TD10_REPORT_LEN | TD15_REPORT_LEN)
+    }
+
+    /// TEE_TCB_SVN of a TD report body
+    pub fn tee_tcb_svn(&self) -> Option<[u8; 16]> {
+        self.is_tdx().then(|| array(&self.body[0..16]))
+    }
+
+    /// MRSIGNERSEAM of a TD report body
+    pub fn mr_signer_seam(&self) -> Option<[u8; 48]> {
+        self.is_tdx().then(|| array(&self.body[64..112]))
+    }
+
+    /// SEAMATTRIBUTES of a TD report body
+    pub fn seam_attributes(&self) -> Option<[u8; 8]> {
+        self.is_tdx().then(|| array(&self.body[112..120]))
+    }
+}
+
+/// Fields of the quoting enclave report used by identity checks
+pub(crate) struct EnclaveReport<'a>(pub &'a [u8]);
+
+impl EnclaveReport<'_> {
+    pub fn misc_select(&self) -> [u8; 4] {
+        array(&self.0[16..20])
+    }
+
+    pub fn attributes(&self) -> [u8; 16] {
+        array(&self.0[48..64])
+    }
+
+    pub fn mr_signer(&self) -> [u8; 32] {
+        array(&self.0[128..160])
+    }
+
+    pub fn isv_prod_id(&self) -> u16 {
+        u16::from_le_bytes([self.0[256], self.0[257]])
+    }
+
+    pub fn isv_svn(&self) -> u16 {
+        u16::from_le_bytes([self.0[258], self.0[259]])
+    }
+
+    pub fn report_data(&self) -> [u8; 64] {
+        array(&self.0[320..384])
+    }
+}
+
+/// Expected QE report data, `sha256(attestation_key || qe_auth_data)` padded
+/// with zeros
+pub(crate) fn qe_report_data(attestation_key: &[u8], qe_auth_data: &[u8]) -> [u8; 64] {
+    let mut report_data = [0u8; 64];
+    report_data[..32].copy_from_slice(
+        &Sha256::new_with_prefix(attestation_key)
+            .chain_update(qe_auth_data)
+            .finalize(),
+    );
+
+    report_data
+}
+
+fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    bytes.try_into().expect("fixed size slice")
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], VerifyError> {
+        let end = { self.pos.checked_add(len) }
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| {
+                VerifyError::MalformedQuote(format!("truncated at offset {}", self.pos))
+            })?;
+
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, VerifyError> {
+        self.take(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, VerifyError> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
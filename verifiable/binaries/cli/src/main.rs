@@ -5,6 +5,8 @@ use clap::{Parser, Subcommand};
 use serde_json::json;
 use uuid::Uuid;
 
+mod verify;
+
 #[derive(Parser)]
 #[command(name = "cli")]
 #[command(about = "CLI for hypervisor operations", long_about = None)]
@@ -18,6 +20,7 @@ enum Commands {
     Search(Search),
     Deploy(Deploy),
     Call(Call),
+    Verify(verify::Verify),
 }
 
 #[derive(Parser)]
@@ -91,6 +94,9 @@ async fn main() -> Result<()> {
                 get_agent_card_execute(&get_card.server).await?;
             }
         },
+        Commands::Verify(verify) => {
+            verify::verify_execute(verify)?;
+        }
     }
 
     Ok(())
@@ -0,0 +1,477 @@
+//! Offline checks of responses from the `/verifiable/*` endpoints
+//!
+//! Everything is recomputed from the saved JSON, the hypervisor isn't
+//! contacted. A response passes when its quote verifies, its TCB is up to
+//! date, its measurements match the policy and its report data binds the
+//! response content.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use attest::{
+    report_data::ReportData,
+    types::Quote,
+    verify::{Collateral, MeasurementPolicy, MockVerifier, QuoteVerifier, Verifier},
+};
+use clap::{Args, Parser, Subcommand};
+use hypervisor::{
+    api::{
+        encrypt::VerifiableCreateKeyPairResponse,
+        execute::{riscv, wasm},
+        policy::python,
+    },
+    commitment::{self, CommittedRequest, CommittedResponse, ExecutorKind},
+    crypto,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+
+#[derive(Parser)]
+pub struct Verify {
+    #[command(subcommand)]
+    pub subcommand: VerifySubcommands,
+}
+
+#[derive(Subcommand)]
+pub enum VerifySubcommands {
+    /// Check a `/verifiable/encrypt/create_keypair` response
+    Quote(VerifyQuote),
+    /// Check a `/x402_execute/verifiable/{wasm,riscv}` or
+    /// `/x402_policy/python/attest` response
+    Execution(VerifyExecution),
+}
+
+#[derive(Parser)]
+pub struct VerifyQuote {
+    #[command(flatten)]
+    trust: TrustArgs,
+
+    /// Saved create keypair response
+    response: PathBuf,
+}
+
+#[derive(Parser)]
+pub struct VerifyExecution {
+    #[command(flatten)]
+    trust: TrustArgs,
+
+    /// Execution request the response answers
+    #[arg(long)]
+    request: PathBuf,
+
+    /// Create keypair response of the session used
+    #[arg(long)]
+    session: PathBuf,
+
+    /// Saved execution response
+    response: PathBuf,
+}
+
+#[derive(Args)]
+struct TrustArgs {
+    /// Measurement policy, `.json` or TOML
+    #[arg(short, long)]
+    policy: PathBuf,
+
+    /// Collateral directory, as served by `/attestation/collateral`
+    #[arg(short, long, required_unless_present = "mock")]
+    collateral: Option<PathBuf>,
+
+    /// Accept mock quotes, development only
+    #[arg(long, conflicts_with = "collateral")]
+    mock: bool,
+}
+
+struct Trust {
+    policy: MeasurementPolicy,
+    verifier: Box<dyn QuoteVerifier>,
+}
+
+impl TrustArgs {
+    fn load(&self) -> Result<Trust> {
+        let policy = MeasurementPolicy::from_file(&self.policy).context("load policy")?;
+
+        let verifier: Box<dyn QuoteVerifier> = match &self.collateral {
+            Some(dir) => Box::new(Verifier::new(
+                Collateral::from_dir(dir).context("load collateral")?,
+            )),
+            None => Box::new(MockVerifier::new()),
+        };
+
+        Ok(Trust { policy, verifier })
+    }
+}
+
+/// Named check outcomes, failures carry the reason
+type Checks = Vec<(&'static str, Result<(), String>)>;
+
+pub fn verify_execute(verify: Verify) -> Result<()> {
+    let checks = match verify.subcommand {
+        VerifySubcommands::Quote(args) => {
+            let trust = args.trust.load()?;
+            let resp = read_json(&args.response)?;
+
+            check_create_keypair(&trust, &resp)
+        }
+        VerifySubcommands::Execution(args) => {
+            let trust = args.trust.load()?;
+            let session = read_json(&args.session)?;
+            let resp: serde_json::Value = read_json(&args.response)?;
+
+            let executor = ExecutorKind::deserialize(&resp["executor"]).context("executor")?;
+            match executor {
+                ExecutorKind::Wasm => {
+                    let req: wasm::ExecutionRequest = read_json(&args.request)?;
+                    let resp: wasm::VerifiableExecutionResponse = serde_json::from_value(resp)?;
+
+                    let evidence = (resp.result_quote.as_str(), resp.result_signature.as_deref());
+                    check_execution(&trust, &req, &session, &resp.execution, evidence)
+                }
+                ExecutorKind::Python => {
+                    let req: python::ExecutionRequest = read_json(&args.request)?;
+                    let resp: python::VerifiableExecutionResponse = serde_json::from_value(resp)?;
+
+                    let evidence = (resp.result_quote.as_str(), resp.result_signature.as_deref());
+                    check_execution(&trust, &req, &session, &resp.execution, evidence)
+                }
+                ExecutorKind::Riscv => {
+                    let req: riscv::ExecutionRequest = read_json(&args.request)?;
+                    let resp: riscv::VerifiableExecutionResponse = serde_json::from_value(resp)?;
+
+                    let evidence = (resp.result_quote.as_str(), resp.result_signature.as_deref());
+                    check_execution(&trust, &req, &session, &resp.execution, evidence)
+                }
+            }
+        }
+    };
+
+    for (name, result) in &checks {
+        match result {
+            Ok(()) => println!("PASS {name}"),
+            Err(reason) => println!("FAIL {name}: {reason}"),
+        }
+    }
+
+    if checks.iter().any(|(_, result)| result.is_err()) {
+        return Err(anyhow!("verification failed"));
+    }
+
+    println!("verification passed");
+
+    Ok(())
+}
+
+fn check_create_keypair(trust: &Trust, resp: &VerifiableCreateKeyPairResponse) -> Checks {
+    let expected = match crypto::pk_from_hex(&resp.session_pubkey) {
+        Ok(public_key) => ReportData::SessionKey {
+            public_key,
+            session_id: resp.session_id,
+        },
+        Err(e) => return vec![("session pubkey", Err(e.to_string()))],
+    };
+
+    check_evidence(trust, &resp.quote, resp.signature.as_deref(), &expected)
+}
+
+/// Check an execution of any executor, `evidence` is the quote and the
+/// response signature
+fn check_execution(
+    trust: &Trust,
+    req: &impl CommittedRequest,
+    session: &VerifiableCreateKeyPairResponse,
+    resp: &impl CommittedResponse,
+    (quote, signature): (&str, Option<&str>),
+) -> Checks {
+    let commitment = match result_commitment(req, session, resp) {
+        Ok(commitment) => commitment,
+        Err(e) => return vec![("result commitment", Err(format!("{e:#}")))],
+    };
+
+    let recomputed = const_hex::encode(commitment);
+    let mut checks: Checks = if recomputed == resp.result_commitment() {
+        vec![("result commitment", Ok(()))]
+    } else {
+        let reason = format!(
+            "recomputed {recomputed}, response has {}",
+            resp.result_commitment()
+        );
+        vec![("result commitment", Err(reason))]
+    };
+
+    checks.extend(check_evidence(
+        trust,
+        quote,
+        signature,
+        &ReportData::ExecutionResult { commitment },
+    ));
+
+    checks
+}
+
+/// Recompute `build_result_commitment` from the request and the session
+fn result_commitment(
+    req: &impl CommittedRequest,
+    session: &VerifiableCreateKeyPairResponse,
+    resp: &impl CommittedResponse,
+) -> Result<[u8; 32]> {
+    if session.session_id != resp.session_id() {
+        return Err(anyhow!(
+            "response session {} isn't the given session {}",
+            resp.session_id(),
+            session.session_id
+        ));
+    }
+
+    let user_pk = crypto::pk_from_hex(req.public_key()).context("request pubkey")?;
+    let session_pk = crypto::pk_from_hex(&session.session_pubkey).context("session pubkey")?;
+
+    Ok(commitment::build_result_commitment(
+        &user_pk,
+        &session_pk,
+        req,
+        resp,
+    ))
+}
+
+/// Check the quote, and that it or the response signature binds `expected`
+fn check_evidence(
+    trust: &Trust,
+    quote_hex: &str,
+    signature: Option<&str>,
+    expected: &ReportData,
+) -> Checks {
+    let quote = match const_hex::decode(quote_hex)
+        .map_err(|e| e.to_string())
+        .and_then(|raw| Quote::from_bytes(&raw).map_err(|e| e.to_string()))
+    {
+        Ok(quote) => quote,
+        Err(e) => return vec![("quote", Err(e))],
+    };
+
+    let mut checks: Checks = Vec::new();
+
+    match trust.verifier.verify(&quote) {
+        Ok(verdict) => {
+            checks.push(("quote signature", Ok(())));
+
+            let tcb_status = if verdict.is_up_to_date() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{:?}, collateral expired {}, advisories {:?}",
+                    verdict.status, verdict.collateral_expired, verdict.advisory_ids
+                ))
+            };
+            checks.push(("tcb status", tcb_status));
+        }
+        Err(e) => checks.push(("quote signature", Err(e.to_string()))),
+    }
+
+    checks.push((
+        "measurement",
+        trust.policy.check(&quote).map_err(|e| e.to_string()),
+    ));
+    checks.push(("report data", check_binding(&quote, signature, expected)));
+
+    checks
+}
+
+fn check_binding(
+    quote: &Quote,
+    signature: Option<&str>,
+    expected: &ReportData,
+) -> Result<(), String> {
+    let report_data = quote.decode_report_data().map_err(|e| e.to_string())?;
+
+    // Signed responses quote the response key once, the key signs the
+    // report data of every response
+    let Some(signature) = signature else {
+        if &report_data != expected {
+            return Err(format!(
+                "quote binds {report_data:?}, expected {expected:?}"
+            ));
+        }
+
+        return Ok(());
+    };
+
+    let ReportData::ResponseKey { public_key } = report_data else {
+        return Err(format!("signed response, but quote binds {report_data:?}"));
+    };
+    let signature = const_hex::decode(signature).map_err(|e| e.to_string())?;
+
+    expected
+        .verify_signature(&public_key, &signature)
+        .map_err(|e| e.to_string())
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let json = std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+
+    serde_json::from_str(&json).with_context(|| format!("parse {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use attest::{get_quote_with_provider, Provider};
+    use k256::ecdsa::{signature::Signer, Signature, SigningKey};
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn mock_trust() -> Trust {
+        Trust {
+            policy: MeasurementPolicy::default(),
+            verifier: Box::new(MockVerifier::new()),
+        }
+    }
+
+    fn failed(checks: &Checks) -> Vec<&'static str> {
+        { checks.iter() }
+            .filter(|(_, result)| result.is_err())
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    fn mock_quote_hex(report: ReportData) -> String {
+        let quote = get_quote_with_provider(report.to_raw(), Provider::Mock).unwrap();
+
+        const_hex::encode(quote.to_bytes())
+    }
+
+    /// A RISC-V execution in a fresh session, and the commitment the
+    /// hypervisor would attest for it
+    fn execution() -> (
+        riscv::ExecutionRequest,
+        VerifiableCreateKeyPairResponse,
+        riscv::ExecutionResponse,
+        [u8; 32],
+    ) {
+        let user_sk = SigningKey::random(&mut rand::rngs::OsRng);
+        let session_sk = SigningKey::random(&mut rand::rngs::OsRng);
+
+        let req = riscv::ExecutionRequest {
+            version: Default::default(),
+            encrypted_elf: "00".to_string(),
+            encrypted_arguments: vec![],
+            encrypted_stdin: None,
+            public_key: crypto::pk_to_hex(user_sk.verifying_key()),
+            session_id: None,
+            limits: Default::default(),
+        };
+        let session = VerifiableCreateKeyPairResponse {
+            session_pubkey: crypto::pk_to_hex(session_sk.verifying_key()),
+            session_id: Uuid::now_v7(),
+            quote: String::new(),
+            signature: None,
+        };
+        let resp = riscv::ExecutionResponse {
+            executor: ExecutorKind::Riscv,
+            runtime_version: "test".to_string(),
+            session_id: session.session_id,
+            encrypted_result: "0102".to_string(),
+            result_nonce: "03".to_string(),
+            result_commitment: String::new(),
+            instructions_retired: 42,
+            peak_memory_bytes: 4096,
+            exit_code: 0,
+            stdout_truncated: false,
+            stderr_truncated: false,
+            encrypted_stderr: None,
+        };
+
+        let commitment = result_commitment(&req, &session, &resp).unwrap();
+        let resp = riscv::ExecutionResponse {
+            result_commitment: const_hex::encode(commitment),
+            ..resp
+        };
+
+        (req, session, resp, commitment)
+    }
+
+    #[test]
+    fn test_check_execution() {
+        let (req, session, mut resp, commitment) = execution();
+        let quote = mock_quote_hex(ReportData::ExecutionResult { commitment });
+
+        let checks = check_execution(&mock_trust(), &req, &session, &resp, (&quote, None));
+        assert_eq!(checks.len(), 5);
+        assert!(failed(&checks).is_empty(), "{checks:?}");
+
+        // The quote still binds the original result
+        resp.encrypted_result = "0103".to_string();
+        let checks = check_execution(&mock_trust(), &req, &session, &resp, (&quote, None));
+        assert_eq!(failed(&checks), ["result commitment", "report data"]);
+    }
+
+    #[test]
+    fn test_check_execution_other_session() {
+        let (req, _, resp, commitment) = execution();
+        let (_, other_session, _, _) = execution();
+        let quote = mock_quote_hex(ReportData::ExecutionResult { commitment });
+
+        let checks = check_execution(&mock_trust(), &req, &other_session, &resp, (&quote, None));
+        let [("result commitment", Err(reason))] = checks.as_slice() else {
+            panic!("expected a failed result commitment, got {checks:?}");
+        };
+        assert!(reason.contains("isn't the given session"), "{reason}");
+    }
+
+    #[test]
+    fn test_check_signed_execution() {
+        let (req, session, resp, commitment) = execution();
+
+        let response_sk = SigningKey::random(&mut rand::rngs::OsRng);
+        let quote = mock_quote_hex(ReportData::ResponseKey {
+            public_key: *response_sk.verifying_key(),
+        });
+        let sign = |report: ReportData| {
+            let signature: Signature = response_sk.sign(&report.encode());
+            const_hex::encode(signature.to_bytes())
+        };
+
+        let signature = sign(ReportData::ExecutionResult { commitment });
+        let evidence = (quote.as_str(), Some(signature.as_str()));
+        let checks = check_execution(&mock_trust(), &req, &session, &resp, evidence);
+        assert!(failed(&checks).is_empty(), "{checks:?}");
+
+        // Signed by the response key, but over another result
+        let signature = sign(ReportData::ExecutionResult {
+            commitment: [0u8; 32],
+        });
+        let evidence = (quote.as_str(), Some(signature.as_str()));
+        let checks = check_execution(&mock_trust(), &req, &session, &resp, evidence);
+        assert_eq!(failed(&checks), ["report data"]);
+
+        // A quote over the result itself carries no response key
+        let quote = mock_quote_hex(ReportData::ExecutionResult { commitment });
+        let signature = sign(ReportData::ExecutionResult { commitment });
+        let evidence = (quote.as_str(), Some(signature.as_str()));
+        let checks = check_execution(&mock_trust(), &req, &session, &resp, evidence);
+        assert_eq!(failed(&checks), ["report data"]);
+    }
+
+    #[test]
+    fn test_check_create_keypair() {
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let session_id = Uuid::now_v7();
+        let report = ReportData::SessionKey {
+            public_key: *sk.verifying_key(),
+            session_id,
+        };
+        let quote = get_quote_with_provider(report.to_raw(), Provider::Mock).unwrap();
+
+        let mut resp = VerifiableCreateKeyPairResponse {
+            session_pubkey: crypto::pk_to_hex(sk.verifying_key()),
+            session_id,
+            quote: const_hex::encode(quote.to_bytes()),
+            signature: None,
+        };
+
+        let checks = check_create_keypair(&mock_trust(), &resp);
+        assert!(checks.iter().all(|(_, result)| result.is_ok()));
+
+        resp.session_id = Uuid::now_v7();
+        let checks = check_create_keypair(&mock_trust(), &resp);
+        assert_eq!(failed(&checks), ["report data"]);
+    }
+}
@@ -2,7 +2,9 @@ use crate::agent::a2a::server::A2AServer;
 use crate::agent::arxiv::personas::Personas;
 use crate::error::HypervisorError;
 use crate::types::HypervisorState;
+use crate::utils::{self, attest::Measurement};
 use anyhow::{anyhow, Context};
+use attest::event_log::EventKind;
 use axum::http::StatusCode;
 use axum::{extract::State, response::Json, routing::post, Router};
 use serde::{Deserialize, Serialize};
@@ -40,6 +42,18 @@ async fn deploy_handler(
             .context(StatusCode::INTERNAL_SERVER_ERROR)
             .map_err(|e| anyhow!(e.to_string()))?;
 
+        // Agents are compiled into the hypervisor, so the executable is the
+        // agent binary
+        let agent_binary = { tokio::fs::read(std::env::current_exe()?).await }
+            .context("read agent binary")
+            .context(StatusCode::INTERNAL_SERVER_ERROR)?;
+        utils::attest::measure_artifact(
+            Measurement::Required,
+            EventKind::AgentBinary,
+            &format!("agent {}", request.agent),
+            &agent_binary,
+        )?;
+
         let handle = tokio::spawn(A2AServer::start(agent, "127.0.0.1:3000"));
 
         state.set_agent(request.agent.clone(), handle).await;
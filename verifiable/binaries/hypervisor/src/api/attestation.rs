@@ -0,0 +1,189 @@
+use anyhow::{anyhow, Context};
+use attest::{
+    event_log::{self, Event},
+    verify::Collateral,
+};
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::HypervisorError, types::HypervisorState, utils::crypto};
+
+/// PCK certificate chain, optional since DCAP quotes usually embed it
+pub const PCK_CHAIN_FILE: &str = "pck_chain.pem";
+
+pub(crate) fn api_register(router: Router<HypervisorState>) -> Router<HypervisorState> {
+    router
+        .route("/attestation/event_log", get(get_event_log))
+        .route("/attestation/collateral", get(get_collateral))
+        .route("/attestation/response_key", get(get_response_key))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventLogResponse {
+    pub events: Vec<Event>,
+    /// Replayed RTMR3, equals the quoted RTMR3 if no artifact was loaded
+    /// after the quote was taken
+    pub rtmr3: String,
+}
+
+async fn get_event_log() -> Json<EventLogResponse> {
+    let events = event_log::events();
+    let rtmr3 = const_hex::encode(event_log::replay(&events));
+
+    Json(EventLogResponse { events, rtmr3 })
+}
+
+/// Collateral as cached from Intel PCS, bodies untouched so their
+/// signatures still verify
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollateralResponse {
+    pub pck_chain: Option<String>,
+    pub tcb_info: String,
+    pub qe_identity: String,
+    pub tcb_signing_chain: String,
+}
+
+impl From<CollateralResponse> for Collateral {
+    fn from(resp: CollateralResponse) -> Self {
+        Collateral {
+            tcb_info: resp.tcb_info,
+            qe_identity: resp.qe_identity,
+            tcb_signing_chain: resp.tcb_signing_chain.into_bytes(),
+        }
+    }
+}
+
+async fn get_collateral(
+    State(state): State<HypervisorState>,
+) -> Result<Json<CollateralResponse>, HypervisorError> {
+    let dir = { state.config.collateral_dir.as_ref() }
+        .ok_or_else(|| anyhow!("collateral cache not configured"))
+        .context(StatusCode::NOT_FOUND)?;
+
+    let collateral = Collateral::from_dir(dir)
+        .context("load cached collateral")
+        .context(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let pck_chain = match tokio::fs::read_to_string(dir.join(PCK_CHAIN_FILE)).await {
+        Ok(pem) => Some(pem),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let tcb_signing_chain = String::from_utf8(collateral.tcb_signing_chain)
+        .context("tcb signing chain isn't pem")
+        .context(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    Ok(Json(CollateralResponse {
+        pck_chain,
+        tcb_info: collateral.tcb_info,
+        qe_identity: collateral.qe_identity,
+        tcb_signing_chain,
+    }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResponseKeyResponse {
+    pub public_key: String,
+    /// Quote with `ReportData::ResponseKey` over `public_key`
+    pub quote: String,
+}
+
+async fn get_response_key(
+    State(state): State<HypervisorState>,
+) -> Result<Json<ResponseKeyResponse>, HypervisorError> {
+    let key = { state.response_key() }
+        .ok_or_else(|| anyhow!("signed responses not enabled"))
+        .context(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(ResponseKeyResponse {
+        public_key: crypto::pk_to_hex(key.public_key()),
+        quote: const_hex::encode(key.quote().to_bytes()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{api::RouterRegister, Config};
+
+    use super::*;
+
+    fn test_server(state: HypervisorState) -> axum_test::TestServer {
+        axum_test::TestServer::new(Router::new().register_api(api_register).with_state(state))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_api_event_log() {
+        let server = test_server(HypervisorState::default());
+
+        let response = server.get("/attestation/event_log").await;
+
+        response.assert_status_ok();
+
+        let log: EventLogResponse = response.json();
+        assert_eq!(
+            const_hex::decode(log.rtmr3).unwrap(),
+            event_log::replay(&log.events)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_collateral() {
+        let server = test_server(HypervisorState::default());
+        server
+            .get("/attestation/collateral")
+            .await
+            .assert_status_not_found();
+
+        let dir = std::env::temp_dir().join(format!("collateral-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, content) in [
+            (attest::verify::TCB_INFO_FILE, "{}"),
+            (attest::verify::QE_IDENTITY_FILE, "{}"),
+            (attest::verify::TCB_SIGNING_CHAIN_FILE, "pem"),
+        ] {
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+
+        let server = test_server(HypervisorState::new(Config {
+            collateral_dir: Some(dir.clone()),
+            ..Default::default()
+        }));
+        let response = server.get("/attestation/collateral").await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        response.assert_status_ok();
+
+        let collateral: CollateralResponse = response.json();
+        assert_eq!(collateral.pck_chain, None);
+        assert_eq!(collateral.tcb_signing_chain, "pem");
+    }
+
+    #[cfg(feature = "mock-attest")]
+    #[tokio::test]
+    async fn test_api_response_key() {
+        use attest::{report_data::ReportData, types::Quote};
+
+        let server = test_server(HypervisorState::default());
+        server
+            .get("/attestation/response_key")
+            .await
+            .assert_status_not_found();
+
+        let key = crate::utils::attest::ResponseKey::generate().unwrap();
+        let server = test_server(HypervisorState::default().with_response_key(key));
+        let response = server.get("/attestation/response_key").await;
+
+        response.assert_status_ok();
+
+        let resp: ResponseKeyResponse = response.json();
+        let quote = Quote::from_bytes(&const_hex::decode(&resp.quote).unwrap()).unwrap();
+        assert_eq!(
+            quote.decode_report_data().unwrap(),
+            ReportData::ResponseKey {
+                public_key: crypto::pk_from_hex(&resp.public_key).unwrap(),
+            }
+        );
+    }
+}
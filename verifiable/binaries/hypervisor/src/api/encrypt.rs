@@ -1,12 +1,19 @@
-use anyhow::Context;
-use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use anyhow::{anyhow, Context};
+use attest::report_data::ReportData;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, post},
+    Json, Router,
+};
+use k256::ecdsa::{signature::Verifier, Signature};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
     error::HypervisorError,
     types::HypervisorState,
-    utils::{attest::generate_raw_report, crypto},
+    utils::{self, crypto},
 };
 
 pub(crate) fn api_register(router: Router<HypervisorState>) -> Router<HypervisorState> {
@@ -16,6 +23,7 @@ pub(crate) fn api_register(router: Router<HypervisorState>) -> Router<Hypervisor
             "/verifiable/encrypt/create_keypair",
             post(verifiable_create_keypair),
         )
+        .route("/encrypt/session/{session_id}", delete(close_session))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,25 +31,31 @@ pub struct VerifiableCreateKeyPairResponse {
     pub session_pubkey: String,
     pub session_id: Uuid,
     pub quote: String,
+    /// Set with signed responses, `quote` then attests the response key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 async fn verifiable_create_keypair(
     state: State<HypervisorState>,
     req: Json<CreateKeyPairRequest>,
 ) -> Result<Json<VerifiableCreateKeyPairResponse>, HypervisorError> {
-    let Json(raw_resp) = create_keypair(state, req).await?;
+    let Json(raw_resp) = create_keypair(state.clone(), req).await?;
 
-    let session_pk = const_hex::decode(raw_resp.session_pubkey.as_str()).expect("impossible");
-    let report = generate_raw_report(&[session_pk.as_slice(), raw_resp.session_id.as_bytes()]);
+    let report = ReportData::SessionKey {
+        public_key: crypto::pk_from_hex(&raw_resp.session_pubkey).expect("impossible"),
+        session_id: raw_resp.session_id,
+    };
 
-    let quote = attest::get_quote(report)
-        .context("get create keypair quote")
+    let attestation = utils::attest::attest_response(&state, report)
+        .context("attest create keypair")
         .context(StatusCode::INTERNAL_SERVER_ERROR)?;
 
     let verifiable_resp = VerifiableCreateKeyPairResponse {
         session_pubkey: raw_resp.session_pubkey,
         session_id: raw_resp.session_id,
-        quote: const_hex::encode(quote.to_bytes()),
+        quote: attestation.quote_hex(),
+        signature: attestation.signature_hex(),
     };
 
     Ok(Json(verifiable_resp))
@@ -76,6 +90,44 @@ async fn create_keypair(
     Ok(Json(resp))
 }
 
+/// Proof of session ownership, `signature` is made by `pubkey` over the
+/// session id bytes
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CloseSessionRequest {
+    pub pubkey: String,
+    pub signature: String,
+}
+
+/// Drop a session and its key pair before it expires
+async fn close_session(
+    State(state): State<HypervisorState>,
+    Path(session_id): Path<Uuid>,
+    Json(req): Json<CloseSessionRequest>,
+) -> Result<StatusCode, HypervisorError> {
+    let req_pk = crypto::pk_from_hex(&req.pubkey)
+        .context("recover request pubkey")
+        .context(StatusCode::BAD_REQUEST)?;
+
+    let signature = const_hex::decode(&req.signature)
+        .ok()
+        .and_then(|raw| Signature::from_slice(&raw).ok())
+        .ok_or_else(|| anyhow!("invalid signature"))
+        .context(StatusCode::BAD_REQUEST)?;
+
+    req_pk
+        .verify(session_id.as_bytes(), &signature)
+        .context("session close not signed by session owner")
+        .context(StatusCode::UNAUTHORIZED)?;
+
+    if !state.close_session(&req_pk, session_id) {
+        return Err(anyhow!("session not found")
+            .context(StatusCode::NOT_FOUND)
+            .into());
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::api::RouterRegister;
@@ -107,4 +159,132 @@ mod tests {
             resp.session_pubkey, resp.session_id
         );
     }
+
+    #[cfg(feature = "mock-attest")]
+    #[tokio::test]
+    async fn test_api_verifiable_create_keypair() {
+        use attest::verify::{MockVerifier, QuoteVerifier};
+
+        let server = axum_test::TestServer::new(
+            Router::new()
+                .register_api(api_register)
+                .with_state(HypervisorState::default()),
+        )
+        .unwrap();
+
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let pk = sk.verifying_key().to_encoded_point(true).to_string();
+
+        let response = server
+            .post("/verifiable/encrypt/create_keypair")
+            .json(&CreateKeyPairRequest { pubkey: pk })
+            .await;
+
+        response.assert_status_ok();
+
+        let resp = response.json::<VerifiableCreateKeyPairResponse>();
+        let quote =
+            attest::types::Quote::from_bytes(&const_hex::decode(&resp.quote).unwrap()).unwrap();
+        MockVerifier::new().verify(&quote).unwrap();
+
+        assert_eq!(
+            quote.decode_report_data().unwrap(),
+            ReportData::SessionKey {
+                public_key: crypto::pk_from_hex(&resp.session_pubkey).unwrap(),
+                session_id: resp.session_id,
+            }
+        );
+    }
+
+    #[cfg(feature = "mock-attest")]
+    #[tokio::test]
+    async fn test_api_verifiable_create_keypair_signed() {
+        use crate::utils::attest::ResponseKey;
+
+        let response_key = ResponseKey::generate().unwrap();
+        let response_pk = *response_key.public_key();
+        let server = axum_test::TestServer::new(
+            Router::new()
+                .register_api(api_register)
+                .with_state(HypervisorState::default().with_response_key(response_key)),
+        )
+        .unwrap();
+
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let pk = sk.verifying_key().to_encoded_point(true).to_string();
+
+        let response = server
+            .post("/verifiable/encrypt/create_keypair")
+            .json(&CreateKeyPairRequest { pubkey: pk })
+            .await;
+
+        response.assert_status_ok();
+
+        let resp = response.json::<VerifiableCreateKeyPairResponse>();
+        let quote =
+            attest::types::Quote::from_bytes(&const_hex::decode(&resp.quote).unwrap()).unwrap();
+        assert_eq!(
+            quote.decode_report_data().unwrap(),
+            ReportData::ResponseKey {
+                public_key: response_pk
+            }
+        );
+
+        let report = ReportData::SessionKey {
+            public_key: crypto::pk_from_hex(&resp.session_pubkey).unwrap(),
+            session_id: resp.session_id,
+        };
+        let signature = const_hex::decode(resp.signature.unwrap()).unwrap();
+        report.verify_signature(&response_pk, &signature).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_close_session() {
+        use k256::ecdsa::signature::Signer;
+
+        let server = axum_test::TestServer::new(
+            Router::new()
+                .register_api(api_register)
+                .with_state(HypervisorState::default()),
+        )
+        .unwrap();
+
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let pk = sk.verifying_key().to_encoded_point(true).to_string();
+
+        let resp = server
+            .post("/encrypt/create_keypair")
+            .json(&CreateKeyPairRequest { pubkey: pk.clone() })
+            .await
+            .json::<CreateKeyPairResponse>();
+        let path = format!("/encrypt/session/{}", resp.session_id);
+
+        // Signed by someone else
+        let other = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let signature: Signature = other.sign(resp.session_id.as_bytes());
+        server
+            .delete(&path)
+            .json(&CloseSessionRequest {
+                pubkey: pk.clone(),
+                signature: const_hex::encode(signature.to_bytes()),
+            })
+            .await
+            .assert_status_unauthorized();
+
+        let signature: Signature = sk.sign(resp.session_id.as_bytes());
+        let req = CloseSessionRequest {
+            pubkey: pk,
+            signature: const_hex::encode(signature.to_bytes()),
+        };
+        server
+            .delete(&path)
+            .json(&req)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        server
+            .delete(&path)
+            .json(&req)
+            .await
+            .assert_status_not_found();
+    }
 }
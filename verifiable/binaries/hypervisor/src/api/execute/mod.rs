@@ -1 +1,76 @@
+//! Guest executors
+//!
+//! The endpoints of every executor decrypt the request, hand the guest to
+//! an [`Executor`], then seal, commit and attest what it returns.
+
+pub mod riscv;
 pub mod wasm;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::HypervisorError, utils::commitment::ExecutorKind};
+
+/// Decrypted guest and what it runs with
+pub struct GuestInput {
+    pub program: Vec<u8>,
+    /// Passed after the argv[0] of the executor
+    pub arguments: Vec<String>,
+    pub stdin: Vec<u8>,
+}
+
+/// What a guest left when it exited
+pub struct GuestOutput {
+    pub exit_code: i32,
+    pub stdout: Vec<u8>,
+    pub stdout_truncated: bool,
+    pub stderr: Vec<u8>,
+    pub stderr_truncated: bool,
+}
+
+/// Limit an execution ran into, reported by every executor
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionLimit {
+    Fuel,
+    Memory,
+    Table,
+    WallClock,
+    /// Retired instructions of a RISC-V guest
+    Instructions,
+    /// Bytes a WASM guest holds in its writable directories
+    Files,
+}
+
+impl std::fmt::Display for ExecutionLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ExecutionLimit::Fuel => "fuel",
+            ExecutionLimit::Memory => "memory",
+            ExecutionLimit::Table => "table",
+            ExecutionLimit::WallClock => "wall clock",
+            ExecutionLimit::Instructions => "instructions",
+            ExecutionLimit::Files => "files",
+        };
+
+        f.write_str(name)
+    }
+}
+
+#[async_trait]
+pub trait Executor: Send {
+    /// Resources a run used, reported and committed with its output
+    type Metrics: Send;
+
+    fn kind(&self) -> ExecutorKind;
+
+    /// Committed with every result
+    fn runtime_version(&self) -> &'static str;
+
+    /// Run `input` until the guest exits. A guest exiting non zero is an
+    /// output too, errors are limits it ran into or faults of the guest
+    async fn execute(
+        self,
+        input: GuestInput,
+    ) -> Result<(GuestOutput, Self::Metrics), HypervisorError>;
+}
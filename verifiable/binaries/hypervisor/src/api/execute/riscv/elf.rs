@@ -0,0 +1,175 @@
+use anyhow::{bail, ensure, Context};
+
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_RISCV: u16 = 0xf3;
+const EF_RISCV_RVC: u32 = 0x1;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+/// Statically linked RV64 executable
+#[derive(Debug)]
+pub struct Elf<'a> {
+    pub entry: u64,
+    pub segments: Vec<Segment<'a>>,
+}
+
+/// `PT_LOAD` segment, zero filled from the end of `data` up to `mem_size`
+#[derive(Debug)]
+pub struct Segment<'a> {
+    pub vaddr: u64,
+    pub data: &'a [u8],
+    pub mem_size: u64,
+}
+
+impl Segment<'_> {
+    pub fn end(&self) -> u64 {
+        self.vaddr + self.mem_size
+    }
+}
+
+/// Parse a little endian ELF64 executable for RISC-V without compressed
+/// instructions, checking its segments lie below `max_addr`
+pub fn parse(bytes: &[u8], max_addr: u64) -> anyhow::Result<Elf<'_>> {
+    ensure!(bytes.len() >= EHDR_SIZE, "truncated elf header");
+    ensure!(&bytes[..4] == ELF_MAGIC, "not an elf file");
+    ensure!(bytes[4] == ELFCLASS64, "not a 64 bit elf");
+    ensure!(bytes[5] == ELFDATA2LSB, "not a little endian elf");
+    ensure!(read_u16(bytes, 0x12) == EM_RISCV, "not a risc-v elf");
+    ensure!(read_u16(bytes, 0x10) == ET_EXEC, "not a static executable");
+    ensure!(
+        read_u32(bytes, 0x30) & EF_RISCV_RVC == 0,
+        "compressed instructions aren't supported"
+    );
+
+    let entry = read_u64(bytes, 0x18);
+    let ph_offset = read_u64(bytes, 0x20) as usize;
+    let ph_entry_size = read_u16(bytes, 0x36) as usize;
+    let ph_count = read_u16(bytes, 0x38) as usize;
+    ensure!(ph_entry_size == PHDR_SIZE, "unexpected program header size");
+
+    let ph_end = ph_count
+        .checked_mul(PHDR_SIZE)
+        .and_then(|len| len.checked_add(ph_offset))
+        .filter(|end| *end <= bytes.len())
+        .context("truncated program headers")?;
+
+    let mut segments = vec![];
+    for ph in bytes[ph_offset..ph_end].chunks_exact(PHDR_SIZE) {
+        match read_u32(ph, 0) {
+            PT_LOAD => {}
+            PT_DYNAMIC | PT_INTERP => bail!("dynamically linked executables aren't supported"),
+            _ => continue,
+        }
+
+        let offset = read_u64(ph, 8) as usize;
+        let vaddr = read_u64(ph, 16);
+        let file_size = read_u64(ph, 32) as usize;
+        let mem_size = read_u64(ph, 40);
+
+        ensure!(
+            file_size as u64 <= mem_size,
+            "segment larger in file than in memory"
+        );
+        let data = offset
+            .checked_add(file_size)
+            .and_then(|end| bytes.get(offset..end))
+            .context("segment past the end of the file")?;
+        ensure!(
+            vaddr
+                .checked_add(mem_size)
+                .is_some_and(|end| end <= max_addr),
+            "segment at {vaddr:#x} outside the program area"
+        );
+
+        segments.push(Segment {
+            vaddr,
+            data,
+            mem_size,
+        });
+    }
+
+    ensure!(!segments.is_empty(), "no loadable segments");
+    ensure!(
+        { segments.iter() }.any(|s| (s.vaddr..s.end()).contains(&entry)),
+        "entry point {entry:#x} outside the loaded segments"
+    );
+
+    Ok(Elf { entry, segments })
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Executable running `code` from the start of its only segment
+#[cfg(test)]
+pub fn executable(code: &[u32]) -> Vec<u8> {
+    const BASE: u64 = 0x10000;
+
+    let headers = EHDR_SIZE + PHDR_SIZE;
+    let size = (headers + 4 * code.len()) as u64;
+
+    let mut elf = vec![0; headers];
+    elf[..4].copy_from_slice(ELF_MAGIC);
+    elf[4..7].copy_from_slice(&[ELFCLASS64, ELFDATA2LSB, 1]);
+    elf[0x10..0x12].copy_from_slice(&ET_EXEC.to_le_bytes());
+    elf[0x12..0x14].copy_from_slice(&EM_RISCV.to_le_bytes());
+    elf[0x14..0x18].copy_from_slice(&1u32.to_le_bytes());
+    elf[0x18..0x20].copy_from_slice(&(BASE + headers as u64).to_le_bytes());
+    elf[0x20..0x28].copy_from_slice(&(EHDR_SIZE as u64).to_le_bytes());
+    elf[0x34..0x36].copy_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    elf[0x36..0x38].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    elf[0x38..0x3a].copy_from_slice(&1u16.to_le_bytes());
+
+    let ph = &mut elf[EHDR_SIZE..];
+    ph[0..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+    ph[4..8].copy_from_slice(&5u32.to_le_bytes());
+    ph[16..24].copy_from_slice(&BASE.to_le_bytes());
+    ph[24..32].copy_from_slice(&BASE.to_le_bytes());
+    ph[32..40].copy_from_slice(&size.to_le_bytes());
+    ph[40..48].copy_from_slice(&size.to_le_bytes());
+
+    elf.extend(code.iter().flat_map(|word| word.to_le_bytes()));
+    elf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let bytes = executable(&[0x0000_0073]);
+        let elf = parse(&bytes, u64::MAX).unwrap();
+        assert_eq!(elf.entry, 0x10000 + 120);
+        assert_eq!(elf.segments.len(), 1);
+        assert_eq!(elf.segments[0].end(), 0x10000 + 124);
+
+        assert!(parse(&bytes, 0x10000).is_err());
+        assert!(parse(&bytes[..100], u64::MAX).is_err());
+
+        let mut compressed = bytes.clone();
+        compressed[0x30] = EF_RISCV_RVC as u8;
+        assert!(parse(&compressed, u64::MAX).is_err());
+
+        let mut x86 = bytes;
+        x86[0x12] = 0x3e;
+        assert!(parse(&x86, u64::MAX).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+# Prints "Hello <argv[1]>", then echoes stdin and exits 0
+#
+# llvm-mc -triple=riscv64 -mattr=+m,-c,-relax -filetype=obj hello.s -o hello.o
+# ld.lld -m elf64lriscv -static -e _start -s hello.o -o hello.elf
+
+    .text
+    .globl _start
+_start:
+    mv      s0, a1              # argv
+    li      a0, 1
+    la      a1, greeting
+    li      a2, 6
+    li      a7, 64              # write
+    ecall
+
+    ld      s1, 8(s0)           # argv[1], null without arguments
+    beqz    s1, 3f
+    li      a2, 0
+1:  add     t0, s1, a2
+    lbu     t0, 0(t0)
+    beqz    t0, 2f
+    addi    a2, a2, 1
+    j       1b
+2:  li      a0, 1
+    mv      a1, s1
+    li      a7, 64              # write
+    ecall
+
+3:  li      a0, 1
+    la      a1, newline
+    li      a2, 1
+    li      a7, 64              # write
+    ecall
+
+    addi    sp, sp, -256
+4:  li      a0, 0
+    mv      a1, sp
+    li      a2, 256
+    li      a7, 63              # read
+    ecall
+    blez    a0, 5f
+    mv      a2, a0
+    li      a0, 1
+    mv      a1, sp
+    li      a7, 64              # write
+    ecall
+    j       4b
+
+5:  li      a0, 0
+    li      a7, 93              # exit
+    ecall
+
+    .section .rodata
+greeting:
+    .ascii  "Hello "
+newline:
+    .ascii  "\n"
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Caps of one RISC-V execution, requests may only tighten the configured
+/// ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiscvLimits {
+    /// Retired instructions, each counts one
+    pub instructions: u64,
+    /// Mapped guest pages, program, stack and heap together
+    pub memory_bytes: u64,
+    pub wall_clock_ms: u64,
+}
+
+impl Default for RiscvLimits {
+    fn default() -> Self {
+        RiscvLimits {
+            instructions: 10_000_000_000,
+            memory_bytes: 256 * 1024 * 1024,
+            wall_clock_ms: 30_000,
+        }
+    }
+}
+
+/// Limits asked for by a request, unset ones keep the configured value
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct RequestedRiscvLimits {
+    pub instructions: Option<u64>,
+    pub memory_bytes: Option<u64>,
+    pub wall_clock_ms: Option<u64>,
+}
+
+impl RiscvLimits {
+    pub fn tighten(self, requested: &RequestedRiscvLimits) -> Self {
+        let min = |cap: u64, requested: Option<u64>| requested.map_or(cap, |r| r.min(cap));
+
+        RiscvLimits {
+            instructions: min(self.instructions, requested.instructions),
+            memory_bytes: min(self.memory_bytes, requested.memory_bytes),
+            wall_clock_ms: min(self.wall_clock_ms, requested.wall_clock_ms),
+        }
+    }
+
+    pub fn wall_clock(&self) -> Duration {
+        Duration::from_millis(self.wall_clock_ms)
+    }
+}
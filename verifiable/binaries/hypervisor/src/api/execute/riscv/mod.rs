@@ -0,0 +1,642 @@
+//! RISC-V guests, statically linked RV64IM executables run by the
+//! interpreter in [`vm`]
+//!
+//! Requests are sealed, committed and attested like WASM executions. The
+//! guest sees its arguments in argv and does its I/O through the syscalls
+//! [`vm`] lists, stdin, stdout and stderr are all it can reach.
+
+pub mod elf;
+pub mod limits;
+pub mod vm;
+
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use aes_gcm_siv::aead::Aead;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use attest::{event_log::EventKind, report_data::ReportData};
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+use limits::{RequestedRiscvLimits, RiscvLimits};
+use vm::{Trap, Vm};
+
+use crate::{
+    api::execute::{ExecutionLimit, Executor, GuestInput, GuestOutput},
+    error::HypervisorError,
+    types::HypervisorState,
+    utils::{
+        self,
+        attest::Measurement,
+        auth::{self, RequestSignature, SignedJson},
+        commitment::{self, CommittedRequest, CommittedResponse, ExecutorKind},
+        crypto::{self, EnvelopeVersion},
+        x402::{self, X402_FIXED_PRICE_USDC},
+    },
+};
+
+/// Instructions between two deadline checks of a running guest
+const INSTRUCTION_SLICE: u64 = 100_000;
+
+/// Committed with every result, bumped whenever a program could observe a
+/// change of the interpreter
+pub const RISCV_RUNTIME_VERSION: &str = "x402-rv64im 1";
+
+pub const DEFAULT_RISCV_OUTPUT_LIMIT_BYTES: usize = 1024 * 1024;
+
+pub(crate) fn api_register(router: Router<HypervisorState>) -> Router<HypervisorState> {
+    router.route("/test/execute/riscv", post(execute_riscv))
+}
+
+pub(crate) fn api_x402_register(
+    router: Router<HypervisorState>,
+    state: HypervisorState,
+) -> Router<HypervisorState> {
+    let x402_router = Router::new()
+        .route(
+            "/x402_execute/verifiable/riscv",
+            post(verifiable_execute_riscv),
+        )
+        .layer(x402::create_x402_middleware(X402_FIXED_PRICE_USDC))
+        .with_state(state);
+
+    router.merge(x402_router)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VerifiableExecutionResponse {
+    #[serde(flatten)]
+    pub execution: ExecutionResponse,
+    pub result_quote: String,
+    /// Set with signed responses, `result_quote` then attests the response key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_signature: Option<String>,
+}
+
+async fn verifiable_execute_riscv(
+    State(state): State<HypervisorState>,
+    SignedJson {
+        body: req,
+        signature,
+    }: SignedJson<ExecutionRequest>,
+) -> Result<Json<VerifiableExecutionResponse>, HypervisorError> {
+    authorize_execution(&state, &req, signature.as_ref())?;
+
+    run_verifiable_execution(&state, req, signature)
+        .await
+        .map(Json)
+}
+
+/// [`run_execution`] with the result attested
+pub(crate) async fn run_verifiable_execution(
+    state: &HypervisorState,
+    req: ExecutionRequest,
+    signature: Option<RequestSignature>,
+) -> Result<VerifiableExecutionResponse, HypervisorError> {
+    let resp = run_execution(state, req, signature, Measurement::Required).await?;
+    let commitment: [u8; 32] =
+        const_hex::decode_to_array(&resp.result_commitment).expect("impossible");
+
+    let report = ReportData::ExecutionResult { commitment };
+    let attestation = utils::attest::attest_response(state, report)
+        .context("attest execute result")
+        .context(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(VerifiableExecutionResponse {
+        execution: resp,
+        result_quote: attestation.quote_hex(),
+        result_signature: attestation.signature_hex(),
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecutionRequest {
+    /// Framing of the encrypted fields, legacy when left out
+    #[serde(default)]
+    pub version: EnvelopeVersion,
+    /// Hex of the sealed ELF executable
+    pub encrypted_elf: String,
+    /// Passed as argv after `riscv`, each must be a string
+    #[serde(default = "Vec::new")]
+    pub encrypted_arguments: Vec<String>,
+    /// Hex of the sealed stdin, empty stdin when left out
+    #[serde(default)]
+    pub encrypted_stdin: Option<String>,
+    pub public_key: String,
+    /// Session to use, the latest session of `public_key` when left out
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+    /// Tighter caps than the configured ones
+    #[serde(default)]
+    pub limits: RequestedRiscvLimits,
+}
+
+impl CommittedRequest for ExecutionRequest {
+    fn version(&self) -> EnvelopeVersion {
+        self.version
+    }
+
+    fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    fn committed_inputs(&self) -> Vec<Cow<'_, [u8]>> {
+        let mut inputs = vec![
+            self.encrypted_elf.as_bytes().into(),
+            commitment::count(self.encrypted_arguments.len()),
+        ];
+        inputs.extend({ self.encrypted_arguments.iter() }.map(|a| a.as_bytes().into()));
+        inputs.push(
+            self.encrypted_stdin
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .into(),
+        );
+
+        inputs
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExecutionResponse {
+    pub executor: ExecutorKind,
+    pub runtime_version: String,
+    pub session_id: Uuid,
+    /// Stdout of the program, also when it failed
+    pub encrypted_result: String,
+    pub result_nonce: String,
+    pub result_commitment: String,
+    /// Same count on every run of the same program and inputs
+    pub instructions_retired: u64,
+    pub peak_memory_bytes: u64,
+    /// Passed to `exit`, non zero when the program failed
+    pub exit_code: i32,
+    /// Output past `riscv_output_limit_bytes` was dropped
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    /// Stderr of a failed program, sealed like the request fields
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_stderr: Option<String>,
+}
+
+/// The result with its nonce, then the retired instructions, the peak
+/// memory, the truncation flags and the stderr of failed runs
+impl CommittedResponse for ExecutionResponse {
+    fn executor(&self) -> ExecutorKind {
+        self.executor
+    }
+
+    fn runtime_version(&self) -> &str {
+        &self.runtime_version
+    }
+
+    fn session_id(&self) -> Uuid {
+        self.session_id
+    }
+
+    fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+
+    fn committed_outputs(&self) -> Vec<Cow<'_, [u8]>> {
+        vec![
+            self.result_nonce.as_bytes().into(),
+            self.encrypted_result.as_bytes().into(),
+            self.instructions_retired.to_be_bytes().to_vec().into(),
+            self.peak_memory_bytes.to_be_bytes().to_vec().into(),
+            vec![
+                u8::from(self.stdout_truncated),
+                u8::from(self.stderr_truncated),
+            ]
+            .into(),
+            self.encrypted_stderr
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .into(),
+        ]
+    }
+
+    fn result_commitment(&self) -> &str {
+        &self.result_commitment
+    }
+}
+
+#[tracing::instrument(skip(state, req, signature), err)]
+async fn execute_riscv(
+    State(state): State<HypervisorState>,
+    SignedJson {
+        body: req,
+        signature,
+    }: SignedJson<ExecutionRequest>,
+) -> Result<Json<ExecutionResponse>, HypervisorError> {
+    authorize_execution(&state, &req, signature.as_ref())?;
+
+    run_execution(&state, req, signature, Measurement::BestEffort)
+        .await
+        .map(Json)
+}
+
+/// Checks made before a request runs, its signature is spent here
+pub(crate) fn authorize_execution(
+    state: &HypervisorState,
+    req: &ExecutionRequest,
+    signature: Option<&RequestSignature>,
+) -> Result<(), HypervisorError> {
+    let validate = || -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !req.encrypted_elf.trim().is_empty(),
+            "encrypted_elf cannot be empty"
+        );
+        anyhow::ensure!(
+            !req.public_key.trim().is_empty(),
+            "public key cannot be empty"
+        );
+
+        Ok(())
+    };
+    validate().context(StatusCode::BAD_REQUEST)?;
+
+    let user_pk = crypto::pk_from_hex(&req.public_key)
+        .context("decode request pubkey")
+        .context(StatusCode::BAD_REQUEST)?;
+
+    auth::authenticate(state, &user_pk, signature)?;
+
+    Ok(())
+}
+
+/// Execute a request [`authorize_execution`] accepted
+pub(crate) async fn run_execution(
+    state: &HypervisorState,
+    req: ExecutionRequest,
+    signature: Option<RequestSignature>,
+    measurement: Measurement,
+) -> Result<ExecutionResponse, HypervisorError> {
+    let start_time = std::time::Instant::now();
+
+    let user_pk = crypto::pk_from_hex(&req.public_key)
+        .context("decode request pubkey")
+        .context(StatusCode::BAD_REQUEST)?;
+
+    let (session_sk, session_id) = state
+        .get_session_keypair(&user_pk, req.session_id)
+        .ok_or(anyhow!("session not found"))
+        .context(StatusCode::UNAUTHORIZED)?;
+
+    let cipher = crypto::create_encrypt_key(&session_sk, &user_pk, session_id)
+        .context("create encrypt key")
+        .context(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let open = |field: &str, hex: &str| -> Result<Vec<u8>, HypervisorError> {
+        let bytes = const_hex::decode(hex)
+            .with_context(|| format!("decode {field} hex"))
+            .context(StatusCode::BAD_REQUEST)?;
+
+        let decrypted = { req.version.open(&cipher, session_id, &bytes) }
+            .with_context(|| format!("decrypt {field}"))
+            .context(StatusCode::BAD_REQUEST)?;
+
+        Ok(decrypted)
+    };
+
+    let decrypted_elf = open("elf", &req.encrypted_elf)?;
+    utils::attest::measure_artifact(
+        measurement,
+        EventKind::RiscvProgram,
+        "riscv program",
+        &decrypted_elf,
+    )?;
+
+    let mut decrypted_arguments = vec![];
+    for a in &req.encrypted_arguments {
+        let arg = String::from_utf8(open("argument", a)?)
+            .context("argument isn't string")
+            .context(StatusCode::BAD_REQUEST)?;
+        decrypted_arguments.push(arg);
+    }
+
+    let decrypted_stdin = match &req.encrypted_stdin {
+        Some(stdin) => open("stdin", stdin)?,
+        None => vec![],
+    };
+
+    info!(
+        session_id = %session_id,
+        public_key = req.public_key,
+        "processing RISC-V execution request"
+    );
+
+    let executor = RiscvExecutor {
+        limits: state.config.riscv_limits.tighten(&req.limits),
+        output_limit: state.config.riscv_output_limit_bytes,
+    };
+    let input = GuestInput {
+        program: decrypted_elf,
+        arguments: decrypted_arguments,
+        stdin: decrypted_stdin,
+    };
+    let (executor_kind, runtime_version) = (executor.kind(), executor.runtime_version());
+    let (output, metrics) = executor.execute(input).await?;
+
+    let exit_code = output.exit_code;
+
+    info!(
+        session_id = %session_id,
+        public_key = req.public_key,
+        execution_time_ms = start_time.elapsed().as_millis(),
+        instructions_retired = metrics.instructions_retired,
+        peak_memory_bytes = metrics.peak_memory_bytes,
+        exit_code,
+        stdout_truncated = output.stdout_truncated,
+        stderr_truncated = output.stderr_truncated,
+        request_timestamp = signature.as_ref().map(|s| s.timestamp),
+        request_signature = signature.as_ref().map(|s| s.signature_hex()),
+        status = if exit_code == 0 { "success" } else { "failed" },
+        msg = "RISC-V execution completed"
+    );
+
+    let output_nonce = req.version.result_nonce(&output.stdout);
+    let encrypted_result = {
+        let encrypted = cipher
+            .encrypt(&output_nonce, output.stdout.as_ref())
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        const_hex::encode(encrypted)
+    };
+
+    let encrypted_stderr = match exit_code {
+        0 => None,
+        _ => Some(const_hex::encode(req.version.seal(
+            &cipher,
+            session_id,
+            &output.stderr,
+        )?)),
+    };
+
+    let mut resp = ExecutionResponse {
+        executor: executor_kind,
+        runtime_version: runtime_version.to_string(),
+        session_id,
+        result_nonce: const_hex::encode(output_nonce),
+        encrypted_result,
+        result_commitment: String::new(),
+        instructions_retired: metrics.instructions_retired,
+        peak_memory_bytes: metrics.peak_memory_bytes,
+        exit_code,
+        stdout_truncated: output.stdout_truncated,
+        stderr_truncated: output.stderr_truncated,
+        encrypted_stderr,
+    };
+    let result_commitment =
+        commitment::build_result_commitment(&user_pk, session_sk.verifying_key(), &req, &resp);
+    resp.result_commitment = const_hex::encode(result_commitment);
+
+    Ok(resp)
+}
+
+/// Interprets RV64IM executables, one instruction at a time
+pub struct RiscvExecutor {
+    limits: RiscvLimits,
+    output_limit: usize,
+}
+
+pub struct RiscvMetrics {
+    pub instructions_retired: u64,
+    /// Mapped pages of program, stack and heap
+    pub peak_memory_bytes: u64,
+}
+
+#[async_trait]
+impl Executor for RiscvExecutor {
+    type Metrics = RiscvMetrics;
+
+    fn kind(&self) -> ExecutorKind {
+        ExecutorKind::Riscv
+    }
+
+    fn runtime_version(&self) -> &'static str {
+        RISCV_RUNTIME_VERSION
+    }
+
+    async fn execute(
+        self,
+        input: GuestInput,
+    ) -> Result<(GuestOutput, RiscvMetrics), HypervisorError> {
+        struct CancelOnDrop(Arc<AtomicBool>);
+
+        impl Drop for CancelOnDrop {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        // A cancelled job drops this future, the guest stops at its next slice
+        let cancelled = CancelOnDrop(Arc::new(AtomicBool::new(false)));
+        let cancel_flag = cancelled.0.clone();
+        let deadline = Instant::now() + self.limits.wall_clock();
+
+        tokio::task::spawn_blocking(move || self.run(input, deadline, &cancel_flag))
+            .await
+            .context("join riscv interpreter")
+            .context(StatusCode::INTERNAL_SERVER_ERROR)?
+    }
+}
+
+impl RiscvExecutor {
+    /// Interpret `input` on the calling thread, checking `deadline` and
+    /// `cancelled` between slices of [`INSTRUCTION_SLICE`] instructions
+    fn run(
+        self,
+        input: GuestInput,
+        deadline: Instant,
+        cancelled: &AtomicBool,
+    ) -> Result<(GuestOutput, RiscvMetrics), HypervisorError> {
+        let argv: Vec<String> = { std::iter::once("riscv".to_string()) }
+            .chain(input.arguments)
+            .collect();
+
+        let vm = Vm::new(
+            &input.program,
+            &argv,
+            input.stdin,
+            &self.limits,
+            self.output_limit,
+        );
+        let mut vm = match vm {
+            Ok(vm) => vm,
+            Err(e) => match e.downcast_ref::<Trap>().and_then(Trap::limit) {
+                Some(limit) => return Err(HypervisorError::LimitExceeded(limit)),
+                None => {
+                    return Err(e
+                        .context("load riscv program")
+                        .context(StatusCode::BAD_REQUEST)
+                        .into())
+                }
+            },
+        };
+
+        let run_result = loop {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(anyhow!("riscv execution cancelled")
+                    .context(StatusCode::SERVICE_UNAVAILABLE)
+                    .into());
+            }
+            if Instant::now() >= deadline {
+                return Err(HypervisorError::LimitExceeded(ExecutionLimit::WallClock));
+            }
+
+            match vm.run(INSTRUCTION_SLICE) {
+                Ok(Some(exit_code)) => break Ok(exit_code),
+                Ok(None) => {}
+                Err(trap) => break Err(trap),
+            }
+        };
+
+        let exit_code = match run_result {
+            Ok(exit_code) => exit_code,
+            Err(trap) => match trap.limit() {
+                Some(limit) => return Err(HypervisorError::LimitExceeded(limit)),
+                None => {
+                    return Err(anyhow::Error::new(trap)
+                        .context(format!("execute riscv at pc {:#x}", vm.pc()))
+                        .context(StatusCode::BAD_REQUEST)
+                        .into())
+                }
+            },
+        };
+
+        let metrics = RiscvMetrics {
+            instructions_retired: vm.instructions_retired(),
+            peak_memory_bytes: vm.peak_memory_bytes(),
+        };
+        let output = GuestOutput {
+            exit_code,
+            stdout: vm.stdout.bytes,
+            stdout_truncated: vm.stdout.truncated,
+            stderr: vm.stderr.bytes,
+            stderr_truncated: vm.stderr.truncated,
+        };
+
+        Ok((output, metrics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aes_gcm_siv::Nonce;
+
+    use crate::{api::RouterRegister, error::ErrorResponse, session::SessionKeyPairs};
+
+    use super::*;
+
+    #[tokio::test]
+    #[test_log::test]
+    async fn test_api_execute_riscv() {
+        let elf = include_bytes!("./hello.elf");
+        let session_key_pairs = SessionKeyPairs::default();
+
+        let mut state = HypervisorState::default();
+        state.set_session_key_pairs(session_key_pairs.clone());
+
+        let server =
+            axum_test::TestServer::new(Router::new().register_api(api_register).with_state(state))
+                .unwrap();
+
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let user_pk = sk.verifying_key();
+
+        let (session_pk, session_id) = session_key_pairs.create(user_pk);
+        let cipher = crypto::create_encrypt_key(&sk, &session_pk, session_id).unwrap();
+
+        let version = EnvelopeVersion::Framed;
+        let seal =
+            |bytes: &[u8]| const_hex::encode(version.seal(&cipher, session_id, bytes).unwrap());
+
+        let mut req = ExecutionRequest {
+            version,
+            encrypted_elf: seal(elf),
+            encrypted_arguments: vec![seal(b"tress")],
+            encrypted_stdin: Some(seal(b"from stdin\n")),
+            public_key: crypto::pk_to_hex(user_pk),
+            session_id: Some(session_id),
+            limits: RequestedRiscvLimits::default(),
+        };
+        let response = server.post("/test/execute/riscv").json(&req).await;
+
+        response.assert_status_ok();
+
+        let result: ExecutionResponse = response.json();
+        let result_nonce = *Nonce::from_slice(&const_hex::decode(&result.result_nonce).unwrap());
+        let result_commitment =
+            commitment::build_result_commitment(user_pk, &session_pk, &req, &result);
+        assert_eq!(result.executor, ExecutorKind::Riscv);
+        assert_eq!(result.exit_code, 0);
+        assert!(result.instructions_retired > 0);
+        assert!(result.peak_memory_bytes > 0);
+        assert_eq!(
+            result_commitment.as_slice(),
+            &const_hex::decode(&result.result_commitment).unwrap(),
+            "invalid commitment"
+        );
+
+        let output = cipher
+            .decrypt(
+                &result_nonce,
+                { const_hex::decode(&result.encrypted_result).unwrap() }.as_slice(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Hello tress\nfrom stdin\n"
+        );
+
+        // The count is deterministic, one short of it stops the program
+        let retired = result.instructions_retired;
+        let again: ExecutionResponse = server.post("/test/execute/riscv").json(&req).await.json();
+        assert_eq!(again.instructions_retired, retired);
+
+        req.limits.instructions = Some(retired - 1);
+        let response = server.post("/test/execute/riscv").json(&req).await;
+
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        let error: ErrorResponse = response.json();
+        assert_eq!(error.limit, Some(ExecutionLimit::Instructions));
+    }
+
+    #[tokio::test]
+    async fn test_wall_clock_limit() {
+        let executor = RiscvExecutor {
+            limits: RiscvLimits {
+                instructions: u64::MAX,
+                wall_clock_ms: 50,
+                ..Default::default()
+            },
+            output_limit: 1024,
+        };
+
+        // `jal x0, 0` spins until the deadline
+        let input = GuestInput {
+            program: elf::executable(&[0x0000_006f]),
+            arguments: vec![],
+            stdin: vec![],
+        };
+
+        let result = executor.execute(input).await;
+        assert!(matches!(
+            result,
+            Err(HypervisorError::LimitExceeded(ExecutionLimit::WallClock))
+        ));
+    }
+}
@@ -0,0 +1,701 @@
+//! RV64IM interpreter
+//!
+//! One hart in user mode, without compressed instructions, floats or
+//! atomics. Every retired instruction counts towards the limit, including
+//! the `ecall` that exits, so a program retires the same count on every run
+//! with the same inputs.
+//!
+//! Programs talk to the host with `ecall`, the Linux numbers and registers:
+//! `a7` holds the call, `a0`..`a2` the arguments and `a0` the result.
+//!
+//! | `a7` | call                        | result                             |
+//! |------|-----------------------------|------------------------------------|
+//! | 63   | `read(0, buf, len)`         | bytes read from stdin, 0 at its end |
+//! | 64   | `write(1 or 2, buf, len)`   | `len`, past the output cap dropped |
+//! | 93   | `exit(code)`                | stops the program                  |
+//! | 94   | `exit_group(code)`          | same as `exit`                     |
+//! | 214  | `brk(addr)`                 | the program break after the call   |
+//!
+//! Other calls return `-ENOSYS`, other descriptors `-EBADF`. The program
+//! starts at its entry with the stack of a Linux process: `argc`, the argv
+//! pointers, a null, an empty environment and auxiliary vector. `a0` and
+//! `a1` hold `argc` and `argv` as well.
+
+use std::collections::{hash_map::Entry, HashMap};
+
+use anyhow::ensure;
+
+use super::{elf, limits::RiscvLimits};
+use crate::api::execute::ExecutionLimit;
+
+pub const PAGE_SIZE: u64 = 4096;
+
+/// Stack grows down from here, programs load below its reserved range
+pub const STACK_TOP: u64 = 0x8000_0000;
+pub const STACK_SIZE: u64 = 8 * 1024 * 1024;
+const STACK_BOTTOM: u64 = STACK_TOP - STACK_SIZE;
+
+const SYS_READ: u64 = 63;
+const SYS_WRITE: u64 = 64;
+const SYS_EXIT: u64 = 93;
+const SYS_EXIT_GROUP: u64 = 94;
+const SYS_BRK: u64 = 214;
+
+const EBADF: i64 = 9;
+const EFAULT: i64 = 14;
+const ENOSYS: i64 = 38;
+
+const SP: usize = 2;
+const A0: usize = 10;
+const A1: usize = 11;
+const A2: usize = 12;
+const A7: usize = 17;
+
+/// Why a program stopped before exiting
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    #[error("instruction limit of {0} exceeded")]
+    InstructionLimit(u64),
+
+    #[error("memory limit of {0} bytes exceeded")]
+    MemoryLimit(u64),
+
+    #[error("access to unmapped address {0:#x}")]
+    Segfault(u64),
+
+    #[error("illegal instruction {0:#010x}")]
+    IllegalInstruction(u32),
+
+    #[error("jump to misaligned address {0:#x}")]
+    MisalignedPc(u64),
+
+    #[error("breakpoint")]
+    Breakpoint,
+}
+
+impl Trap {
+    /// Limit behind the trap, if any
+    pub fn limit(&self) -> Option<ExecutionLimit> {
+        match self {
+            Trap::InstructionLimit(_) => Some(ExecutionLimit::Instructions),
+            Trap::MemoryLimit(_) => Some(ExecutionLimit::Memory),
+            _ => None,
+        }
+    }
+}
+
+/// Output of the program, kept up to a limit
+#[derive(Debug, Default)]
+pub struct Output {
+    pub bytes: Vec<u8>,
+    pub truncated: bool,
+}
+
+pub struct Vm {
+    regs: [u64; 32],
+    pc: u64,
+    memory: Memory,
+    stdin: Vec<u8>,
+    stdin_read: usize,
+    pub stdout: Output,
+    pub stderr: Output,
+    output_limit: usize,
+    retired: u64,
+    instruction_limit: u64,
+    exit_code: Option<i32>,
+}
+
+impl Vm {
+    /// Load `program` with `argv`, ready to run from its entry
+    pub fn new(
+        program: &[u8],
+        argv: &[String],
+        stdin: Vec<u8>,
+        limits: &RiscvLimits,
+        output_limit: usize,
+    ) -> anyhow::Result<Self> {
+        let elf = elf::parse(program, STACK_BOTTOM)?;
+
+        let args_size: u64 = { argv.iter() }.map(|a| a.len() as u64 + 9).sum();
+        ensure!(
+            args_size < STACK_SIZE / 2,
+            "arguments don't fit on the stack"
+        );
+
+        // The break starts past the last segment, rounded up to a page
+        let program_end = { elf.segments.iter() }.map(elf::Segment::end).max();
+        let heap_start = program_end.unwrap_or_default().next_multiple_of(PAGE_SIZE);
+
+        let mut vm = Vm {
+            regs: [0; 32],
+            pc: elf.entry,
+            memory: Memory {
+                pages: HashMap::new(),
+                max_pages: limits.memory_bytes / PAGE_SIZE,
+                memory_bytes: limits.memory_bytes,
+                heap_start,
+                brk: heap_start,
+            },
+            stdin,
+            stdin_read: 0,
+            stdout: Output::default(),
+            stderr: Output::default(),
+            output_limit,
+            retired: 0,
+            instruction_limit: limits.instructions,
+            exit_code: None,
+        };
+
+        for segment in &elf.segments {
+            vm.memory.map(segment.vaddr, segment.mem_size)?;
+            vm.memory.write(segment.vaddr, segment.data)?;
+        }
+        vm.push_args(argv)?;
+
+        Ok(vm)
+    }
+
+    /// Run at most `budget` more instructions, the exit code once the
+    /// program exited
+    pub fn run(&mut self, budget: u64) -> Result<Option<i32>, Trap> {
+        for _ in 0..budget {
+            if self.exit_code.is_some() {
+                break;
+            }
+            if self.retired == self.instruction_limit {
+                return Err(Trap::InstructionLimit(self.instruction_limit));
+            }
+
+            self.step()?;
+            self.retired += 1;
+        }
+
+        Ok(self.exit_code)
+    }
+
+    pub fn pc(&self) -> u64 {
+        self.pc
+    }
+
+    pub fn instructions_retired(&self) -> u64 {
+        self.retired
+    }
+
+    /// Pages are never unmapped, the mapped memory is its peak
+    pub fn peak_memory_bytes(&self) -> u64 {
+        self.memory.pages.len() as u64 * PAGE_SIZE
+    }
+
+    fn push_args(&mut self, argv: &[String]) -> Result<(), Trap> {
+        let mut sp = STACK_TOP;
+        let mut pointers = vec![];
+        for arg in argv {
+            sp -= arg.len() as u64 + 1;
+            self.memory.write(sp, arg.as_bytes())?;
+            self.memory.write(sp + arg.len() as u64, &[0])?;
+            pointers.push(sp);
+        }
+
+        // argc, argv..., null, envp null, auxv AT_NULL
+        let mut words = vec![argv.len() as u64];
+        words.extend(pointers);
+        words.extend([0; 4]);
+
+        sp = (sp - 8 * words.len() as u64) & !15;
+        for (i, word) in words.iter().enumerate() {
+            self.memory.write(sp + 8 * i as u64, &word.to_le_bytes())?;
+        }
+
+        self.regs[SP] = sp;
+        self.regs[A0] = argv.len() as u64;
+        self.regs[A1] = sp + 8;
+
+        Ok(())
+    }
+
+    fn step(&mut self) -> Result<(), Trap> {
+        let pc = self.pc;
+        if !pc.is_multiple_of(4) {
+            return Err(Trap::MisalignedPc(pc));
+        }
+
+        let word = self.memory.read_u32(pc)?;
+        let opcode = word & 0x7f;
+        let rd = ((word >> 7) & 0x1f) as usize;
+        let funct3 = (word >> 12) & 0x7;
+        let funct7 = word >> 25;
+        let x1 = self.regs[((word >> 15) & 0x1f) as usize];
+        let x2 = self.regs[((word >> 20) & 0x1f) as usize];
+        let illegal = Trap::IllegalInstruction(word);
+
+        let mut next_pc = pc.wrapping_add(4);
+        match opcode {
+            // LUI
+            0x37 => self.regs[rd] = imm_u(word),
+            // AUIPC
+            0x17 => self.regs[rd] = pc.wrapping_add(imm_u(word)),
+            // JAL
+            0x6f => {
+                self.regs[rd] = next_pc;
+                next_pc = pc.wrapping_add(imm_j(word));
+            }
+            // JALR
+            0x67 if funct3 == 0 => {
+                let target = x1.wrapping_add(imm_i(word)) & !1;
+                self.regs[rd] = next_pc;
+                next_pc = target;
+            }
+            // BEQ, BNE, BLT, BGE, BLTU, BGEU
+            0x63 => {
+                let taken = match funct3 {
+                    0 => x1 == x2,
+                    1 => x1 != x2,
+                    4 => (x1 as i64) < (x2 as i64),
+                    5 => (x1 as i64) >= (x2 as i64),
+                    6 => x1 < x2,
+                    7 => x1 >= x2,
+                    _ => return Err(illegal),
+                };
+                if taken {
+                    next_pc = pc.wrapping_add(imm_b(word));
+                }
+            }
+            // LB, LH, LW, LD, LBU, LHU, LWU
+            0x03 => {
+                let addr = x1.wrapping_add(imm_i(word));
+                self.regs[rd] = match funct3 {
+                    0 => self.memory.read_n::<1>(addr)?[0] as i8 as u64,
+                    1 => i16::from_le_bytes(self.memory.read_n(addr)?) as u64,
+                    2 => i32::from_le_bytes(self.memory.read_n(addr)?) as u64,
+                    3 => u64::from_le_bytes(self.memory.read_n(addr)?),
+                    4 => self.memory.read_n::<1>(addr)?[0] as u64,
+                    5 => u16::from_le_bytes(self.memory.read_n(addr)?) as u64,
+                    6 => u32::from_le_bytes(self.memory.read_n(addr)?) as u64,
+                    _ => return Err(illegal),
+                };
+            }
+            // SB, SH, SW, SD
+            0x23 => {
+                let addr = x1.wrapping_add(imm_s(word));
+                let bytes = x2.to_le_bytes();
+                match funct3 {
+                    0..=3 => self.memory.write(addr, &bytes[..1 << funct3])?,
+                    _ => return Err(illegal),
+                }
+            }
+            // ADDI, SLTI, SLTIU, XORI, ORI, ANDI, SLLI, SRLI, SRAI
+            0x13 => {
+                let imm = imm_i(word);
+                let shamt = (word >> 20) & 0x3f;
+                self.regs[rd] = match (funct3, word >> 26) {
+                    (0, _) => x1.wrapping_add(imm),
+                    (2, _) => ((x1 as i64) < (imm as i64)) as u64,
+                    (3, _) => (x1 < imm) as u64,
+                    (4, _) => x1 ^ imm,
+                    (6, _) => x1 | imm,
+                    (7, _) => x1 & imm,
+                    (1, 0x00) => x1 << shamt,
+                    (5, 0x00) => x1 >> shamt,
+                    (5, 0x10) => ((x1 as i64) >> shamt) as u64,
+                    _ => return Err(illegal),
+                };
+            }
+            // ADDIW, SLLIW, SRLIW, SRAIW
+            0x1b => {
+                let x1 = x1 as u32;
+                let shamt = (word >> 20) & 0x1f;
+                self.regs[rd] = match (funct3, funct7) {
+                    (0, _) => sext(x1.wrapping_add(imm_i(word) as u32)),
+                    (1, 0x00) => sext(x1 << shamt),
+                    (5, 0x00) => sext(x1 >> shamt),
+                    (5, 0x20) => sext(((x1 as i32) >> shamt) as u32),
+                    _ => return Err(illegal),
+                };
+            }
+            0x33 => self.regs[rd] = op(funct3, funct7, x1, x2).ok_or(illegal)?,
+            0x3b => self.regs[rd] = op_32(funct3, funct7, x1 as u32, x2 as u32).ok_or(illegal)?,
+            // FENCE, FENCE.I, a single hart has nothing to order
+            0x0f => {}
+            // ECALL
+            0x73 if word == 0x0000_0073 => self.syscall()?,
+            // EBREAK
+            0x73 if word == 0x0010_0073 => return Err(Trap::Breakpoint),
+            _ => return Err(illegal),
+        }
+
+        self.regs[0] = 0;
+        self.pc = next_pc;
+
+        Ok(())
+    }
+
+    fn syscall(&mut self) -> Result<(), Trap> {
+        let [a0, a1, a2] = [self.regs[A0], self.regs[A1], self.regs[A2]];
+
+        let result = match self.regs[A7] {
+            SYS_READ if a0 == 0 => {
+                let unread = &self.stdin[self.stdin_read..];
+                let len = unread.len().min(a2 as usize);
+                match self.memory.write(a1, &unread[..len]) {
+                    Ok(()) => {
+                        self.stdin_read += len;
+                        len as i64
+                    }
+                    Err(Trap::Segfault(_)) => -EFAULT,
+                    Err(e) => return Err(e),
+                }
+            }
+            SYS_WRITE if a0 == 1 || a0 == 2 => {
+                let output = if a0 == 1 { &self.stdout } else { &self.stderr };
+                let room = self.output_limit.saturating_sub(output.bytes.len());
+                let len = room.min(a2 as usize);
+
+                let mut bytes = vec![0; len];
+                match self.memory.read(a1, &mut bytes) {
+                    Ok(()) => {
+                        let output = if a0 == 1 {
+                            &mut self.stdout
+                        } else {
+                            &mut self.stderr
+                        };
+                        output.bytes.extend(bytes);
+                        output.truncated |= len < a2 as usize;
+                        a2 as i64
+                    }
+                    Err(Trap::Segfault(_)) => -EFAULT,
+                    Err(e) => return Err(e),
+                }
+            }
+            SYS_READ | SYS_WRITE => -EBADF,
+            SYS_EXIT | SYS_EXIT_GROUP => {
+                self.exit_code = Some(a0 as i32);
+                return Ok(());
+            }
+            SYS_BRK => {
+                if (self.memory.heap_start..=STACK_BOTTOM).contains(&a0) {
+                    self.memory.set_brk(a0);
+                }
+                self.memory.brk as i64
+            }
+            _ => -ENOSYS,
+        };
+
+        self.regs[A0] = result as u64;
+
+        Ok(())
+    }
+}
+
+/// OP, the base integer and the M extension register operations
+fn op(funct3: u32, funct7: u32, x1: u64, x2: u64) -> Option<u64> {
+    let shamt = x2 & 0x3f;
+    let (s1, s2) = (x1 as i64, x2 as i64);
+
+    let value = match (funct7, funct3) {
+        (0x00, 0) => x1.wrapping_add(x2),
+        (0x20, 0) => x1.wrapping_sub(x2),
+        (0x00, 1) => x1 << shamt,
+        (0x00, 2) => (s1 < s2) as u64,
+        (0x00, 3) => (x1 < x2) as u64,
+        (0x00, 4) => x1 ^ x2,
+        (0x00, 5) => x1 >> shamt,
+        (0x20, 5) => (s1 >> shamt) as u64,
+        (0x00, 6) => x1 | x2,
+        (0x00, 7) => x1 & x2,
+        (0x01, 0) => x1.wrapping_mul(x2),
+        (0x01, 1) => ((s1 as i128 * s2 as i128) >> 64) as u64,
+        (0x01, 2) => ((s1 as i128 * x2 as i128) >> 64) as u64,
+        (0x01, 3) => ((x1 as u128 * x2 as u128) >> 64) as u64,
+        // Division by zero and overflow don't trap on RISC-V
+        (0x01, 4) if x2 == 0 => u64::MAX,
+        (0x01, 4) => s1.wrapping_div(s2) as u64,
+        (0x01, 5) => x1.checked_div(x2).unwrap_or(u64::MAX),
+        (0x01, 6) if x2 == 0 => x1,
+        (0x01, 6) => s1.wrapping_rem(s2) as u64,
+        (0x01, 7) => x1.checked_rem(x2).unwrap_or(x1),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// OP-32, [`op`] on the low words with sign extended results
+fn op_32(funct3: u32, funct7: u32, x1: u32, x2: u32) -> Option<u64> {
+    let shamt = x2 & 0x1f;
+    let (s1, s2) = (x1 as i32, x2 as i32);
+
+    let value = match (funct7, funct3) {
+        (0x00, 0) => x1.wrapping_add(x2),
+        (0x20, 0) => x1.wrapping_sub(x2),
+        (0x00, 1) => x1 << shamt,
+        (0x00, 5) => x1 >> shamt,
+        (0x20, 5) => (s1 >> shamt) as u32,
+        (0x01, 0) => x1.wrapping_mul(x2),
+        (0x01, 4) if x2 == 0 => u32::MAX,
+        (0x01, 4) => s1.wrapping_div(s2) as u32,
+        (0x01, 5) => x1.checked_div(x2).unwrap_or(u32::MAX),
+        (0x01, 6) if x2 == 0 => x1,
+        (0x01, 6) => s1.wrapping_rem(s2) as u32,
+        (0x01, 7) => x1.checked_rem(x2).unwrap_or(x1),
+        _ => return None,
+    };
+
+    Some(sext(value))
+}
+
+fn sext(value: u32) -> u64 {
+    value as i32 as i64 as u64
+}
+
+fn imm_i(word: u32) -> u64 {
+    ((word as i32) >> 20) as i64 as u64
+}
+
+fn imm_s(word: u32) -> u64 {
+    let imm = ((word as i32) >> 25 << 5) as u32 | ((word >> 7) & 0x1f);
+    sext(imm)
+}
+
+fn imm_b(word: u32) -> u64 {
+    let imm = ((word as i32) >> 31 << 12) as u32
+        | ((word >> 7) & 0x1) << 11
+        | ((word >> 25) & 0x3f) << 5
+        | ((word >> 8) & 0xf) << 1;
+    sext(imm)
+}
+
+fn imm_u(word: u32) -> u64 {
+    sext(word & 0xffff_f000)
+}
+
+fn imm_j(word: u32) -> u64 {
+    let imm = ((word as i32) >> 31 << 20) as u32
+        | ((word >> 12) & 0xff) << 12
+        | ((word >> 20) & 0x1) << 11
+        | ((word >> 21) & 0x3ff) << 1;
+    sext(imm)
+}
+
+/// Sparse guest memory of zeroed pages
+///
+/// Pages of the program are mapped when loading, the stack and the heap
+/// below the break map theirs on first touch. Anything else is unmapped,
+/// lowering the break unmaps the heap pages left above it.
+struct Memory {
+    pages: HashMap<u64, Box<[u8]>>,
+    max_pages: u64,
+    memory_bytes: u64,
+    heap_start: u64,
+    brk: u64,
+}
+
+impl Memory {
+    fn set_brk(&mut self, brk: u64) {
+        for page in brk.div_ceil(PAGE_SIZE)..self.brk.div_ceil(PAGE_SIZE) {
+            self.pages.remove(&page);
+        }
+        self.brk = brk;
+    }
+
+    /// Map the pages covering `len` bytes from `addr`
+    fn map(&mut self, addr: u64, len: u64) -> Result<(), Trap> {
+        for page in addr / PAGE_SIZE..(addr + len).div_ceil(PAGE_SIZE) {
+            self.page(page * PAGE_SIZE, true)?;
+        }
+
+        Ok(())
+    }
+
+    fn page(&mut self, addr: u64, map: bool) -> Result<&mut [u8], Trap> {
+        let map = map
+            || (self.heap_start..self.brk).contains(&addr)
+            || (STACK_BOTTOM..STACK_TOP).contains(&addr);
+        let full = self.pages.len() as u64 >= self.max_pages;
+
+        match self.pages.entry(addr / PAGE_SIZE) {
+            Entry::Occupied(page) => Ok(page.into_mut()),
+            Entry::Vacant(_) if !map => Err(Trap::Segfault(addr)),
+            Entry::Vacant(_) if full => Err(Trap::MemoryLimit(self.memory_bytes)),
+            Entry::Vacant(page) => Ok(page.insert(vec![0; PAGE_SIZE as usize].into())),
+        }
+    }
+
+    fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), Trap> {
+        let mut done = 0;
+        while done < buf.len() {
+            let at = addr.wrapping_add(done as u64);
+            let offset = (at % PAGE_SIZE) as usize;
+            let len = (PAGE_SIZE as usize - offset).min(buf.len() - done);
+
+            let page = self.page(at, false)?;
+            buf[done..done + len].copy_from_slice(&page[offset..offset + len]);
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, addr: u64, bytes: &[u8]) -> Result<(), Trap> {
+        let mut done = 0;
+        while done < bytes.len() {
+            let at = addr.wrapping_add(done as u64);
+            let offset = (at % PAGE_SIZE) as usize;
+            let len = (PAGE_SIZE as usize - offset).min(bytes.len() - done);
+
+            let page = self.page(at, false)?;
+            page[offset..offset + len].copy_from_slice(&bytes[done..done + len]);
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    fn read_n<const N: usize>(&mut self, addr: u64) -> Result<[u8; N], Trap> {
+        let mut bytes = [0; N];
+        self.read(addr, &mut bytes)?;
+
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self, addr: u64) -> Result<u32, Trap> {
+        self.read_n(addr).map(u32::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECALL: u32 = 0x0000_0073;
+
+    /// `addi rd, rs1, imm`
+    fn addi(rd: u32, rs1: u32, imm: i32) -> u32 {
+        ((imm as u32) << 20) | (rs1 << 15) | (rd << 7) | 0x13
+    }
+
+    fn run(
+        code: &[u32],
+        argv: &[&str],
+        stdin: &[u8],
+        limits: RiscvLimits,
+    ) -> (Vm, Result<Option<i32>, Trap>) {
+        let argv: Vec<String> = { argv.iter() }.map(|a| a.to_string()).collect();
+        let mut vm = Vm::new(&elf::executable(code), &argv, stdin.to_vec(), &limits, 16).unwrap();
+        let result = vm.run(u64::MAX);
+
+        (vm, result)
+    }
+
+    #[test]
+    fn test_exit_and_count() {
+        // exit(argc + 40)
+        let code = [addi(10, 10, 40), addi(17, 0, 93), ECALL];
+        let (vm, result) = run(&code, &["riscv", "a"], b"", RiscvLimits::default());
+
+        assert_eq!(result, Ok(Some(42)));
+        assert_eq!(vm.instructions_retired(), 3);
+    }
+
+    #[test]
+    fn test_echo_stdin() {
+        // read(0, sp - 64, 64), then write(1, sp - 64, n) and exit(0)
+        let code = [
+            addi(11, 2, -64),
+            addi(12, 0, 64),
+            addi(10, 0, 0),
+            addi(17, 0, 63),
+            ECALL,
+            addi(12, 10, 0),
+            addi(10, 0, 1),
+            addi(17, 0, 64),
+            ECALL,
+            addi(10, 0, 0),
+            addi(17, 0, 93),
+            ECALL,
+        ];
+        let (vm, result) = run(
+            &code,
+            &[],
+            b"echo this past the cap",
+            RiscvLimits::default(),
+        );
+
+        assert_eq!(result, Ok(Some(0)));
+        assert_eq!(vm.stdout.bytes, b"echo this past t");
+        assert!(vm.stdout.truncated);
+    }
+
+    #[test]
+    fn test_instruction_limit() {
+        // jal x0, 0
+        let limits = RiscvLimits {
+            instructions: 1_000,
+            ..Default::default()
+        };
+        let (vm, result) = run(&[0x0000_006f], &[], b"", limits);
+
+        assert_eq!(result, Err(Trap::InstructionLimit(1_000)));
+        assert_eq!(vm.instructions_retired(), 1_000);
+    }
+
+    #[test]
+    fn test_traps() {
+        let (_, result) = run(&[0xffff_ffff], &[], b"", RiscvLimits::default());
+        assert_eq!(result, Err(Trap::IllegalInstruction(0xffff_ffff)));
+
+        // sd x0, 0(x0)
+        let (_, result) = run(&[0x0000_3023], &[], b"", RiscvLimits::default());
+        assert_eq!(result, Err(Trap::Segfault(0)));
+
+        // Stack pages count towards memory
+        let limits = RiscvLimits {
+            memory_bytes: PAGE_SIZE,
+            ..Default::default()
+        };
+        let argv = vec!["riscv".to_string()];
+        let e = Vm::new(&elf::executable(&[ECALL]), &argv, vec![], &limits, 0)
+            .err()
+            .unwrap();
+        assert_eq!(e.downcast_ref(), Some(&Trap::MemoryLimit(PAGE_SIZE)));
+    }
+
+    #[test]
+    fn test_brk_shrink_unmaps() {
+        // Grow the break by 16, store below it, shrink it back and load
+        let code = [
+            addi(10, 0, 0),
+            addi(17, 0, 214),
+            ECALL,
+            addi(9, 10, 0),
+            addi(10, 9, 16),
+            ECALL,
+            // sd x0, 0(s1)
+            0x0004_b023,
+            addi(10, 9, 0),
+            ECALL,
+            // ld t0, 0(s1)
+            0x0004_b283,
+        ];
+        let (vm, result) = run(&code, &[], b"", RiscvLimits::default());
+
+        assert_eq!(vm.memory.brk, vm.memory.heap_start);
+        assert_eq!(result, Err(Trap::Segfault(vm.memory.heap_start)));
+    }
+
+    #[test]
+    fn test_m_extension() {
+        assert_eq!(op(0, 0x01, -3i64 as u64, 5), Some(-15i64 as u64));
+        assert_eq!(op(1, 0x01, -1i64 as u64, -1i64 as u64), Some(0));
+        assert_eq!(op(3, 0x01, u64::MAX, 2), Some(1));
+        assert_eq!(op(4, 0x01, 7, 0), Some(u64::MAX));
+        assert_eq!(
+            op(4, 0x01, i64::MIN as u64, -1i64 as u64),
+            Some(i64::MIN as u64)
+        );
+        assert_eq!(op(6, 0x01, -7i64 as u64, 2), Some(-1i64 as u64));
+        assert_eq!(op(7, 0x01, 7, 0), Some(7));
+        assert_eq!(op_32(0, 0x01, 0x8000_0000, 2), Some(0));
+        assert_eq!(op_32(5, 0x01, 0x8000_0000, 1), Some(0xffff_ffff_8000_0000));
+        assert_eq!(op(0, 0x02, 1, 1), None);
+    }
+}
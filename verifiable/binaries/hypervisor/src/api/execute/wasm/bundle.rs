@@ -0,0 +1,435 @@
+//! Files of an execution, exchanged as ustar archives
+//!
+//! Only regular files and directories are read or written. Links, devices
+//! and pax or GNU extension entries are refused, as are paths leaving the
+//! archive root, so an unpacked bundle never reaches outside its directory.
+//!
+//! Unpacked bundles are plaintext, work dirs default to a tmpfs so they stay
+//! in memory.
+
+use std::{
+    ffi::CString,
+    io,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{DirBuilderExt, MetadataExt},
+    },
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+
+const BLOCK_LEN: usize = 512;
+
+/// Parent of work dirs unless configured, a tmpfs on Linux
+pub const DEFAULT_WASM_WORK_DIR: &str = "/dev/shm";
+
+/// How often guest writes are measured against the files limit
+const FILES_LIMIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Guest paths of the preopened directories
+pub const INPUT_DIR: &str = "/input";
+pub const SCRATCH_DIR: &str = "/scratch";
+pub const OUTPUT_DIR: &str = "/output";
+
+/// Host directory backing the preopens of one execution, removed on drop
+pub struct WorkDir {
+    root: PathBuf,
+}
+
+impl WorkDir {
+    pub fn create(parent: &Path) -> anyhow::Result<Self> {
+        let root = parent.join(format!("wasm-{}", uuid::Uuid::now_v7()));
+
+        let mut builder = std::fs::DirBuilder::new();
+        builder.mode(0o700);
+        builder
+            .create(&root)
+            .with_context(|| format!("create {}", root.display()))?;
+
+        let work_dir = WorkDir { root };
+        for dir in [INPUT_DIR, SCRATCH_DIR, OUTPUT_DIR] {
+            builder.create(work_dir.host_path(dir))?;
+        }
+
+        Ok(work_dir)
+    }
+
+    /// Host directory behind a guest preopen
+    pub fn host_path(&self, guest_dir: &str) -> PathBuf {
+        self.root.join(guest_dir.trim_start_matches('/'))
+    }
+
+    /// Bytes the guest holds under `/scratch` and `/output`
+    pub fn written_bytes(&self) -> io::Result<u64> {
+        { [SCRATCH_DIR, OUTPUT_DIR].iter() }
+            .map(|dir| disk_usage(&self.host_path(dir)))
+            .sum()
+    }
+
+    /// Resolves once the guest holds more than `limit` bytes under
+    /// `/scratch` and `/output`
+    ///
+    /// Writes aren't intercepted, usage is measured every
+    /// [`FILES_LIMIT_INTERVAL`]. What a guest writes in between is only
+    /// bounded by the free space of the filesystem, see
+    /// [`WorkDir::ensure_free_space`].
+    pub async fn files_limit_exceeded(&self, limit: usize) {
+        let mut interval = tokio::time::interval(FILES_LIMIT_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match self.written_bytes() {
+                Ok(written) if written > limit as u64 => return,
+                Ok(_) => {}
+                // Files may go away while measured, the next tick retries
+                Err(e) => tracing::debug!("measure {}: {e}", self.root.display()),
+            }
+        }
+    }
+
+    /// Fail unless the filesystem of the work dir has `limit` bytes free,
+    /// so a guest reaching the files limit runs out of its own space
+    /// rather than the space of the executions next to it
+    pub fn ensure_free_space(&self, limit: usize) -> anyhow::Result<()> {
+        let path = CString::new(self.root.as_os_str().as_bytes())?;
+
+        // SAFETY: `stat` is a plain output struct
+        let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(path.as_ptr(), &mut stat) } < 0 {
+            return Err(io::Error::last_os_error()).context("statfs work dir");
+        }
+
+        let free = (stat.f_bavail as u64).saturating_mul(stat.f_bsize as u64);
+        anyhow::ensure!(
+            free >= limit as u64,
+            "work dir has {free} bytes free, less than the {limit} byte files limit"
+        );
+
+        Ok(())
+    }
+}
+
+/// Allocated bytes under `dir`, every entry counting at least a block so
+/// piles of empty files run into the files limit too
+fn disk_usage(dir: &Path) -> io::Result<u64> {
+    let mut usage = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        usage += (metadata.blocks() * 512).max(BLOCK_LEN as u64);
+        if metadata.is_dir() {
+            usage += disk_usage(&entry.path())?;
+        }
+    }
+
+    Ok(usage)
+}
+
+/// Whether files under `path` are kept in memory, on a tmpfs or ramfs
+pub fn is_memory_backed(path: &Path) -> bool {
+    const TMPFS_MAGIC: u32 = 0x0102_1994;
+    const RAMFS_MAGIC: u32 = 0x8584_58f6;
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    // SAFETY: `stat` is a plain output struct
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } < 0 {
+        return false;
+    }
+
+    matches!(stat.f_type as u32, TMPFS_MAGIC | RAMFS_MAGIC)
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.root) {
+            tracing::warn!("remove {}: {e}", self.root.display());
+        }
+    }
+}
+
+/// Unpack `archive` into `dir`, refusing more than `limit` file bytes
+pub fn unpack(archive: &[u8], dir: &Path, limit: usize) -> anyhow::Result<()> {
+    let mut unpacked = 0usize;
+    let mut offset = 0;
+
+    while offset + BLOCK_LEN <= archive.len() {
+        let header = &archive[offset..offset + BLOCK_LEN];
+        offset += BLOCK_LEN;
+
+        // Two zero blocks end the archive, one is enough to stop
+        if header.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+        verify_checksum(header)?;
+
+        let path = entry_path(header)?;
+        let size = parse_octal(&header[124..136]).context("entry size")?;
+        let data = archive
+            .get(offset..offset + size)
+            .ok_or(anyhow!("{} truncated", path.display()))?;
+        offset += size.div_ceil(BLOCK_LEN) * BLOCK_LEN;
+
+        match header[156] {
+            b'0' | 0 => {
+                unpacked += size;
+                anyhow::ensure!(unpacked <= limit, "bundle larger than {limit} bytes");
+
+                let target = dir.join(&path);
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&target, data)
+                    .with_context(|| format!("write {}", path.display()))?;
+            }
+            b'5' => std::fs::create_dir_all(dir.join(&path))?,
+            kind => {
+                return Err(anyhow!(
+                    "{} has unsupported entry type {:?}",
+                    path.display(),
+                    kind as char
+                ))
+            }
+        }
+    }
+
+    Err(anyhow!("archive has no end marker"))
+}
+
+/// Pack the regular files under `dir`, sorted by path, `None` when there
+/// are none
+pub fn pack(dir: &Path, limit: usize) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut files = Vec::new();
+    collect_files(dir, Path::new(""), &mut files)?;
+    if files.is_empty() {
+        return Ok(None);
+    }
+    files.sort();
+
+    let mut archive = Vec::new();
+    let mut packed = 0usize;
+
+    for path in files {
+        // Sparse files look small on disk, their length is what gets read
+        let file = dir.join(&path);
+        let len = std::fs::metadata(&file)?.len();
+        anyhow::ensure!(
+            len <= (limit - packed) as u64,
+            "output files larger than {limit} bytes"
+        );
+
+        let data = std::fs::read(&file)?;
+        packed += data.len();
+        anyhow::ensure!(packed <= limit, "output files larger than {limit} bytes");
+
+        archive.extend(file_header(&path, data.len())?);
+        archive.extend(&data);
+        archive.resize(archive.len().div_ceil(BLOCK_LEN) * BLOCK_LEN, 0);
+    }
+    archive.extend([0u8; 2 * BLOCK_LEN]);
+
+    Ok(Some(archive))
+}
+
+fn collect_files(root: &Path, rel: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in std::fs::read_dir(root.join(rel))? {
+        let entry = entry?;
+        let path = rel.join(entry.file_name());
+
+        // Symlinks left by the guest are skipped, not followed
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn entry_path(header: &[u8]) -> anyhow::Result<PathBuf> {
+    let field = |range: std::ops::Range<usize>| {
+        let bytes = &header[range];
+        let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        std::str::from_utf8(&bytes[..len]).context("entry path isn't utf-8")
+    };
+
+    let name = field(0..100)?;
+    // GNU archives keep other fields where POSIX ustar has the prefix
+    let path = match &header[257..263] {
+        b"ustar\0" => match field(345..500)? {
+            "" => PathBuf::from(name),
+            prefix => Path::new(prefix).join(name),
+        },
+        _ => PathBuf::from(name),
+    };
+
+    let contained =
+        { path.components() }.all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    anyhow::ensure!(
+        contained && path.components().next().is_some(),
+        "entry path {} leaves the bundle",
+        path.display()
+    );
+
+    Ok(path)
+}
+
+fn file_header(path: &Path, size: usize) -> anyhow::Result<[u8; BLOCK_LEN]> {
+    let path = path.to_str().context("output path isn't utf-8")?.as_bytes();
+
+    // Long paths are split at a slash into the ustar prefix and name
+    let (prefix, name) = match path.len() {
+        0..=100 => (&[][..], path),
+        _ => {
+            let split = { path.iter().enumerate() }
+                .filter(|(i, b)| **b == b'/' && *i <= 155 && path.len() - i - 1 <= 100)
+                .map(|(i, _)| i)
+                .next()
+                .ok_or(anyhow!(
+                    "output path {} too long",
+                    String::from_utf8_lossy(path)
+                ))?;
+
+            (&path[..split], &path[split + 1..])
+        }
+    };
+
+    let mut header = [0u8; BLOCK_LEN];
+    header[..name.len()].copy_from_slice(name);
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix);
+    set_checksum(&mut header);
+
+    Ok(header)
+}
+
+fn set_checksum(header: &mut [u8; BLOCK_LEN]) {
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+}
+
+fn verify_checksum(header: &[u8]) -> anyhow::Result<()> {
+    let expected = parse_octal(&header[148..156]).context("entry checksum")?;
+    let checksum: usize = { header.iter().enumerate() }
+        .map(|(i, b)| match i {
+            148..156 => b' ' as usize,
+            _ => *b as usize,
+        })
+        .sum();
+
+    anyhow::ensure!(checksum == expected, "bad tar header checksum");
+
+    Ok(())
+}
+
+fn parse_octal(field: &[u8]) -> anyhow::Result<usize> {
+    let digits = std::str::from_utf8(field)?.trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+
+    Ok(usize::from_str_radix(digits, 8)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_unpack() {
+        let work_dir = WorkDir::create(&std::env::temp_dir()).unwrap();
+        let output = work_dir.host_path(OUTPUT_DIR);
+
+        let long_dir = "d".repeat(80);
+        std::fs::create_dir_all(output.join(&long_dir)).unwrap();
+        std::fs::write(output.join("a.txt"), b"hello").unwrap();
+        std::fs::write(output.join(&long_dir).join("b".repeat(60)), [0u8; 700]).unwrap();
+
+        let archive = pack(&output, 1024).unwrap().unwrap();
+        assert!(pack(&output, 512).is_err());
+        assert!(pack(&work_dir.host_path(SCRATCH_DIR), 1024)
+            .unwrap()
+            .is_none());
+
+        let input = work_dir.host_path(INPUT_DIR);
+        unpack(&archive, &input, 1024).unwrap();
+        assert_eq!(std::fs::read(input.join("a.txt")).unwrap(), b"hello");
+        assert_eq!(
+            std::fs::read(input.join(&long_dir).join("b".repeat(60))).unwrap(),
+            [0u8; 700]
+        );
+
+        assert!(unpack(&archive, &input, 512).is_err());
+    }
+
+    #[test]
+    fn test_pack_checks_length_before_reading() {
+        let work_dir = WorkDir::create(&std::env::temp_dir()).unwrap();
+        let output = work_dir.host_path(OUTPUT_DIR);
+
+        let sparse = std::fs::File::create(output.join("sparse")).unwrap();
+        sparse.set_len(1 << 40).unwrap();
+
+        assert!(work_dir.written_bytes().unwrap() < 1 << 20);
+        assert!(pack(&output, 1024).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_files_limit() {
+        let work_dir = WorkDir::create(&std::env::temp_dir()).unwrap();
+        std::fs::write(work_dir.host_path(SCRATCH_DIR).join("a"), [1u8; 8192]).unwrap();
+
+        let exceeded = tokio::time::timeout(
+            Duration::from_millis(100),
+            work_dir.files_limit_exceeded(4096),
+        );
+        assert!(exceeded.await.is_ok());
+
+        let within = tokio::time::timeout(
+            Duration::from_millis(100),
+            work_dir.files_limit_exceeded(1 << 20),
+        );
+        assert!(within.await.is_err());
+    }
+
+    #[test]
+    fn test_free_space() {
+        let work_dir = WorkDir::create(&std::env::temp_dir()).unwrap();
+
+        assert!(work_dir.ensure_free_space(4096).is_ok());
+        assert!(work_dir.ensure_free_space(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_unpack_refuses_escapes() {
+        let work_dir = WorkDir::create(&std::env::temp_dir()).unwrap();
+
+        let mut archive = file_header(Path::new("../escape"), 0).unwrap().to_vec();
+        archive.extend([0u8; 2 * BLOCK_LEN]);
+        assert!(unpack(&archive, &work_dir.host_path(INPUT_DIR), 1024).is_err());
+
+        let mut header = file_header(Path::new("link"), 0).unwrap();
+        header[156] = b'2';
+        set_checksum(&mut header);
+        let mut archive = header.to_vec();
+        archive.extend([0u8; 2 * BLOCK_LEN]);
+        assert!(unpack(&archive, &work_dir.host_path(INPUT_DIR), 1024).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use wasmtime::{component::Component, Config, Engine};
+
+pub const DEFAULT_WASM_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// The engine shared by all executions, and its compiled components by
+/// blake3 of the decrypted wasm
+///
+/// Components are bounded by the size of their compiled code, the least
+/// recently used ones are evicted first. A capacity of 0 compiles every
+/// time.
+pub struct ComponentCache {
+    engine: Engine,
+    capacity_bytes: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    components: HashMap<[u8; 32], Entry>,
+    size_bytes: usize,
+    /// Bumped on every hit or insert, orders entries by last use
+    clock: u64,
+}
+
+struct Entry {
+    component: Component,
+    size_bytes: usize,
+    last_used: u64,
+}
+
+impl Default for ComponentCache {
+    fn default() -> Self {
+        ComponentCache::new(DEFAULT_WASM_CACHE_BYTES).expect("wasm engine")
+    }
+}
+
+impl ComponentCache {
+    pub fn new(capacity_bytes: u64) -> anyhow::Result<Self> {
+        let mut config = Config::new();
+        config.async_support(true);
+        config.consume_fuel(true);
+
+        Ok(ComponentCache {
+            engine: Engine::new(&config)?,
+            capacity_bytes: capacity_bytes.try_into().unwrap_or(usize::MAX),
+            entries: Mutex::default(),
+        })
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Compiled `wasm`, and whether it came from the cache
+    pub fn get_or_compile(&self, wasm: &[u8]) -> anyhow::Result<(Component, bool)> {
+        let hash = *blake3::hash(wasm).as_bytes();
+
+        if let Some(component) = self.lock().get(&hash) {
+            return Ok((component, true));
+        }
+
+        // Compiled unlocked, concurrent misses of one wasm compile it twice
+        let component = Component::from_binary(&self.engine, wasm)?;
+        let range = component.image_range();
+        let size_bytes = range.end as usize - range.start as usize;
+
+        if size_bytes <= self.capacity_bytes {
+            self.lock()
+                .insert(hash, component.clone(), size_bytes, self.capacity_bytes);
+        }
+
+        Ok((component, false))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Entries {
+    fn get(&mut self, hash: &[u8; 32]) -> Option<Component> {
+        self.clock += 1;
+
+        let entry = self.components.get_mut(hash)?;
+        entry.last_used = self.clock;
+
+        Some(entry.component.clone())
+    }
+
+    fn insert(&mut self, hash: [u8; 32], component: Component, size_bytes: usize, capacity: usize) {
+        self.clock += 1;
+
+        if let Some(replaced) = self.components.remove(&hash) {
+            self.size_bytes -= replaced.size_bytes;
+        }
+
+        while self.size_bytes + size_bytes > capacity {
+            let Some(oldest) = { self.components.iter() }
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(hash, _)| *hash)
+            else {
+                break;
+            };
+
+            if let Some(evicted) = self.components.remove(&oldest) {
+                self.size_bytes -= evicted.size_bytes;
+            }
+        }
+
+        self.size_bytes += size_bytes;
+        self.components.insert(
+            hash,
+            Entry {
+                component,
+                size_bytes,
+                last_used: self.clock,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_component_cache() {
+        let wasm = include_bytes!("./hello.wasm");
+
+        let cache = ComponentCache::default();
+        assert!(!cache.get_or_compile(wasm).unwrap().1);
+        assert!(cache.get_or_compile(wasm).unwrap().1);
+
+        let cache = ComponentCache::new(0).unwrap();
+        assert!(!cache.get_or_compile(wasm).unwrap().1);
+        assert!(!cache.get_or_compile(wasm).unwrap().1);
+    }
+
+    #[test]
+    fn test_component_cache_eviction() {
+        let cache = ComponentCache::default();
+        let (component, _) = cache
+            .get_or_compile(include_bytes!("./hello.wasm"))
+            .unwrap();
+
+        let mut entries = Entries::default();
+        entries.insert([1; 32], component.clone(), 10, 25);
+        entries.insert([2; 32], component.clone(), 10, 25);
+        assert!(entries.get(&[1; 32]).is_some());
+
+        // Least recently used goes first
+        entries.insert([3; 32], component, 10, 25);
+        assert!(entries.get(&[2; 32]).is_none());
+        assert!(entries.get(&[1; 32]).is_some());
+        assert!(entries.get(&[3; 32]).is_some());
+        assert_eq!(entries.size_bytes, 20);
+    }
+}
@@ -0,0 +1,327 @@
+//! Host side of the `x402:host` world, see `wit/host.wit`
+//!
+//! Every import is linked for every guest. Calls the request didn't grant
+//! fail with an error the guest can handle, they don't trap.
+
+use std::sync::{Arc, OnceLock};
+
+use anyhow::Context;
+use k256::ecdsa::{signature::Signer, Signature, SigningKey};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::SessionKv;
+
+wasmtime::component::bindgen!({
+    path: "wit/host.wit",
+    world: "agent",
+    imports: { default: async },
+});
+
+use x402::host::{crypto, http, kv};
+
+/// Prefix of every message signed for a guest, so its signatures can't pass
+/// for anything else the session key signs
+pub const GUEST_SIGNATURE_DOMAIN: &[u8] = b"x402:host/crypto";
+
+const MAX_HTTP_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Host imports a request grants its guest, none by default
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capabilities {
+    /// Origins `x402:host/http` may reach, each one of `wasm_http_allowlist`.
+    /// `host` stands for the default port of the scheme, `host:port` for
+    /// that port only
+    pub http: Vec<String>,
+    /// `x402:host/kv`, the store of the session
+    pub kv: bool,
+    /// `x402:host/crypto`, signing with the session key
+    pub sign: bool,
+}
+
+impl Capabilities {
+    /// Check every granted origin is a `host[:port]` the operator allows
+    pub fn validate(&self, allowlist: &[String]) -> anyhow::Result<()> {
+        for origin in &self.http {
+            anyhow::ensure!(
+                !origin.is_empty() && !origin.contains(['/', '@', '?', '#']),
+                "http grant {origin} isn't host[:port]"
+            );
+            anyhow::ensure!(
+                allowlist.iter().any(|h| h.eq_ignore_ascii_case(origin)),
+                "http to {origin} isn't allowed"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Committed form, empty when nothing is granted
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if self == &Capabilities::default() {
+            return vec![];
+        }
+
+        let mut bytes = vec![u8::from(self.kv), u8::from(self.sign)];
+        for origin in &self.http {
+            bytes.extend(origin.to_ascii_lowercase().as_bytes());
+            bytes.push(b'\n');
+        }
+
+        bytes
+    }
+}
+
+/// What the imports of one execution act on
+pub struct HostCtx {
+    pub capabilities: Capabilities,
+    pub session_id: Uuid,
+    pub session_sk: SigningKey,
+    pub kv: Arc<SessionKv>,
+    pub kv_limit_bytes: usize,
+    pub http: reqwest::Client,
+}
+
+impl HostCtx {
+    /// Client shared by guest requests, redirects would leave the granted
+    /// hosts
+    pub fn http_client() -> reqwest::Client {
+        static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+        let client = CLIENT.get_or_init(|| {
+            reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .expect("http client")
+        });
+
+        client.clone()
+    }
+
+    async fn try_fetch(&self, request: http::Request) -> anyhow::Result<http::Response> {
+        let url = reqwest::Url::parse(&request.url).context("invalid url")?;
+        anyhow::ensure!(
+            matches!(url.scheme(), "http" | "https"),
+            "unsupported scheme {}",
+            url.scheme()
+        );
+
+        // `port` is none on the default port of the scheme, which a bare
+        // host grants as well
+        let host = url.host_str().unwrap_or_default();
+        let origin = format!("{host}:{}", url.port_or_known_default().unwrap_or_default());
+        let granted = |grant: &String| {
+            grant.eq_ignore_ascii_case(&origin)
+                || (url.port().is_none() && grant.eq_ignore_ascii_case(host))
+        };
+        anyhow::ensure!(
+            self.capabilities.http.iter().any(granted),
+            "http to {origin} not granted"
+        );
+
+        let method = reqwest::Method::from_bytes(request.method.as_bytes())?;
+        let mut builder = self.http.request(method, url).body(request.body);
+        for (name, value) in request.headers {
+            builder = builder.header(name, value);
+        }
+        let mut response = builder.send().await?;
+
+        let status = response.status().as_u16();
+        let headers = { response.headers().iter() }
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (name.to_string(), value)
+            })
+            .collect();
+
+        let mut body = vec![];
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            anyhow::ensure!(
+                body.len() <= MAX_HTTP_RESPONSE_BYTES,
+                "response larger than {MAX_HTTP_RESPONSE_BYTES} bytes"
+            );
+        }
+
+        Ok(http::Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+fn granted(granted: bool, capability: &str) -> Result<(), String> {
+    match granted {
+        true => Ok(()),
+        false => Err(format!("{capability} not granted")),
+    }
+}
+
+impl http::Host for HostCtx {
+    async fn fetch(&mut self, request: http::Request) -> Result<http::Response, String> {
+        self.try_fetch(request).await.map_err(|e| format!("{e:#}"))
+    }
+}
+
+impl kv::Host for HostCtx {
+    async fn get(&mut self, key: String) -> Result<Option<Vec<u8>>, String> {
+        granted(self.capabilities.kv, "kv")?;
+
+        Ok(self.kv.get(self.session_id, &key))
+    }
+
+    async fn set(&mut self, key: String, value: Vec<u8>) -> Result<(), String> {
+        granted(self.capabilities.kv, "kv")?;
+
+        let limit = self.kv_limit_bytes;
+        { self.kv.set(self.session_id, key, value, limit) }.map_err(|e| e.to_string())
+    }
+
+    async fn delete(&mut self, key: String) -> Result<(), String> {
+        granted(self.capabilities.kv, "kv")?;
+
+        self.kv.delete(self.session_id, &key);
+
+        Ok(())
+    }
+}
+
+impl crypto::Host for HostCtx {
+    async fn public_key(&mut self) -> Result<Vec<u8>, String> {
+        granted(self.capabilities.sign, "sign")?;
+
+        let pk = self.session_sk.verifying_key().to_encoded_point(true);
+
+        Ok(pk.as_bytes().to_vec())
+    }
+
+    async fn sign(&mut self, message: Vec<u8>) -> Result<Vec<u8>, String> {
+        granted(self.capabilities.sign, "sign")?;
+
+        let signature: Signature = self
+            .session_sk
+            .sign(&[GUEST_SIGNATURE_DOMAIN, &message].concat());
+
+        Ok(signature.to_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::signature::Verifier;
+
+    use super::*;
+
+    fn host_ctx(capabilities: Capabilities) -> HostCtx {
+        HostCtx {
+            capabilities,
+            session_id: Uuid::now_v7(),
+            session_sk: SigningKey::random(&mut rand::rngs::OsRng),
+            kv: Arc::default(),
+            kv_limit_bytes: 1024,
+            http: HostCtx::http_client(),
+        }
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = Capabilities {
+            http: vec!["Example.com".to_string(), "example.com:8443".to_string()],
+            ..Default::default()
+        };
+
+        let allowlist = ["example.com".to_string(), "example.com:8443".to_string()];
+        assert!(capabilities.validate(&allowlist).is_ok());
+        assert!(capabilities.validate(&allowlist[..1]).is_err());
+        assert!(capabilities.validate(&[]).is_err());
+
+        let url_grant = Capabilities {
+            http: vec!["https://example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(url_grant
+            .validate(&["https://example.com".to_string()])
+            .is_err());
+
+        assert!(Capabilities::default().to_bytes().is_empty());
+        assert_eq!(
+            capabilities.to_bytes(),
+            b"\0\0example.com\nexample.com:8443\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_denied_imports() {
+        let mut host = host_ctx(Capabilities::default());
+
+        assert!(kv::Host::get(&mut host, "key".to_string()).await.is_err());
+        assert!(crypto::Host::sign(&mut host, vec![]).await.is_err());
+
+        let get = |url: &str| http::Request {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        };
+        let error = http::Host::fetch(&mut host, get("http://127.0.0.1/"))
+            .await
+            .unwrap_err();
+        assert!(error.contains("not granted"), "{error}");
+
+        // A host grants its default ports only, a port grants nothing else
+        host.capabilities.http = vec!["127.0.0.1".to_string(), "localhost:8080".to_string()];
+        for url in [
+            "http://127.0.0.1:8080/",
+            "https://127.0.0.1:80/",
+            "http://localhost/",
+            "https://localhost:8443/",
+        ] {
+            let error = http::Host::fetch(&mut host, get(url)).await.unwrap_err();
+            assert!(error.contains("not granted"), "{url} {error}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_granted_imports() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app =
+            axum::Router::new().route("/", axum::routing::post(|body: String| async { body }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut host = host_ctx(Capabilities {
+            http: vec![format!("127.0.0.1:{port}")],
+            kv: true,
+            sign: true,
+        });
+
+        kv::Host::set(&mut host, "key".to_string(), b"value".to_vec())
+            .await
+            .unwrap();
+        let value = kv::Host::get(&mut host, "key".to_string()).await.unwrap();
+        assert_eq!(value.unwrap(), b"value");
+
+        let signature = crypto::Host::sign(&mut host, b"message".to_vec())
+            .await
+            .unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        let signed = [GUEST_SIGNATURE_DOMAIN, b"message"].concat();
+        assert!(host
+            .session_sk
+            .verifying_key()
+            .verify(&signed, &signature)
+            .is_ok());
+
+        let request = http::Request {
+            method: "POST".to_string(),
+            url: format!("http://127.0.0.1:{port}/"),
+            headers: vec![("content-type".to_string(), "text/plain".to_string())],
+            body: b"echo".to_vec(),
+        };
+        let response = http::Host::fetch(&mut host, request).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"echo");
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgumentKind {
+    /// UTF-8 text, passed as is
+    String,
+    /// Raw bytes, passed hex encoded as argv only holds strings. Large
+    /// binary inputs belong on stdin
+    Bytes,
+    /// A JSON document, checked then passed as is
+    Json,
+}
+
+impl From<ArgumentKind> for u8 {
+    fn from(kind: ArgumentKind) -> Self {
+        kind as u8
+    }
+}
+
+/// A sealed argument and how it reaches the app
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypedArgument {
+    #[serde(rename = "type")]
+    pub kind: ArgumentKind,
+    /// Hex of the sealed argument
+    pub encrypted: String,
+}
+
+impl ArgumentKind {
+    /// Argv form of a decrypted argument
+    pub fn to_arg(self, decrypted: Vec<u8>) -> anyhow::Result<String> {
+        match self {
+            ArgumentKind::String => String::from_utf8(decrypted).context("argument isn't string"),
+            ArgumentKind::Bytes => Ok(const_hex::encode(decrypted)),
+            ArgumentKind::Json => {
+                let json = String::from_utf8(decrypted).context("argument isn't string")?;
+                serde_json::from_str::<serde::de::IgnoredAny>(&json)
+                    .context("argument isn't json")?;
+
+                Ok(json)
+            }
+        }
+    }
+
+    /// Param form of a decrypted argument, for a called function
+    pub fn to_json(self, decrypted: Vec<u8>) -> anyhow::Result<serde_json::Value> {
+        match self {
+            ArgumentKind::String => Ok(serde_json::Value::String(
+                String::from_utf8(decrypted).context("argument isn't string")?,
+            )),
+            // A list<u8>
+            ArgumentKind::Bytes => Ok(serde_json::Value::from(decrypted)),
+            ArgumentKind::Json => serde_json::from_slice(&decrypted).context("argument isn't json"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_arguments() {
+        let arg: TypedArgument =
+            serde_json::from_str(r#"{"type": "bytes", "encrypted": "00"}"#).unwrap();
+        assert_eq!(arg.kind, ArgumentKind::Bytes);
+
+        assert_eq!(
+            ArgumentKind::String.to_arg(b"tress".to_vec()).unwrap(),
+            "tress"
+        );
+        assert_eq!(ArgumentKind::Bytes.to_arg(vec![0xff, 0]).unwrap(), "ff00");
+        assert_eq!(
+            ArgumentKind::Json.to_arg(br#"{"a":1}"#.to_vec()).unwrap(),
+            r#"{"a":1}"#
+        );
+
+        assert!(ArgumentKind::String.to_arg(vec![0xff]).is_err());
+        assert!(ArgumentKind::Json.to_arg(b"{".to_vec()).is_err());
+
+        assert_eq!(
+            ArgumentKind::String.to_json(b"tress".to_vec()).unwrap(),
+            serde_json::json!("tress")
+        );
+        assert_eq!(
+            ArgumentKind::Bytes.to_json(vec![0xff, 0]).unwrap(),
+            serde_json::json!([255, 0])
+        );
+        assert_eq!(
+            ArgumentKind::Json.to_json(br#"{"a":1}"#.to_vec()).unwrap(),
+            serde_json::json!({"a": 1})
+        );
+    }
+}
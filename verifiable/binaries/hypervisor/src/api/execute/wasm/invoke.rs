@@ -0,0 +1,416 @@
+//! Calls of exported component functions with JSON values
+//!
+//! Values map onto WIT types as follows: numbers, bools and strings as is,
+//! a char as a one char string, lists and tuples as arrays, records as
+//! objects by field name, enums as their case name and flags as an array of
+//! set names. A variant case is its name when it has no payload, else an
+//! object `{"case": payload}`, a result is `{"ok": value}` or
+//! `{"err": value}`, an option is null or its value. Resources, futures and
+//! streams can't be passed.
+
+use anyhow::{anyhow, Context};
+use serde_json::{Map, Value};
+use wasmtime::{
+    component::{Func, Instance, Type, Val},
+    Store,
+};
+
+/// Call the export `name` of `instance`, `interface#function` for a
+/// function of an exported interface
+///
+/// Params are taken in order. The return value is null for a function
+/// without one.
+pub async fn call<T: Send>(
+    store: &mut Store<T>,
+    instance: &Instance,
+    name: &str,
+    params: &[Value],
+) -> anyhow::Result<Value> {
+    let func = lookup(store, instance, name)?;
+    let ty = func.ty(&*store);
+
+    anyhow::ensure!(
+        ty.params().len() == params.len(),
+        "{name} takes {} params, got {}",
+        ty.params().len(),
+        params.len()
+    );
+    let params = { ty.params().zip(params) }
+        .map(|((param, ty), json)| to_val(json, &ty).with_context(|| format!("param {param}")))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut results = vec![Val::Bool(false); ty.results().len()];
+    func.call_async(&mut *store, &params, &mut results).await?;
+    func.post_return_async(&mut *store).await?;
+
+    match results.as_slice() {
+        [] => Ok(Value::Null),
+        [result] => to_json(result),
+        results => Ok(Value::Array(
+            results.iter().map(to_json).collect::<anyhow::Result<_>>()?,
+        )),
+    }
+}
+
+/// Check `name` reads as an export, before anything runs
+pub fn validate_name(name: &str) -> anyhow::Result<()> {
+    let valid =
+        { name.split('#') }.all(|part| !part.is_empty() && !part.contains(char::is_whitespace));
+    anyhow::ensure!(
+        valid && name.matches('#').count() <= 1,
+        "function name {name:?} isn't an export"
+    );
+
+    Ok(())
+}
+
+fn lookup<T>(store: &mut Store<T>, instance: &Instance, name: &str) -> anyhow::Result<Func> {
+    let not_found = || anyhow!("component exports no function {name}");
+
+    let index = match name.split_once('#') {
+        Some((interface, function)) => {
+            let interface = { instance.get_export_index(&mut *store, None, interface) }
+                .ok_or_else(not_found)?;
+            instance.get_export_index(&mut *store, Some(&interface), function)
+        }
+        None => instance.get_export_index(&mut *store, None, name),
+    }
+    .ok_or_else(not_found)?;
+
+    instance.get_func(&mut *store, index).ok_or_else(not_found)
+}
+
+/// JSON as a value of `ty`
+pub fn to_val(json: &Value, ty: &Type) -> anyhow::Result<Val> {
+    let mismatch = || anyhow!("{json} isn't {}", wit_name(ty));
+
+    let int = || json.as_i64().ok_or_else(mismatch);
+    let uint = || json.as_u64().ok_or_else(mismatch);
+
+    let val = match ty {
+        Type::Bool => Val::Bool(json.as_bool().ok_or_else(mismatch)?),
+        Type::S8 => Val::S8(int()?.try_into().map_err(|_| mismatch())?),
+        Type::U8 => Val::U8(uint()?.try_into().map_err(|_| mismatch())?),
+        Type::S16 => Val::S16(int()?.try_into().map_err(|_| mismatch())?),
+        Type::U16 => Val::U16(uint()?.try_into().map_err(|_| mismatch())?),
+        Type::S32 => Val::S32(int()?.try_into().map_err(|_| mismatch())?),
+        Type::U32 => Val::U32(uint()?.try_into().map_err(|_| mismatch())?),
+        Type::S64 => Val::S64(int()?),
+        Type::U64 => Val::U64(uint()?),
+        Type::Float32 => Val::Float32(json.as_f64().ok_or_else(mismatch)? as f32),
+        Type::Float64 => Val::Float64(json.as_f64().ok_or_else(mismatch)?),
+        Type::Char => {
+            let mut chars = json.as_str().ok_or_else(mismatch)?.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Val::Char(c),
+                _ => return Err(mismatch()),
+            }
+        }
+        Type::String => Val::String(json.as_str().ok_or_else(mismatch)?.to_string()),
+        Type::List(list) => {
+            let ty = list.ty();
+            let items = json.as_array().ok_or_else(mismatch)?;
+
+            Val::List(
+                items
+                    .iter()
+                    .map(|i| to_val(i, &ty))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        Type::Record(record) => {
+            let fields = json.as_object().ok_or_else(mismatch)?;
+
+            // Missing fields read as null, which only an option takes
+            let fields = { record.fields() }
+                .map(|field| {
+                    let json = fields.get(field.name).unwrap_or(&Value::Null);
+                    let val =
+                        to_val(json, &field.ty).with_context(|| format!("field {}", field.name))?;
+
+                    Ok((field.name.to_string(), val))
+                })
+                .collect::<anyhow::Result<_>>()?;
+
+            Val::Record(fields)
+        }
+        Type::Tuple(tuple) => {
+            let items = json.as_array().ok_or_else(mismatch)?;
+            anyhow::ensure!(items.len() == tuple.types().len(), mismatch());
+
+            let items = { tuple.types().zip(items) }
+                .map(|(ty, item)| to_val(item, &ty))
+                .collect::<anyhow::Result<_>>()?;
+
+            Val::Tuple(items)
+        }
+        Type::Variant(variant) => {
+            let (name, payload) = case(json).ok_or_else(mismatch)?;
+            let case = { variant.cases() }
+                .find(|c| c.name == name)
+                .ok_or_else(mismatch)?;
+
+            let payload = match (case.ty, payload) {
+                (Some(ty), Some(payload)) => Some(Box::new(to_val(payload, &ty)?)),
+                (None, None) => None,
+                _ => return Err(mismatch()),
+            };
+
+            Val::Variant(name.to_string(), payload)
+        }
+        Type::Enum(enum_) => {
+            let name = json.as_str().ok_or_else(mismatch)?;
+            anyhow::ensure!(enum_.names().any(|n| n == name), mismatch());
+
+            Val::Enum(name.to_string())
+        }
+        Type::Option(option) => match json {
+            Value::Null => Val::Option(None),
+            json => Val::Option(Some(Box::new(to_val(json, &option.ty())?))),
+        },
+        Type::Result(result) => {
+            let (name, payload) = case(json).ok_or_else(mismatch)?;
+            let ty = match name {
+                "ok" => result.ok(),
+                "err" => result.err(),
+                _ => return Err(mismatch()),
+            };
+
+            let payload = match (ty, payload) {
+                (Some(ty), Some(payload)) => Some(Box::new(to_val(payload, &ty)?)),
+                (None, None | Some(Value::Null)) => None,
+                _ => return Err(mismatch()),
+            };
+
+            match name {
+                "ok" => Val::Result(Ok(payload)),
+                _ => Val::Result(Err(payload)),
+            }
+        }
+        Type::Flags(flags) => {
+            let names = { json.as_array().ok_or_else(mismatch)?.iter() }
+                .map(|name| {
+                    let name = name.as_str().ok_or_else(mismatch)?;
+                    anyhow::ensure!(flags.names().any(|n| n == name), mismatch());
+
+                    Ok(name.to_string())
+                })
+                .collect::<anyhow::Result<_>>()?;
+
+            Val::Flags(names)
+        }
+        _ => anyhow::bail!("{} can't be passed as json", wit_name(ty)),
+    };
+
+    Ok(val)
+}
+
+/// JSON of a returned value
+pub fn to_json(val: &Val) -> anyhow::Result<Value> {
+    let float = |f: f64| {
+        serde_json::Number::from_f64(f)
+            .map(Value::Number)
+            .ok_or(anyhow!("{f} isn't a json number"))
+    };
+
+    let json = match val {
+        Val::Bool(b) => Value::Bool(*b),
+        Val::S8(n) => Value::from(*n),
+        Val::U8(n) => Value::from(*n),
+        Val::S16(n) => Value::from(*n),
+        Val::U16(n) => Value::from(*n),
+        Val::S32(n) => Value::from(*n),
+        Val::U32(n) => Value::from(*n),
+        Val::S64(n) => Value::from(*n),
+        Val::U64(n) => Value::from(*n),
+        Val::Float32(f) => float(*f as f64)?,
+        Val::Float64(f) => float(*f)?,
+        Val::Char(c) => Value::String(c.to_string()),
+        Val::String(s) => Value::String(s.clone()),
+        Val::List(items) | Val::Tuple(items) => {
+            Value::Array(items.iter().map(to_json).collect::<anyhow::Result<_>>()?)
+        }
+        Val::Record(fields) => Value::Object(
+            { fields.iter() }
+                .map(|(name, val)| Ok((name.clone(), to_json(val)?)))
+                .collect::<anyhow::Result<Map<_, _>>>()?,
+        ),
+        Val::Variant(name, None) | Val::Enum(name) => Value::String(name.clone()),
+        Val::Variant(name, Some(payload)) => single(name, to_json(payload)?),
+        Val::Option(None) => Value::Null,
+        Val::Option(Some(val)) => to_json(val)?,
+        Val::Result(result) => {
+            let (name, payload) = match result {
+                Ok(payload) => ("ok", payload),
+                Err(payload) => ("err", payload),
+            };
+            let payload = match payload {
+                Some(val) => to_json(val)?,
+                None => Value::Null,
+            };
+
+            single(name, payload)
+        }
+        Val::Flags(names) => Value::from(names.clone()),
+        _ => anyhow::bail!("function returned a value json can't hold"),
+    };
+
+    Ok(json)
+}
+
+/// Case name and payload of a variant or result
+fn case(json: &Value) -> Option<(&str, Option<&Value>)> {
+    match json {
+        Value::String(name) => Some((name, None)),
+        Value::Object(object) if object.len() == 1 => {
+            let (name, payload) = object.iter().next()?;
+            Some((name, Some(payload)))
+        }
+        _ => None,
+    }
+}
+
+fn single(name: &str, payload: Value) -> Value {
+    Value::Object(Map::from_iter([(name.to_string(), payload)]))
+}
+
+fn wit_name(ty: &Type) -> &'static str {
+    match ty {
+        Type::Bool => "a bool",
+        Type::S8 => "an s8",
+        Type::U8 => "a u8",
+        Type::S16 => "an s16",
+        Type::U16 => "a u16",
+        Type::S32 => "an s32",
+        Type::U32 => "a u32",
+        Type::S64 => "an s64",
+        Type::U64 => "a u64",
+        Type::Float32 => "an f32",
+        Type::Float64 => "an f64",
+        Type::Char => "a char",
+        Type::String => "a string",
+        Type::List(_) => "a list",
+        Type::Record(_) => "a record",
+        Type::Tuple(_) => "a tuple",
+        Type::Variant(_) => "a variant",
+        Type::Enum(_) => "an enum",
+        Type::Option(_) => "an option",
+        Type::Result(_) => "a result",
+        Type::Flags(_) => "flags",
+        Type::Own(_) | Type::Borrow(_) => "a resource",
+        _ => "an async value",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wasmtime::component::{Component, Linker};
+
+    use super::*;
+    use crate::api::execute::wasm::cache::ComponentCache;
+
+    const CALC_WAT: &str = r#"
+        (component
+            (core module $m
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0
+                    local.get 1
+                    i32.add)
+                (func (export "presence") (param i32 i32) (result i32)
+                    local.get 0))
+            (core instance $i (instantiate $m))
+
+            (type $presence (enum "absent" "present"))
+            (export $presence-export "presence" (type $presence))
+
+            (func $add (param "a" u32) (param "b" u32) (result u32)
+                (canon lift (core func $i "add")))
+            (func $check (param "x" (option u32)) (result $presence-export)
+                (canon lift (core func $i "presence")))
+
+            (instance $calc (export "add" (func $add)))
+
+            (export "add" (func $add))
+            (export "check" (func $check))
+            (export "calc" (instance $calc)))
+    "#;
+
+    #[tokio::test]
+    async fn test_call_function() {
+        let cache = ComponentCache::default();
+        let component = Component::new(cache.engine(), CALC_WAT).unwrap();
+
+        let mut store = Store::new(cache.engine(), ());
+        store.set_fuel(1_000_000).unwrap();
+        let instance = { Linker::new(cache.engine()) }
+            .instantiate_async(&mut store, &component)
+            .await
+            .unwrap();
+
+        let mut call =
+            async |name: &str, params: &[Value]| call(&mut store, &instance, name, params).await;
+
+        assert_eq!(call("add", &[json!(2), json!(3)]).await.unwrap(), json!(5));
+        assert_eq!(
+            call("calc#add", &[json!(2), json!(3)]).await.unwrap(),
+            json!(5)
+        );
+        assert_eq!(
+            call("check", &[json!(null)]).await.unwrap(),
+            json!("absent")
+        );
+        assert_eq!(call("check", &[json!(7)]).await.unwrap(), json!("present"));
+
+        assert!(call("sub", &[]).await.is_err());
+        assert!(call("add", &[json!(2)]).await.is_err());
+        assert!(call("add", &[json!(-2), json!(3)]).await.is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let val = Val::Record(vec![
+            ("id".to_string(), Val::U64(u64::MAX)),
+            ("tag".to_string(), Val::Char('x')),
+            (
+                "parts".to_string(),
+                Val::List(vec![Val::Float64(0.5), Val::Float64(2.0)]),
+            ),
+            ("kind".to_string(), Val::Enum("large".to_string())),
+            ("note".to_string(), Val::Option(None)),
+            (
+                "outcome".to_string(),
+                Val::Result(Err(Some(Box::new(Val::String("no".to_string()))))),
+            ),
+            (
+                "shape".to_string(),
+                Val::Variant("circle".to_string(), Some(Box::new(Val::U8(3)))),
+            ),
+        ]);
+
+        assert_eq!(
+            to_json(&val).unwrap(),
+            json!({
+                "id": u64::MAX,
+                "tag": "x",
+                "parts": [0.5, 2.0],
+                "kind": "large",
+                "note": null,
+                "outcome": {"err": "no"},
+                "shape": {"circle": 3},
+            })
+        );
+        assert!(to_json(&Val::Float32(f32::NAN)).is_err());
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("run").is_ok());
+        assert!(validate_name("wasi:cli/run@0.2.0#run").is_ok());
+
+        assert!(validate_name("").is_err());
+        assert!(validate_name("calc#").is_err());
+        assert!(validate_name("a#b#c").is_err());
+        assert!(validate_name("add two").is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use wasmtime::{ResourceLimiter, Trap};
+
+use crate::api::execute::ExecutionLimit;
+
+/// Caps of one WASM execution, requests may only tighten the configured
+/// ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WasmLimits {
+    /// Fuel units, roughly one per executed instruction
+    pub fuel: u64,
+    /// Linear memory summed over all memories
+    pub memory_bytes: u64,
+    /// Elements of any single table
+    pub table_elements: u64,
+    pub wall_clock_ms: u64,
+}
+
+impl Default for WasmLimits {
+    fn default() -> Self {
+        WasmLimits {
+            fuel: 10_000_000_000,
+            memory_bytes: 256 * 1024 * 1024,
+            table_elements: 100_000,
+            wall_clock_ms: 30_000,
+        }
+    }
+}
+
+/// Limits asked for by a request, unset ones keep the configured value
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct RequestedLimits {
+    pub fuel: Option<u64>,
+    pub memory_bytes: Option<u64>,
+    pub table_elements: Option<u64>,
+    pub wall_clock_ms: Option<u64>,
+}
+
+impl WasmLimits {
+    pub fn tighten(self, requested: &RequestedLimits) -> Self {
+        let min = |cap: u64, requested: Option<u64>| requested.map_or(cap, |r| r.min(cap));
+
+        WasmLimits {
+            fuel: min(self.fuel, requested.fuel),
+            memory_bytes: min(self.memory_bytes, requested.memory_bytes),
+            table_elements: min(self.table_elements, requested.table_elements),
+            wall_clock_ms: min(self.wall_clock_ms, requested.wall_clock_ms),
+        }
+    }
+
+    pub fn wall_clock(&self) -> Duration {
+        Duration::from_millis(self.wall_clock_ms)
+    }
+}
+
+/// Enforces the memory and table caps, tracking peak memory on the way
+pub struct ExecutionLimiter {
+    limits: WasmLimits,
+    memory_bytes: usize,
+    pub peak_memory_bytes: usize,
+    pub exceeded: Option<ExecutionLimit>,
+}
+
+impl ExecutionLimiter {
+    pub fn new(limits: WasmLimits) -> Self {
+        ExecutionLimiter {
+            limits,
+            memory_bytes: 0,
+            peak_memory_bytes: 0,
+            exceeded: None,
+        }
+    }
+
+    /// Limit behind a failed execution, if any
+    pub fn exceeded_by(&self, e: &anyhow::Error) -> Option<ExecutionLimit> {
+        match e.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => Some(ExecutionLimit::Fuel),
+            _ => self.exceeded,
+        }
+    }
+}
+
+impl ResourceLimiter for ExecutionLimiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        let total = self.memory_bytes - current + desired;
+        if total as u64 > self.limits.memory_bytes {
+            self.exceeded = Some(ExecutionLimit::Memory);
+            anyhow::bail!(
+                "memory limit of {} bytes exceeded",
+                self.limits.memory_bytes
+            );
+        }
+
+        self.memory_bytes = total;
+        self.peak_memory_bytes = self.peak_memory_bytes.max(total);
+
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        if desired as u64 > self.limits.table_elements {
+            self.exceeded = Some(ExecutionLimit::Table);
+            anyhow::bail!(
+                "table limit of {} elements exceeded",
+                self.limits.table_elements
+            );
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tighten_limits() {
+        let configured = WasmLimits::default();
+        let requested = RequestedLimits {
+            fuel: Some(1_000),
+            wall_clock_ms: Some(u64::MAX),
+            ..Default::default()
+        };
+
+        let limits = configured.tighten(&requested);
+        assert_eq!(limits.fuel, 1_000);
+        assert_eq!(limits.wall_clock_ms, configured.wall_clock_ms);
+        assert_eq!(limits.memory_bytes, configured.memory_bytes);
+    }
+
+    #[test]
+    fn test_execution_limiter() {
+        let mut limiter = ExecutionLimiter::new(WasmLimits {
+            memory_bytes: 3 * 65536,
+            table_elements: 10,
+            ..Default::default()
+        });
+
+        assert!(limiter.memory_growing(0, 65536, None).unwrap());
+        assert!(limiter.memory_growing(65536, 2 * 65536, None).unwrap());
+        assert!(limiter.memory_growing(0, 65536, None).unwrap());
+        assert_eq!(limiter.peak_memory_bytes, 3 * 65536);
+
+        assert!(limiter.memory_growing(65536, 2 * 65536, None).is_err());
+        assert_eq!(limiter.exceeded, Some(ExecutionLimit::Memory));
+
+        assert!(limiter.table_growing(0, 10, None).unwrap());
+        assert!(limiter.table_growing(10, 11, None).is_err());
+        assert_eq!(limiter.exceeded, Some(ExecutionLimit::Table));
+    }
+}
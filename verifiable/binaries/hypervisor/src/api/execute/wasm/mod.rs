@@ -1,29 +1,71 @@
+pub mod bundle;
+pub mod cache;
+pub mod host;
+pub mod input;
+pub mod invoke;
+pub mod limits;
+pub mod output;
+pub mod stream;
+
+use std::{borrow::Cow, convert::Infallible};
+
 use aes_gcm_siv::aead::Aead;
 use anyhow::{anyhow, Context};
-use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use async_trait::async_trait;
+use attest::{event_log::EventKind, report_data::ReportData};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::post,
+    Json, Router,
+};
+use futures_util::Stream;
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
 use tracing::info;
 use uuid::Uuid;
 use wasmtime::{
-    component::{Component, Linker},
-    Config, Engine, Store,
+    component::{HasSelf, Linker},
+    Store,
 };
 use wasmtime_wasi::{
-    p2::{bindings::Command, pipe::MemoryOutputPipe},
-    ResourceTable, WasiCtx, WasiCtxView, WasiView,
+    p2::{bindings::Command, pipe::MemoryInputPipe},
+    DirPerms, FilePerms, I32Exit, ResourceTable, WasiCtx, WasiCtxView, WasiView,
 };
 
+use bundle::{WorkDir, INPUT_DIR, OUTPUT_DIR, SCRATCH_DIR};
+use cache::ComponentCache;
+use host::{Capabilities, HostCtx};
+use input::{ArgumentKind, TypedArgument};
+use limits::{ExecutionLimiter, RequestedLimits, WasmLimits};
+use output::OutputCapture;
+use stream::{FrameSealer, StreamError, StreamEvent};
+
 use crate::{
+    api::execute::{ExecutionLimit, Executor, GuestInput, GuestOutput},
     error::HypervisorError,
     types::HypervisorState,
     utils::{
-        self, commitment, crypto,
+        self,
+        attest::Measurement,
+        auth::{self, RequestSignature, SignedJson},
+        commitment::{self, CommittedRequest, CommittedResponse, ExecutorKind},
+        crypto::{self, EnvelopeVersion},
         x402::{self, X402_FIXED_PRICE_USDC},
     },
 };
 
+/// Fuel between two yields of a running guest
+const FUEL_YIELD_INTERVAL: u64 = 100_000;
+
+/// Committed with every result, follows the wasmtime dependency
+pub const WASM_RUNTIME_VERSION: &str = "wasmtime 38";
+
 pub(crate) fn api_register(router: Router<HypervisorState>) -> Router<HypervisorState> {
-    router.route("/test/execute/wasm", post(execute_wasm))
+    router
+        .route("/test/execute/wasm", post(execute_wasm))
+        .route("/test/execute/wasm/stream", post(stream_execute_wasm))
 }
 
 pub(crate) fn api_x402_register(
@@ -33,68 +75,307 @@ pub(crate) fn api_x402_register(
     let x402_router = Router::new()
         .route("/test/wasm", post(execute_wasm))
         .route("/verifiable/wasm", post(verifiable_execute_wasm))
+        .route("/verifiable/wasm/stream", post(stream_execute_wasm))
         .layer(x402::create_x402_middleware(X402_FIXED_PRICE_USDC))
         .with_state(state);
 
     router.nest("/x402_execute", x402_router)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VerifiableExecutionResponse {
-    pub session_id: Uuid,
-    pub encrypted_result: String,
-    pub result_nonce: String,
-    pub result_commitment: String,
+    #[serde(flatten)]
+    pub execution: ExecutionResponse,
     pub result_quote: String,
+    /// Set with signed responses, `result_quote` then attests the response key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_signature: Option<String>,
 }
 
 async fn verifiable_execute_wasm(
-    state: State<HypervisorState>,
-    req: Json<ExecutionRequest>,
+    State(state): State<HypervisorState>,
+    SignedJson {
+        body: req,
+        signature,
+    }: SignedJson<ExecutionRequest>,
 ) -> Result<Json<VerifiableExecutionResponse>, HypervisorError> {
-    let Json(resp) = execute_wasm(state, req).await?;
+    authorize_execution(&state, &req, signature.as_ref())?;
+
+    run_verifiable_execution(&state, req, signature, None)
+        .await
+        .map(Json)
+}
+
+/// Server-sent `frame` events of stdout while the guest runs, then a
+/// `result` event with the verifiable response, or an `error` event
+#[tracing::instrument(skip(state, req, signature), err)]
+async fn stream_execute_wasm(
+    State(state): State<HypervisorState>,
+    SignedJson {
+        body: req,
+        signature,
+    }: SignedJson<ExecutionRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, HypervisorError> {
+    // Legacy envelopes would seal every frame under the same nonce
+    if req.version == EnvelopeVersion::Legacy {
+        return Err(anyhow!("streaming needs framed envelopes")
+            .context(StatusCode::BAD_REQUEST)
+            .into());
+    }
+
+    authorize_execution(&state, &req, signature.as_ref())?;
+
+    let (events, rx) = mpsc::channel(stream::STREAM_FRAME_BACKLOG);
+    let execution = tokio::spawn(async move {
+        let frames = events.clone();
+        let event = match run_verifiable_execution(&state, req, signature, Some(frames)).await {
+            Ok(resp) => StreamEvent::Result(Box::new(resp)),
+            Err(e) => {
+                let (status_code, error) = e.into_parts();
+                StreamEvent::Error(StreamError {
+                    status_code: status_code.as_u16(),
+                    error,
+                })
+            }
+        };
+
+        let _ = events.send(event).await;
+    });
+
+    Ok(Sse::new(stream::sse_events(rx, execution)).keep_alive(KeepAlive::default()))
+}
+
+/// Run an authorized request and attest its result
+pub(crate) async fn run_verifiable_execution(
+    state: &HypervisorState,
+    req: ExecutionRequest,
+    signature: Option<RequestSignature>,
+    frames: Option<mpsc::Sender<StreamEvent>>,
+) -> Result<VerifiableExecutionResponse, HypervisorError> {
+    let resp = run_execution(state, req, signature, frames, Measurement::Required).await?;
     let commitment: [u8; 32] =
         const_hex::decode_to_array(&resp.result_commitment).expect("impossible");
 
-    let quote = attest::get_quote(utils::attest::generate_raw_report_from_hash(commitment))
-        .context("get execute result quote")
+    let report = ReportData::ExecutionResult { commitment };
+    let attestation = utils::attest::attest_response(state, report)
+        .context("attest execute result")
         .context(StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let verifiable_resp = VerifiableExecutionResponse {
-        session_id: resp.session_id,
-        result_nonce: resp.result_nonce,
-        encrypted_result: resp.encrypted_result,
-        result_commitment: resp.result_commitment,
-        result_quote: const_hex::encode(quote.to_bytes()),
-    };
-
-    Ok(Json(verifiable_resp))
+    Ok(VerifiableExecutionResponse {
+        execution: resp,
+        result_quote: attestation.quote_hex(),
+        result_signature: attestation.signature_hex(),
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExecutionRequest {
+    /// Framing of the encrypted fields, legacy when left out
+    #[serde(default)]
+    pub version: EnvelopeVersion,
     pub encrypted_wasm: String,
+    /// Export to call instead of `wasi:cli/run`, `interface#function` for a
+    /// function of an exported interface
+    #[serde(default)]
+    pub function_name: Option<String>,
     #[serde(default = "Vec::new")]
     pub encrypted_arguments: Vec<String>,
+    /// Passed after `encrypted_arguments`, as the params of `function_name`
+    /// when set
+    #[serde(default)]
+    pub typed_arguments: Vec<TypedArgument>,
+    /// Hex of the sealed stdin, empty stdin when left out
+    #[serde(default)]
+    pub encrypted_stdin: Option<String>,
+    /// Hex of a sealed ustar archive, mounted read-only at `/input`
+    #[serde(default)]
+    pub encrypted_bundle: Option<String>,
     pub public_key: String,
+    /// Session to use, the latest session of `public_key` when left out
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+    /// Tighter caps than the configured ones
+    #[serde(default)]
+    pub limits: RequestedLimits,
+    /// `x402:host` imports the guest may use
+    #[serde(default)]
+    pub capabilities: Capabilities,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Argument lists come with their length, typed arguments with their type
+/// before their ciphertext
+impl CommittedRequest for ExecutionRequest {
+    fn version(&self) -> EnvelopeVersion {
+        self.version
+    }
+
+    fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    fn committed_inputs(&self) -> Vec<Cow<'_, [u8]>> {
+        let mut inputs: Vec<Cow<'_, [u8]>> = vec![
+            self.encrypted_wasm.as_bytes().into(),
+            self.function_name
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .into(),
+            commitment::count(self.encrypted_arguments.len()),
+        ];
+        inputs.extend({ self.encrypted_arguments.iter() }.map(|a| a.as_bytes().into()));
+
+        inputs.push(commitment::count(self.typed_arguments.len()));
+        for a in &self.typed_arguments {
+            inputs.extend([vec![u8::from(a.kind)].into(), a.encrypted.as_bytes().into()]);
+        }
+
+        inputs.extend([
+            self.encrypted_stdin
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .into(),
+            self.encrypted_bundle
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .into(),
+            self.capabilities.to_bytes().into(),
+        ]);
+
+        inputs
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ExecutionResponse {
+    pub executor: ExecutorKind,
+    pub runtime_version: String,
     pub session_id: Uuid,
     pub encrypted_result: String,
     pub result_nonce: String,
     pub result_commitment: String,
+    pub fuel_consumed: u64,
+    pub peak_memory_bytes: u64,
+    /// Non zero when the app failed, `encrypted_result` then holds what it
+    /// wrote to stdout until then. A called function's JSON return value
+    /// takes the place of stdout
+    pub exit_code: i32,
+    /// Output past `wasm_output_limit_bytes` was dropped
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    /// Stderr of a failed app, sealed like the request fields
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_stderr: Option<String>,
+    /// Files the app left in `/output`, a sealed ustar archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_files: Option<String>,
+    /// Chain after the last stdout frame of a streamed execution
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_chain: Option<String>,
 }
 
-#[tracing::instrument(skip(state, req), err)]
+/// The result comes with its nonce, legacy envelopes don't carry it. The
+/// consumed fuel and peak memory, the truncation flags, the stderr of
+/// failed runs, the output files and the stdout frame chain of streamed
+/// runs follow it
+impl CommittedResponse for ExecutionResponse {
+    fn executor(&self) -> ExecutorKind {
+        self.executor
+    }
+
+    fn runtime_version(&self) -> &str {
+        &self.runtime_version
+    }
+
+    fn session_id(&self) -> Uuid {
+        self.session_id
+    }
+
+    fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+
+    fn committed_outputs(&self) -> Vec<Cow<'_, [u8]>> {
+        vec![
+            self.result_nonce.as_bytes().into(),
+            self.encrypted_result.as_bytes().into(),
+            self.fuel_consumed.to_be_bytes().to_vec().into(),
+            self.peak_memory_bytes.to_be_bytes().to_vec().into(),
+            vec![
+                u8::from(self.stdout_truncated),
+                u8::from(self.stderr_truncated),
+            ]
+            .into(),
+            self.encrypted_stderr
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .into(),
+            self.encrypted_files
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .into(),
+            self.stream_chain
+                .as_deref()
+                .unwrap_or_default()
+                .as_bytes()
+                .into(),
+        ]
+    }
+
+    fn result_commitment(&self) -> &str {
+        &self.result_commitment
+    }
+}
+
+#[tracing::instrument(skip(state, req, signature), err)]
 async fn execute_wasm(
     State(state): State<HypervisorState>,
-    Json(req): Json<ExecutionRequest>,
+    SignedJson {
+        body: req,
+        signature,
+    }: SignedJson<ExecutionRequest>,
 ) -> Result<Json<ExecutionResponse>, HypervisorError> {
-    // Validate request
-    validate_execution_request(&req)?;
+    authorize_execution(&state, &req, signature.as_ref())?;
+
+    run_execution(&state, req, signature, None, Measurement::BestEffort)
+        .await
+        .map(Json)
+}
 
+/// Checks made before a request runs, its signature is spent here
+pub(crate) fn authorize_execution(
+    state: &HypervisorState,
+    req: &ExecutionRequest,
+    signature: Option<&RequestSignature>,
+) -> Result<(), HypervisorError> {
+    validate_execution_request(req)?;
+
+    let user_pk = crypto::pk_from_hex(&req.public_key)
+        .context("decode request pubkey")
+        .context(StatusCode::BAD_REQUEST)?;
+
+    auth::authenticate(state, &user_pk, signature)?;
+
+    { req.capabilities.validate(&state.config.wasm_http_allowlist) }
+        .context("grant capabilities")
+        .context(StatusCode::FORBIDDEN)?;
+
+    Ok(())
+}
+
+/// Execute a request [`authorize_execution`] accepted, sending stdout as
+/// [`StreamEvent::Frame`]s to `frames` if given
+pub(crate) async fn run_execution(
+    state: &HypervisorState,
+    req: ExecutionRequest,
+    signature: Option<RequestSignature>,
+    frames: Option<mpsc::Sender<StreamEvent>>,
+    measurement: Measurement,
+) -> Result<ExecutionResponse, HypervisorError> {
     let start_time = std::time::Instant::now();
 
     let user_pk = crypto::pk_from_hex(&req.public_key)
@@ -102,7 +383,7 @@ async fn execute_wasm(
         .context(StatusCode::BAD_REQUEST)?;
 
     let (session_sk, session_id) = state
-        .get_session_keypair(&user_pk)
+        .get_session_keypair(&user_pk, req.session_id)
         .ok_or(anyhow!("session not found"))
         .context(StatusCode::UNAUTHORIZED)?;
 
@@ -110,116 +391,192 @@ async fn execute_wasm(
         .context("create encrypt key")
         .context(StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let msg_nonce = crypto::derive_msg_nonce(session_id);
-
     let decrypted_wasm = {
         let encrypted_bytes = const_hex::decode(&req.encrypted_wasm)
             .context("invalid wasm binary hex")
             .context(StatusCode::BAD_REQUEST)?;
 
-        cipher
-            .decrypt(&msg_nonce, encrypted_bytes.as_slice())
-            .map_err(|e| anyhow!(e.to_string()))
+        { req.version.open(&cipher, session_id, &encrypted_bytes) }
             .context("decrypt wasm binary")
             .context(StatusCode::BAD_REQUEST)?
     };
 
-    let decrypted_arguments = { req.encrypted_arguments.iter() }
-        .map(|a| {
-            let bytes = const_hex::decode(a).context("decode argument hex")?;
+    utils::attest::measure_artifact(
+        measurement,
+        EventKind::WasmComponent,
+        "wasm component",
+        &decrypted_wasm,
+    )?;
 
-            let decrypted = cipher
-                .decrypt(&msg_nonce, bytes.as_ref())
-                .map_err(|e| anyhow!(e.to_string()))
-                .context("decrypt argument")?;
+    let arguments = { req.encrypted_arguments.iter() }
+        .map(|a| (ArgumentKind::String, a))
+        .chain(req.typed_arguments.iter().map(|a| (a.kind, &a.encrypted)));
 
-            let a = String::from_utf8(decrypted).context("argument isn't string")?;
+    let mut decrypted_arguments = vec![];
+    let mut function_params = vec![];
+    for (kind, a) in arguments {
+        let bytes = const_hex::decode(a)
+            .context("decode argument hex")
+            .context(StatusCode::BAD_REQUEST)?;
 
-            Ok(a)
-        })
-        .collect::<Result<Vec<String>, anyhow::Error>>()
-        .context(StatusCode::BAD_REQUEST)?;
+        let decrypted = { req.version.open(&cipher, session_id, &bytes) }
+            .context("decrypt argument")
+            .context(StatusCode::BAD_REQUEST)?;
+
+        // Params of a called function, argv of a command
+        if req.function_name.is_some() {
+            let param = kind.to_json(decrypted).context(StatusCode::BAD_REQUEST)?;
+            function_params.push(param);
+        } else {
+            let arg = kind.to_arg(decrypted).context(StatusCode::BAD_REQUEST)?;
+            decrypted_arguments.push(arg);
+        }
+    }
+
+    let decrypted_stdin = match &req.encrypted_stdin {
+        Some(stdin) => {
+            let bytes = const_hex::decode(stdin)
+                .context("decode stdin hex")
+                .context(StatusCode::BAD_REQUEST)?;
+
+            { req.version.open(&cipher, session_id, &bytes) }
+                .context("decrypt stdin")
+                .context(StatusCode::BAD_REQUEST)?
+        }
+        None => vec![],
+    };
+
+    let files_limit = state.config.wasm_files_limit_bytes;
+    let work_dir = WorkDir::create(&state.config.wasm_work_dir)
+        .context("create work dir")
+        .context(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(encrypted_bundle) = &req.encrypted_bundle {
+        let bytes = const_hex::decode(encrypted_bundle)
+            .context("decode bundle hex")
+            .context(StatusCode::BAD_REQUEST)?;
+
+        let bundle = { req.version.open(&cipher, session_id, &bytes) }
+            .context("decrypt bundle")
+            .context(StatusCode::BAD_REQUEST)?;
+
+        bundle::unpack(&bundle, &work_dir.host_path(INPUT_DIR), files_limit)
+            .context("unpack bundle")
+            .context(StatusCode::BAD_REQUEST)?;
+    }
+
+    work_dir
+        .ensure_free_space(files_limit)
+        .context(StatusCode::SERVICE_UNAVAILABLE)?;
 
     info!(
         session_id = %session_id,
         public_key = req.public_key,
+        capabilities = ?req.capabilities,
         "processing WASM execution request"
     );
 
-    let mut config = Config::new();
-    config.async_support(true);
-
-    let engine = Engine::new(&config).unwrap();
-    let mut linker = Linker::new(&engine);
-    wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
-
-    let stdout = MemoryOutputPipe::new(4096);
-    let wasi = WasiCtx::builder()
-        .arg("wasm")
-        .args(&decrypted_arguments)
-        .stdout(stdout.clone())
-        .build();
+    let mut stdout = OutputCapture::new(state.config.wasm_output_limit_bytes);
+    if let Some(frames) = frames {
+        let sealer = FrameSealer::new(cipher.clone(), req.version, session_id, frames);
+        stdout = stdout.with_frames(sealer);
+    }
 
-    let state = ComponentRunStates {
-        wasi_ctx: wasi,
-        resource_table: ResourceTable::new(),
+    let executor = WasmExecutor {
+        components: state.components(),
+        limits: state.config.wasm_limits.tighten(&req.limits),
+        output_limit: state.config.wasm_output_limit_bytes,
+        files_limit,
+        function: { req.function_name.as_deref() }.map(|name| (name, function_params)),
+        work_dir: &work_dir,
+        host: HostCtx {
+            capabilities: req.capabilities.clone(),
+            session_id,
+            session_sk: session_sk.clone(),
+            kv: state.session_kv(),
+            kv_limit_bytes: state.config.session_kv_limit_bytes,
+            http: HostCtx::http_client(),
+        },
+        stdout: stdout.clone(),
     };
-    let mut store = Store::new(&engine, state);
-
-    let component = Component::from_binary(&engine, &decrypted_wasm)?;
-
-    let command = Command::instantiate_async(&mut store, &component, &linker).await?;
-
-    let app_output = match command.wasi_cli_run().call_run(&mut store).await {
-        Err(e) => {
-            return Err(e
-                .context("execute wasm")
-                .context(StatusCode::BAD_REQUEST)
-                .into());
-        }
-        Ok(Ok(_)) => stdout.contents(),
-        Ok(Err(_)) => {
-            return Err(anyhow!("unexpected app exited")
-                .context(StatusCode::BAD_REQUEST)
-                .into())
-        }
+    let input = GuestInput {
+        program: decrypted_wasm,
+        arguments: decrypted_arguments,
+        stdin: decrypted_stdin,
     };
+    let (executor_kind, runtime_version) = (executor.kind(), executor.runtime_version());
+    let (output, metrics) = executor.execute(input).await?;
+
+    let exit_code = output.exit_code;
+    let stream_chain = stdout.stream_chain().map(const_hex::encode);
 
     info!(
         session_id = %session_id,
         public_key = req.public_key,
         execution_time_ms = start_time.elapsed().as_millis(),
-        status = "success",
-        msg = "WASM execution completed successfully"
+        cached = metrics.cached,
+        fuel_consumed = metrics.fuel_consumed,
+        peak_memory_bytes = metrics.peak_memory_bytes,
+        exit_code,
+        stdout_truncated = output.stdout_truncated,
+        stderr_truncated = output.stderr_truncated,
+        request_timestamp = signature.as_ref().map(|s| s.timestamp),
+        request_signature = signature.as_ref().map(|s| s.signature_hex()),
+        status = if exit_code == 0 { "success" } else { "failed" },
+        msg = "WASM execution completed"
     );
 
-    let output_nonce = crypto::derive_msg_nonce(&app_output);
+    let output_nonce = req.version.result_nonce(&output.stdout);
     let encrypted_result = {
         let encrypted = cipher
-            .encrypt(&output_nonce, app_output.as_ref())
+            .encrypt(&output_nonce, output.stdout.as_ref())
             .map_err(|e| anyhow!(e.to_string()))?;
 
         const_hex::encode(encrypted)
     };
 
-    let result_commitment = commitment::build_result_commitment(
-        &user_pk,
-        session_sk.verifying_key(),
-        session_id,
-        &req.encrypted_wasm,
-        &req.encrypted_arguments,
-        output_nonce,
-        &encrypted_result,
-    );
+    let encrypted_stderr = match exit_code {
+        0 => None,
+        _ => Some(const_hex::encode(req.version.seal(
+            &cipher,
+            session_id,
+            &output.stderr,
+        )?)),
+    };
+
+    let output_files = bundle::pack(&work_dir.host_path(OUTPUT_DIR), files_limit)
+        .context("pack output files")
+        .context(StatusCode::UNPROCESSABLE_ENTITY)?;
+    drop(work_dir);
+
+    let encrypted_files = match output_files {
+        Some(files) => Some(const_hex::encode(
+            req.version.seal(&cipher, session_id, &files)?,
+        )),
+        None => None,
+    };
 
-    let resp = ExecutionResponse {
+    let mut resp = ExecutionResponse {
+        executor: executor_kind,
+        runtime_version: runtime_version.to_string(),
         session_id,
         result_nonce: const_hex::encode(output_nonce),
         encrypted_result,
-        result_commitment: const_hex::encode(result_commitment),
+        result_commitment: String::new(),
+        fuel_consumed: metrics.fuel_consumed,
+        peak_memory_bytes: metrics.peak_memory_bytes,
+        exit_code,
+        stdout_truncated: output.stdout_truncated,
+        stderr_truncated: output.stderr_truncated,
+        encrypted_stderr,
+        encrypted_files,
+        stream_chain,
     };
+    let result_commitment =
+        commitment::build_result_commitment(&user_pk, session_sk.verifying_key(), &req, &resp);
+    resp.result_commitment = const_hex::encode(result_commitment);
 
-    Ok(axum::Json(resp))
+    Ok(resp)
 }
 
 /// Validate execution request
@@ -231,6 +588,10 @@ fn validate_execution_request(request: &ExecutionRequest) -> Result<(), Hypervis
             "encrypted_wasm cannot be empty"
         );
 
+        if let Some(function_name) = &request.function_name {
+            invoke::validate_name(function_name)?;
+        }
+
         // Validate public_key
         anyhow::ensure!(
             !(request.public_key.trim().is_empty()),
@@ -245,9 +606,189 @@ fn validate_execution_request(request: &ExecutionRequest) -> Result<(), Hypervis
     Ok(())
 }
 
+/// Runs components on the shared engine, as a command or by calling one
+/// of their functions
+pub struct WasmExecutor<'a> {
+    components: &'a ComponentCache,
+    limits: WasmLimits,
+    output_limit: usize,
+    /// Bytes of `/scratch` and `/output` together, measured while the guest
+    /// runs
+    files_limit: usize,
+    /// Export called with its params in place of `wasi:cli/run`, its JSON
+    /// return value becomes stdout
+    function: Option<(&'a str, Vec<serde_json::Value>)>,
+    work_dir: &'a WorkDir,
+    host: HostCtx,
+    /// Kept by the caller, for the chain of streamed frames
+    stdout: OutputCapture,
+}
+
+pub struct WasmMetrics {
+    pub fuel_consumed: u64,
+    pub peak_memory_bytes: u64,
+    /// Whether the component came compiled from the cache
+    pub cached: bool,
+}
+
+#[async_trait]
+impl<'a> Executor for WasmExecutor<'a> {
+    type Metrics = WasmMetrics;
+
+    fn kind(&self) -> ExecutorKind {
+        ExecutorKind::Wasm
+    }
+
+    fn runtime_version(&self) -> &'static str {
+        WASM_RUNTIME_VERSION
+    }
+
+    async fn execute(
+        self,
+        input: GuestInput,
+    ) -> Result<(GuestOutput, WasmMetrics), HypervisorError> {
+        let WasmExecutor {
+            components,
+            limits,
+            output_limit,
+            files_limit,
+            function,
+            work_dir,
+            host: host_ctx,
+            stdout,
+        } = self;
+
+        let engine = components.engine();
+        let mut linker = Linker::new(engine);
+        wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
+        host::Agent::add_to_linker::<_, HasSelf<HostCtx>>(&mut linker, |s| &mut s.host)?;
+
+        let stderr = OutputCapture::new(output_limit);
+        let mut wasi = WasiCtx::builder();
+        wasi.arg("wasm")
+            .args(&input.arguments)
+            .stdin(MemoryInputPipe::new(input.stdin))
+            .stdout(stdout.clone())
+            .stderr(stderr.clone());
+
+        wasi.preopened_dir(
+            work_dir.host_path(INPUT_DIR),
+            INPUT_DIR,
+            DirPerms::READ,
+            FilePerms::READ,
+        )?;
+        for dir in [SCRATCH_DIR, OUTPUT_DIR] {
+            wasi.preopened_dir(
+                work_dir.host_path(dir),
+                dir,
+                DirPerms::all(),
+                FilePerms::all(),
+            )?;
+        }
+        let wasi = wasi.build();
+
+        let run_states = ComponentRunStates {
+            wasi_ctx: wasi,
+            resource_table: ResourceTable::new(),
+            limiter: ExecutionLimiter::new(limits),
+            host: host_ctx,
+        };
+        let mut store = Store::new(engine, run_states);
+        store.limiter(|s| &mut s.limiter);
+        store.set_fuel(limits.fuel)?;
+        // Yielding lets the wall clock timeout fire, even on a busy runtime
+        store.fuel_async_yield_interval(Some(FUEL_YIELD_INTERVAL))?;
+
+        let (component, cached) = components.get_or_compile(&input.program)?;
+
+        let run = async {
+            match &function {
+                Some((name, params)) => {
+                    let instance = linker.instantiate_async(&mut store, &component).await?;
+                    let returned = invoke::call(&mut store, &instance, name, params).await?;
+
+                    anyhow::Ok((Ok(()), Some(returned)))
+                }
+                None => {
+                    let command =
+                        Command::instantiate_async(&mut store, &component, &linker).await?;
+                    let run = command.wasi_cli_run().call_run(&mut store).await?;
+
+                    anyhow::Ok((run, None))
+                }
+            }
+        };
+        // Dropping the run stops a guest caught past the files limit
+        let run = async {
+            tokio::select! {
+                run_result = run => Ok(run_result),
+                () = work_dir.files_limit_exceeded(files_limit) => Err(ExecutionLimit::Files),
+            }
+        };
+        let run_result = match tokio::time::timeout(limits.wall_clock(), run).await {
+            Ok(Ok(run_result)) => run_result,
+            Ok(Err(limit)) => return Err(HypervisorError::LimitExceeded(limit)),
+            Err(_) => return Err(HypervisorError::LimitExceeded(ExecutionLimit::WallClock)),
+        };
+
+        // Writes after the last check count too
+        let written = work_dir.written_bytes().context("measure guest files")?;
+        if written > files_limit as u64 {
+            return Err(HypervisorError::LimitExceeded(ExecutionLimit::Files));
+        }
+
+        let fuel_consumed = limits.fuel.saturating_sub(store.get_fuel()?);
+        let peak_memory_bytes = store.data().limiter.peak_memory_bytes as u64;
+
+        let (exit_code, returned) = match run_result {
+            Ok((Ok(()), returned)) => (0, returned),
+            Ok((Err(()), _)) => (1, None),
+            Err(e) => match e.downcast_ref::<I32Exit>() {
+                Some(I32Exit(code)) => (*code, None),
+                None => {
+                    if let Some(limit) = store.data().limiter.exceeded_by(&e) {
+                        return Err(HypervisorError::LimitExceeded(limit));
+                    }
+
+                    return Err(e
+                        .context("execute wasm")
+                        .context(StatusCode::BAD_REQUEST)
+                        .into());
+                }
+            },
+        };
+
+        let (stdout, stdout_truncated) = match returned {
+            Some(returned) => (
+                serde_json::to_vec(&returned).context("encode returned value")?,
+                false,
+            ),
+            None => stdout.contents(),
+        };
+        let (stderr, stderr_truncated) = stderr.contents();
+
+        let output = GuestOutput {
+            exit_code,
+            stdout,
+            stdout_truncated,
+            stderr,
+            stderr_truncated,
+        };
+        let metrics = WasmMetrics {
+            fuel_consumed,
+            peak_memory_bytes,
+            cached,
+        };
+
+        Ok((output, metrics))
+    }
+}
+
 struct ComponentRunStates {
     wasi_ctx: WasiCtx,
     resource_table: ResourceTable,
+    limiter: ExecutionLimiter,
+    host: HostCtx,
 }
 
 impl WasiView for ComponentRunStates {
@@ -264,13 +805,19 @@ mod tests {
     use aes_gcm_siv::{aead::Aead, Nonce};
 
     use crate::utils::crypto;
-    use crate::{api::RouterRegister, types::SessionKeyPairs};
+    use crate::{api::RouterRegister, session::SessionKeyPairs};
 
     use super::*;
 
     #[tokio::test]
     #[test_log::test]
     async fn test_api_execute_wasm() {
+        for version in [EnvelopeVersion::Legacy, EnvelopeVersion::Framed] {
+            execute_hello_wasm(version).await;
+        }
+    }
+
+    async fn execute_hello_wasm(version: EnvelopeVersion) {
         let wasm = include_bytes!("./hello.wasm");
         let session_key_pairs = SessionKeyPairs::default();
 
@@ -287,48 +834,334 @@ mod tests {
         let (session_pk, session_id) = session_key_pairs.create(user_pk);
         let cipher = crypto::create_encrypt_key(&sk, &session_pk, session_id).unwrap();
 
-        let nonce = crypto::derive_msg_nonce(session_id);
-        let encrypted_wasm = cipher.encrypt(&nonce, wasm.as_slice()).unwrap();
-        let encrypted_arguments = { vec!["tress".to_string()].into_iter() }
-            .map(|a| cipher.encrypt(&nonce, a.as_bytes()).unwrap())
+        let encrypted_wasm = version.seal(&cipher, session_id, wasm).unwrap();
+        let arguments = { vec!["tress".to_string()].into_iter() }
+            .map(|a| version.seal(&cipher, session_id, a.as_bytes()).unwrap())
             .map(const_hex::encode)
             .collect::<Vec<_>>();
+        let encrypted_stdin = version.seal(&cipher, session_id, &[0, 1, 2]).unwrap();
+
+        // Same argument, untyped and typed
+        let (encrypted_arguments, typed_arguments) = match version {
+            EnvelopeVersion::Legacy => (arguments, vec![]),
+            EnvelopeVersion::Framed => {
+                let typed = { arguments.into_iter() }
+                    .map(|encrypted| TypedArgument {
+                        kind: ArgumentKind::String,
+                        encrypted,
+                    })
+                    .collect();
+
+                (vec![], typed)
+            }
+        };
+
+        let req = ExecutionRequest {
+            version,
+            encrypted_wasm: const_hex::encode(&encrypted_wasm),
+            function_name: None,
+            encrypted_arguments,
+            typed_arguments,
+            encrypted_stdin: Some(const_hex::encode(encrypted_stdin)),
+            encrypted_bundle: None,
+            public_key: crypto::pk_to_hex(user_pk),
+            session_id: Some(session_id),
+            limits: RequestedLimits::default(),
+            capabilities: Capabilities::default(),
+        };
+        let response = server.post("/test/execute/wasm").json(&req).await;
+
+        response.assert_status_ok();
+
+        let result: ExecutionResponse = response.json();
+        let result_nonce = *Nonce::from_slice(&const_hex::decode(&result.result_nonce).unwrap());
+        let result_commitment =
+            commitment::build_result_commitment(user_pk, &session_pk, &req, &result);
+        assert_eq!(result.executor, ExecutorKind::Wasm);
+        assert!(result.encrypted_files.is_none());
+        assert_eq!(result.exit_code, 0);
+        assert!(result.fuel_consumed > 0);
+        assert!(result.peak_memory_bytes > 0);
+        assert_eq!(
+            result_commitment.as_slice(),
+            &const_hex::decode(result.result_commitment).unwrap(),
+            "invalid commitment"
+        );
+
+        let output = cipher
+            .decrypt(
+                &result_nonce,
+                { const_hex::decode(&result.encrypted_result).unwrap() }.as_slice(),
+            )
+            .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "Hello tress\n");
+    }
+
+    #[tokio::test]
+    async fn test_api_execute_wasm_stream() {
+        let wasm = include_bytes!("./hello.wasm");
+        let session_key_pairs = SessionKeyPairs::default();
+
+        let mut state = HypervisorState::default();
+        state.set_session_key_pairs(session_key_pairs.clone());
+
+        let server =
+            axum_test::TestServer::new(Router::new().register_api(api_register).with_state(state))
+                .unwrap();
+
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let (session_pk, session_id) = session_key_pairs.create(sk.verifying_key());
+        let cipher = crypto::create_encrypt_key(&sk, &session_pk, session_id).unwrap();
+
+        let mut req = ExecutionRequest {
+            version: EnvelopeVersion::Framed,
+            encrypted_wasm: String::new(),
+            function_name: None,
+            encrypted_arguments: vec![],
+            typed_arguments: vec![],
+            encrypted_stdin: None,
+            encrypted_bundle: None,
+            public_key: crypto::pk_to_hex(sk.verifying_key()),
+            session_id: Some(session_id),
+            limits: RequestedLimits::default(),
+            capabilities: Capabilities::default(),
+        };
+        for version in [EnvelopeVersion::Framed, EnvelopeVersion::Legacy] {
+            let seal =
+                |bytes: &[u8]| const_hex::encode(version.seal(&cipher, session_id, bytes).unwrap());
+            req.version = version;
+            req.encrypted_wasm = seal(wasm);
+            req.encrypted_arguments = vec![seal(b"tress")];
+
+            if version == EnvelopeVersion::Legacy {
+                let response = server.post("/test/execute/wasm/stream").json(&req).await;
+                response.assert_status_bad_request();
+                continue;
+            }
+
+            let response = server.post("/test/execute/wasm/stream").json(&req).await;
+            response.assert_status_ok();
+
+            let text = response.text();
+            let mut events = { text.split("\n\n").filter(|e| !e.trim().is_empty()) }
+                .map(|event| {
+                    let field = |name: &str| {
+                        { event.lines() }
+                            .find_map(|l| l.strip_prefix(name))
+                            .unwrap()
+                            .trim()
+                            .to_string()
+                    };
+
+                    (field("event:"), field("data:"))
+                })
+                .collect::<Vec<_>>();
+            let (last, data) = events.pop().unwrap();
+
+            // Frames replay into stdout and the chain
+            let mut chain = stream::chain_seed(session_id);
+            let mut stdout = vec![];
+            for (i, (event, data)) in events.iter().enumerate() {
+                assert_eq!(event, "frame");
+
+                let frame: stream::StdoutFrame = serde_json::from_str(data).unwrap();
+                assert_eq!(frame.index, i as u64);
+
+                let sealed = const_hex::decode(&frame.encrypted_chunk).unwrap();
+                chain = stream::chain_next(&chain, &sealed);
+                assert_eq!(frame.chain, const_hex::encode(chain));
+
+                stdout.extend(version.open(&cipher, session_id, &sealed).unwrap());
+            }
+            assert_eq!(String::from_utf8(stdout).unwrap(), "Hello tress\n");
+
+            // Without a TEE the result can't be attested
+            match last.as_str() {
+                "result" => {
+                    let resp: VerifiableExecutionResponse = serde_json::from_str(&data).unwrap();
+                    assert_eq!(resp.execution.stream_chain, Some(const_hex::encode(chain)));
+                }
+                _ => {
+                    assert_eq!(last, "error");
+                    let error: StreamError = serde_json::from_str(&data).unwrap();
+                    assert_eq!(error.status_code, 500);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_execute_wasm_fuel_limit() {
+        let wasm = include_bytes!("./hello.wasm");
+        let session_key_pairs = SessionKeyPairs::default();
+
+        let mut state = HypervisorState::default();
+        state.set_session_key_pairs(session_key_pairs.clone());
+
+        let server =
+            axum_test::TestServer::new(Router::new().register_api(api_register).with_state(state))
+                .unwrap();
+
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let (session_pk, session_id) = session_key_pairs.create(sk.verifying_key());
+        let cipher = crypto::create_encrypt_key(&sk, &session_pk, session_id).unwrap();
+
+        let version = EnvelopeVersion::Framed;
+        let encrypted_wasm = version.seal(&cipher, session_id, wasm).unwrap();
 
         let response = server
             .post("/test/execute/wasm")
             .json(&ExecutionRequest {
+                version,
                 encrypted_wasm: const_hex::encode(&encrypted_wasm),
-                encrypted_arguments: encrypted_arguments.clone(),
-                public_key: crypto::pk_to_hex(user_pk),
+                function_name: None,
+                encrypted_arguments: vec![],
+                typed_arguments: vec![],
+                encrypted_stdin: None,
+                encrypted_bundle: None,
+                public_key: crypto::pk_to_hex(sk.verifying_key()),
+                session_id: Some(session_id),
+                limits: RequestedLimits {
+                    fuel: Some(1_000),
+                    ..Default::default()
+                },
+                capabilities: Capabilities::default(),
             })
             .await;
 
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+        let error: serde_json::Value = response.json();
+        assert_eq!(error["limit"], "fuel");
+    }
+
+    #[tokio::test]
+    async fn test_api_execute_wasm_capabilities() {
+        let wasm = include_bytes!("./hello.wasm");
+        let session_key_pairs = SessionKeyPairs::default();
+
+        let mut state = HypervisorState::default();
+        state.config.wasm_http_allowlist = vec!["example.com".to_string()];
+        state.set_session_key_pairs(session_key_pairs.clone());
+
+        let server =
+            axum_test::TestServer::new(Router::new().register_api(api_register).with_state(state))
+                .unwrap();
+
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let (session_pk, session_id) = session_key_pairs.create(sk.verifying_key());
+        let cipher = crypto::create_encrypt_key(&sk, &session_pk, session_id).unwrap();
+
+        let version = EnvelopeVersion::Framed;
+        let encrypted_wasm = version.seal(&cipher, session_id, wasm).unwrap();
+
+        let request = |http: &str| ExecutionRequest {
+            version,
+            encrypted_wasm: const_hex::encode(&encrypted_wasm),
+            function_name: None,
+            encrypted_arguments: vec![],
+            typed_arguments: vec![],
+            encrypted_stdin: None,
+            encrypted_bundle: None,
+            public_key: crypto::pk_to_hex(sk.verifying_key()),
+            session_id: Some(session_id),
+            limits: RequestedLimits::default(),
+            capabilities: Capabilities {
+                http: vec![http.to_string()],
+                kv: true,
+                sign: true,
+            },
+        };
+
+        let req = request("example.com");
+        let response = server.post("/test/execute/wasm").json(&req).await;
         response.assert_status_ok();
 
+        // Granted capabilities are committed
         let result: ExecutionResponse = response.json();
-        let result_nonce = *Nonce::from_slice(&const_hex::decode(result.result_nonce).unwrap());
-        let result_commitment = commitment::build_result_commitment(
-            user_pk,
-            &session_pk,
-            session_id,
-            &const_hex::encode(encrypted_wasm),
-            &encrypted_arguments,
-            result_nonce,
-            &result.encrypted_result,
-        );
+        let result_commitment = |req: &ExecutionRequest| {
+            commitment::build_result_commitment(sk.verifying_key(), &session_pk, req, &result)
+        };
+        let committed = const_hex::decode(&result.result_commitment).unwrap();
+        assert_eq!(result_commitment(&req).as_slice(), &committed);
+
+        let ungranted = ExecutionRequest {
+            capabilities: Capabilities::default(),
+            ..req
+        };
+        assert_ne!(result_commitment(&ungranted).as_slice(), &committed);
+
+        let response = server
+            .post("/test/execute/wasm")
+            .json(&request("example.org"))
+            .await;
+        response.assert_status(StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_api_execute_wasm_function() {
+        let wasm = include_bytes!("./hello.wasm");
+        let session_key_pairs = SessionKeyPairs::default();
+
+        let mut state = HypervisorState::default();
+        state.set_session_key_pairs(session_key_pairs.clone());
+
+        let server =
+            axum_test::TestServer::new(Router::new().register_api(api_register).with_state(state))
+                .unwrap();
+
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let (session_pk, session_id) = session_key_pairs.create(sk.verifying_key());
+        let cipher = crypto::create_encrypt_key(&sk, &session_pk, session_id).unwrap();
+
+        let version = EnvelopeVersion::Framed;
+        let encrypted_wasm = version.seal(&cipher, session_id, wasm).unwrap();
+
+        let request = |function_name: &str| ExecutionRequest {
+            version,
+            encrypted_wasm: const_hex::encode(&encrypted_wasm),
+            function_name: Some(function_name.to_string()),
+            encrypted_arguments: vec![],
+            typed_arguments: vec![],
+            encrypted_stdin: None,
+            encrypted_bundle: None,
+            public_key: crypto::pk_to_hex(sk.verifying_key()),
+            session_id: Some(session_id),
+            limits: RequestedLimits::default(),
+            capabilities: Capabilities::default(),
+        };
+
+        // The run export of a command is a function like any other
+        let req = request("wasi:cli/run@0.2.6#run");
+        let response = server.post("/test/execute/wasm").json(&req).await;
+        response.assert_status_ok();
+
+        let result: ExecutionResponse = response.json();
+        let result_commitment =
+            commitment::build_result_commitment(sk.verifying_key(), &session_pk, &req, &result);
         assert_eq!(
             result_commitment.as_slice(),
-            &const_hex::decode(result.result_commitment).unwrap(),
-            "invalid commitment"
+            &const_hex::decode(&result.result_commitment).unwrap()
         );
 
         let output = cipher
             .decrypt(
-                &result_nonce,
+                Nonce::from_slice(&const_hex::decode(&result.result_nonce).unwrap()),
                 { const_hex::decode(&result.encrypted_result).unwrap() }.as_slice(),
             )
             .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&output).unwrap(),
+            serde_json::json!({"ok": null})
+        );
 
-        assert_eq!(String::from_utf8(output).unwrap(), "Hello tress\n");
+        for function_name in ["wasi:cli/run@0.2.6#walk", "run two"] {
+            let response = server
+                .post("/test/execute/wasm")
+                .json(&request(function_name))
+                .await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+        }
     }
 }
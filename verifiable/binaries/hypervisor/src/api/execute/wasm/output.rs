@@ -0,0 +1,176 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
+
+use tokio::io::AsyncWrite;
+use wasmtime_wasi::cli::{IsTerminal, StdoutStream};
+
+use super::stream::FrameSealer;
+
+pub const DEFAULT_WASM_OUTPUT_LIMIT_BYTES: usize = 1024 * 1024;
+
+/// Guest output kept up to a limit
+///
+/// Writes past the limit still succeed, so the guest runs on, their bytes
+/// are dropped and the capture is marked truncated. Kept bytes are also
+/// sealed into frames when streamed, writes wait while the frame backlog is
+/// full.
+#[derive(Clone)]
+pub struct OutputCapture {
+    captured: Arc<Mutex<Captured>>,
+    limit: usize,
+}
+
+#[derive(Default)]
+struct Captured {
+    bytes: Vec<u8>,
+    truncated: bool,
+    frames: Option<FrameSealer>,
+}
+
+impl OutputCapture {
+    pub fn new(limit: usize) -> Self {
+        OutputCapture {
+            captured: Arc::default(),
+            limit,
+        }
+    }
+
+    /// Stream kept bytes through `sealer` as they're written
+    pub fn with_frames(self, sealer: FrameSealer) -> Self {
+        self.lock().frames = Some(sealer);
+        self
+    }
+
+    /// Chain after the last frame, if streamed
+    pub fn stream_chain(&self) -> Option<[u8; 32]> {
+        self.lock().frames.as_ref().map(FrameSealer::chain)
+    }
+
+    /// Captured bytes, and whether any were dropped
+    pub fn contents(&self) -> (Vec<u8>, bool) {
+        let captured = self.lock();
+
+        (captured.bytes.clone(), captured.truncated)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Captured> {
+        self.captured.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl IsTerminal for OutputCapture {
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+impl StdoutStream for OutputCapture {
+    fn async_stream(&self) -> Box<dyn AsyncWrite + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+impl AsyncWrite for OutputCapture {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut captured = self.lock();
+
+        let kept = buf
+            .len()
+            .min(self.limit.saturating_sub(captured.bytes.len()));
+
+        if let Some(frames) = captured.frames.as_mut().filter(|_| kept > 0) {
+            ready!(frames.poll_ready(cx));
+            frames.push(&buf[..kept]).map_err(io::Error::other)?;
+        }
+
+        captured.bytes.extend_from_slice(&buf[..kept]);
+        captured.truncated |= kept < buf.len();
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::{
+        api::execute::wasm::stream::{StreamEvent, STREAM_FRAME_BACKLOG},
+        utils::crypto::{self, EnvelopeVersion},
+    };
+
+    #[tokio::test]
+    async fn test_output_capture() {
+        let output = OutputCapture::new(8);
+
+        let mut stream = output.clone();
+        stream.write_all(b"hello").await.unwrap();
+        assert_eq!(output.contents(), (b"hello".to_vec(), false));
+
+        stream.write_all(b" world").await.unwrap();
+        assert_eq!(output.contents(), (b"hello wo".to_vec(), true));
+    }
+
+    #[tokio::test]
+    async fn test_output_frames() {
+        let session_id = uuid::Uuid::now_v7();
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let cipher = crypto::create_encrypt_key(&sk, sk.verifying_key(), session_id).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(STREAM_FRAME_BACKLOG);
+        let sealer = FrameSealer::new(cipher, EnvelopeVersion::Framed, session_id, tx);
+        let output = OutputCapture::new(8).with_frames(sealer);
+
+        let mut stream = output.clone();
+        stream.write_all(b"hello").await.unwrap();
+        stream.write_all(b" world").await.unwrap();
+        stream.write_all(b"!").await.unwrap();
+
+        // Only kept bytes are framed
+        assert!(matches!(rx.try_recv(), Ok(StreamEvent::Frame(f)) if f.index == 0));
+        assert!(matches!(rx.try_recv(), Ok(StreamEvent::Frame(f)) if f.index == 1));
+        assert!(rx.try_recv().is_err());
+        assert!(output.stream_chain().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_output_frame_backlog() {
+        let session_id = uuid::Uuid::now_v7();
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let cipher = crypto::create_encrypt_key(&sk, sk.verifying_key(), session_id).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let sealer = FrameSealer::new(cipher, EnvelopeVersion::Framed, session_id, tx);
+        let output = OutputCapture::new(64).with_frames(sealer);
+
+        let mut stream = output.clone();
+        stream.write_all(b"hello").await.unwrap();
+
+        // A full backlog holds the write back without keeping its bytes
+        assert!(stream.write_all(b" world").now_or_never().is_none());
+        assert_eq!(output.contents(), (b"hello".to_vec(), false));
+
+        assert!(matches!(rx.recv().await, Some(StreamEvent::Frame(f)) if f.index == 0));
+        stream.write_all(b" world").await.unwrap();
+        assert!(matches!(rx.recv().await, Some(StreamEvent::Frame(f)) if f.index == 1));
+        assert_eq!(output.contents(), (b"hello world".to_vec(), false));
+    }
+}
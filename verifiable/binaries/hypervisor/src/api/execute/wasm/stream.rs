@@ -0,0 +1,201 @@
+//! Stdout of an execution sent as it's written, over server-sent events
+//!
+//! Each chunk is sealed into a frame, and frames are chained: the chain
+//! starts at [`chain_seed`] and every frame advances it with
+//! [`chain_next`]. The final `result` event is the verifiable response, its
+//! `stream_chain` is the chain after the last frame and part of the
+//! commitment, so a client holding every frame can check none was dropped,
+//! reordered or altered.
+//!
+//! At most [`STREAM_FRAME_BACKLOG`] frames wait for a client, guest writes
+//! past it wait in turn, bounded by the wall clock limit of the execution.
+
+use std::{
+    convert::Infallible,
+    task::{Context, Poll},
+};
+
+use aes_gcm_siv::Aes256GcmSiv;
+use axum::response::sse::Event;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_util::sync::PollSender;
+use uuid::Uuid;
+
+use super::VerifiableExecutionResponse;
+use crate::{
+    error::ErrorResponse,
+    utils::{crypto::EnvelopeVersion, hasher},
+};
+
+/// Hashed with the session id into the first link of a chain
+pub const STREAM_CHAIN_DOMAIN: &[u8] = b"x402:stream";
+
+/// Events queued for a client before the guest is held back
+pub const STREAM_FRAME_BACKLOG: usize = 64;
+
+/// One chunk of stdout
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StdoutFrame {
+    pub index: u64,
+    /// The chunk, sealed like the request fields
+    pub encrypted_chunk: String,
+    /// Chain after this frame
+    pub chain: String,
+}
+
+/// An execution failed after the stream started, as the endpoint would have
+/// answered
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StreamError {
+    pub status_code: u16,
+    pub error: ErrorResponse,
+}
+
+pub enum StreamEvent {
+    Frame(StdoutFrame),
+    Result(Box<VerifiableExecutionResponse>),
+    Error(StreamError),
+}
+
+impl StreamEvent {
+    fn into_sse(self) -> Event {
+        let event = match self {
+            StreamEvent::Frame(frame) => Event::default().event("frame").json_data(frame),
+            StreamEvent::Result(resp) => Event::default().event("result").json_data(resp),
+            StreamEvent::Error(error) => Event::default().event("error").json_data(error),
+        };
+
+        event.expect("impossible")
+    }
+}
+
+pub fn chain_seed(session_id: Uuid) -> [u8; 32] {
+    hasher::hash_multi(&[STREAM_CHAIN_DOMAIN, session_id.as_bytes()])
+}
+
+/// Chain after a frame, over the sealed bytes of its chunk
+pub fn chain_next(chain: &[u8; 32], encrypted_chunk: &[u8]) -> [u8; 32] {
+    hasher::hash_multi(&[chain.as_slice(), encrypted_chunk])
+}
+
+/// Seals stdout chunks into chained frames
+pub struct FrameSealer {
+    cipher: Aes256GcmSiv,
+    version: EnvelopeVersion,
+    session_id: Uuid,
+    chain: [u8; 32],
+    next_index: u64,
+    events: PollSender<StreamEvent>,
+}
+
+impl FrameSealer {
+    pub fn new(
+        cipher: Aes256GcmSiv,
+        version: EnvelopeVersion,
+        session_id: Uuid,
+        events: mpsc::Sender<StreamEvent>,
+    ) -> Self {
+        FrameSealer {
+            cipher,
+            version,
+            session_id,
+            chain: chain_seed(session_id),
+            next_index: 0,
+            events: PollSender::new(events),
+        }
+    }
+
+    /// Wait for room for the next frame, to be called before each
+    /// [`FrameSealer::push`]
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        // A gone client stops the execution, not the sealer
+        self.events.poll_reserve(cx).map(|_| ())
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> anyhow::Result<()> {
+        let sealed = self.version.seal(&self.cipher, self.session_id, chunk)?;
+        self.chain = chain_next(&self.chain, &sealed);
+
+        let frame = StdoutFrame {
+            index: self.next_index,
+            encrypted_chunk: const_hex::encode(sealed),
+            chain: const_hex::encode(self.chain),
+        };
+        self.next_index += 1;
+
+        let _ = self.events.send_item(StreamEvent::Frame(frame));
+
+        Ok(())
+    }
+
+    pub fn chain(&self) -> [u8; 32] {
+        self.chain
+    }
+}
+
+/// Server-sent events of `events`, `execution` is aborted when the client
+/// goes away
+pub fn sse_events(
+    events: mpsc::Receiver<StreamEvent>,
+    execution: JoinHandle<()>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    struct AbortOnDrop(JoinHandle<()>);
+
+    impl Drop for AbortOnDrop {
+        fn drop(&mut self) {
+            self.0.abort();
+        }
+    }
+
+    let state = (events, AbortOnDrop(execution));
+    futures_util::stream::unfold(state, |(mut events, execution)| async move {
+        let event = events.recv().await?;
+
+        Some((Ok(event.into_sse()), (events, execution)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_chain() {
+        let cipher = crate::utils::crypto::create_encrypt_key(
+            &k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng),
+            k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng).verifying_key(),
+            Uuid::now_v7(),
+        )
+        .unwrap();
+        let session_id = Uuid::now_v7();
+        let version = EnvelopeVersion::Framed;
+
+        let (tx, mut rx) = mpsc::channel(STREAM_FRAME_BACKLOG);
+        let mut sealer = FrameSealer::new(cipher.clone(), version, session_id, tx);
+        for chunk in [b"hello ".as_slice(), b"world"] {
+            std::future::poll_fn(|cx| sealer.poll_ready(cx)).await;
+            sealer.push(chunk).unwrap();
+        }
+
+        // Replay the chain from the frames alone
+        let mut chain = chain_seed(session_id);
+        let mut stdout = vec![];
+        for index in 0..2 {
+            let Ok(StreamEvent::Frame(frame)) = rx.try_recv() else {
+                panic!("frame {index} missing");
+            };
+            assert_eq!(frame.index, index);
+
+            let sealed = const_hex::decode(&frame.encrypted_chunk).unwrap();
+            chain = chain_next(&chain, &sealed);
+            assert_eq!(frame.chain, const_hex::encode(chain));
+
+            stdout.extend(version.open(&cipher, session_id, &sealed).unwrap());
+        }
+
+        assert_eq!(stdout, b"hello world");
+        assert_eq!(sealer.chain(), chain);
+    }
+}
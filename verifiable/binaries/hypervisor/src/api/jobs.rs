@@ -0,0 +1,635 @@
+//! Executions run in the background, for guests that take longer than a
+//! proxy waits for a response
+//!
+//! A job is authorized when it's submitted, its result is the response the
+//! matching `/verifiable/*` endpoint would have answered with. Only the key
+//! that submitted a job may read or cancel it, with the request signature
+//! headers of [`crate::utils::auth`] made over [`job_request_message`].
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, Method, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use dashmap::DashMap;
+use k256::ecdsa::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{watch, OwnedSemaphorePermit, Semaphore},
+    task::AbortHandle,
+};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        execute::{riscv, wasm},
+        policy::python,
+    },
+    error::{ErrorResponse, HypervisorError},
+    types::HypervisorState,
+    utils::{
+        auth::{self, RequestSignature, SignedJson},
+        crypto,
+        x402::{self, X402_FIXED_PRICE_USDC},
+    },
+};
+
+pub const DEFAULT_JOB_WORKERS: usize = 4;
+pub const DEFAULT_JOB_QUEUE_DEPTH: usize = 64;
+pub const DEFAULT_JOB_RETENTION_SECS: u64 = 60 * 60;
+
+pub(crate) fn api_register(router: Router<HypervisorState>) -> Router<HypervisorState> {
+    router
+        .route("/test/jobs", post(submit_job))
+        .route("/jobs/{job_id}", get(get_job).delete(cancel_job))
+}
+
+pub(crate) fn api_x402_register(
+    router: Router<HypervisorState>,
+    state: HypervisorState,
+) -> Router<HypervisorState> {
+    let x402_router = Router::new()
+        .route("/jobs", post(submit_job))
+        .layer(x402::create_x402_middleware(X402_FIXED_PRICE_USDC))
+        .with_state(state);
+
+    router.merge(x402_router)
+}
+
+/// Execution to run, the body of the matching endpoint plus its `kind`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobRequest {
+    Wasm(wasm::ExecutionRequest),
+    Python(python::ExecutionRequest),
+    Riscv(riscv::ExecutionRequest),
+}
+
+impl JobRequest {
+    /// Key and session the request names
+    fn owner(&self) -> Result<JobOwner, HypervisorError> {
+        let (public_key, session_id) = match self {
+            JobRequest::Wasm(req) => (&req.public_key, req.session_id),
+            JobRequest::Python(req) => (&req.public_key, req.session_id),
+            JobRequest::Riscv(req) => (&req.public_key, req.session_id),
+        };
+
+        let public_key = crypto::pk_from_hex(public_key)
+            .context("decode request pubkey")
+            .context(StatusCode::BAD_REQUEST)?;
+
+        Ok(JobOwner {
+            public_key,
+            session_id,
+        })
+    }
+}
+
+/// Client a job answers to
+#[derive(Clone, Debug)]
+pub struct JobOwner {
+    pub public_key: VerifyingKey,
+    /// Session the job was submitted with, the latest of `public_key` when
+    /// `None`
+    pub session_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubmitJobResponse {
+    pub job_id: Uuid,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobResult {
+    Wasm(wasm::VerifiableExecutionResponse),
+    Python(python::VerifiableExecutionResponse),
+    Riscv(riscv::VerifiableExecutionResponse),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded {
+        result: JobResult,
+    },
+    /// `status_code` and `error` are what the endpoint would have answered
+    Failed {
+        status_code: u16,
+        error: ErrorResponse,
+    },
+    Cancelled,
+}
+
+impl JobStatus {
+    fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// Room for one more job, taken before the submission is authorized so a
+/// full queue doesn't spend its signature
+pub struct JobSlot(OwnedSemaphorePermit);
+
+struct Job {
+    owner: JobOwner,
+    status: watch::Sender<JobStatus>,
+    abort: Option<AbortHandle>,
+    finished_at: Option<Instant>,
+}
+
+/// Submitted jobs, at most `workers` running and `queue_depth` waiting
+pub struct Jobs {
+    jobs: DashMap<Uuid, Job>,
+    /// Queued and running jobs together
+    slots: Arc<Semaphore>,
+    workers: Arc<Semaphore>,
+    /// Finished jobs are kept this long for polling
+    retention: Duration,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Jobs::new(
+            DEFAULT_JOB_WORKERS,
+            DEFAULT_JOB_QUEUE_DEPTH,
+            Duration::from_secs(DEFAULT_JOB_RETENTION_SECS),
+        )
+    }
+}
+
+impl Jobs {
+    pub fn new(workers: usize, queue_depth: usize, retention: Duration) -> Self {
+        Jobs {
+            jobs: DashMap::new(),
+            slots: Arc::new(Semaphore::new(workers + queue_depth)),
+            workers: Arc::new(Semaphore::new(workers)),
+            retention,
+        }
+    }
+
+    /// Refused with 429 when the queue is full
+    pub fn reserve(&self) -> Result<JobSlot, HypervisorError> {
+        let permit = { self.slots.clone().try_acquire_owned() }
+            .map_err(|_| anyhow!("job queue full"))
+            .context(StatusCode::TOO_MANY_REQUESTS)?;
+
+        Ok(JobSlot(permit))
+    }
+
+    /// Queue `job` of `owner` until a worker is free
+    pub fn submit<F>(self: &Arc<Self>, slot: JobSlot, owner: JobOwner, job: F) -> Uuid
+    where
+        F: Future<Output = Result<JobResult, HypervisorError>> + Send + 'static,
+    {
+        let job_id = Uuid::now_v7();
+        self.jobs.insert(
+            job_id,
+            Job {
+                owner,
+                status: watch::Sender::new(JobStatus::Queued),
+                abort: None,
+                finished_at: None,
+            },
+        );
+
+        let jobs = self.clone();
+        let task = tokio::spawn(async move {
+            let JobSlot(_slot) = slot;
+            let _worker = { jobs.workers.clone().acquire_owned().await }.expect("workers closed");
+
+            match jobs.jobs.get(&job_id) {
+                Some(job) if matches!(*job.status.borrow(), JobStatus::Queued) => {
+                    job.status.send_replace(JobStatus::Running);
+                }
+                _ => return,
+            }
+
+            let status = match job.await {
+                Ok(result) => JobStatus::Succeeded { result },
+                Err(e) => {
+                    let (status_code, error) = e.into_parts();
+                    JobStatus::Failed {
+                        status_code: status_code.as_u16(),
+                        error,
+                    }
+                }
+            };
+
+            // A cancelled job keeps its status
+            if let Some(mut job) = jobs.jobs.get_mut(&job_id) {
+                if matches!(*job.status.borrow(), JobStatus::Running) {
+                    job.status.send_replace(status);
+                    job.finished_at = Some(Instant::now());
+                }
+            }
+        });
+
+        if let Some(mut job) = self.jobs.get_mut(&job_id) {
+            job.abort = Some(task.abort_handle());
+        }
+
+        job_id
+    }
+
+    pub fn status(&self, job_id: Uuid) -> Option<JobStatus> {
+        self.jobs
+            .get(&job_id)
+            .map(|job| job.status.borrow().clone())
+    }
+
+    /// Status changes of a job, until it's forgotten
+    pub fn watch(&self, job_id: Uuid) -> Option<watch::Receiver<JobStatus>> {
+        self.jobs.get(&job_id).map(|job| job.status.subscribe())
+    }
+
+    pub fn owner(&self, job_id: Uuid) -> Option<JobOwner> {
+        self.jobs.get(&job_id).map(|job| job.owner.clone())
+    }
+
+    /// Stop a pending job, or forget a finished one. Returns false if the
+    /// job isn't known
+    pub fn cancel(&self, job_id: Uuid) -> bool {
+        let Some(mut job) = self.jobs.get_mut(&job_id) else {
+            return false;
+        };
+
+        if job.status.borrow().is_finished() {
+            drop(job);
+            self.jobs.remove(&job_id);
+            return true;
+        }
+
+        if let Some(abort) = job.abort.take() {
+            abort.abort();
+        }
+        job.status.send_replace(JobStatus::Cancelled);
+        job.finished_at = Some(Instant::now());
+
+        true
+    }
+
+    /// Drop jobs finished longer than the retention ago, returns how many
+    pub fn reap(&self) -> usize {
+        let before = self.jobs.len();
+
+        self.jobs.retain(|_, job| match job.finished_at {
+            Some(finished_at) => finished_at.elapsed() < self.retention,
+            None => true,
+        });
+
+        before.saturating_sub(self.jobs.len())
+    }
+}
+
+#[tracing::instrument(skip(state, req, signature), err)]
+async fn submit_job(
+    State(state): State<HypervisorState>,
+    SignedJson {
+        body: req,
+        signature,
+    }: SignedJson<JobRequest>,
+) -> Result<(StatusCode, Json<SubmitJobResponse>), HypervisorError> {
+    let slot = state.jobs().reserve()?;
+    let job_state = state.clone();
+    let owner = req.owner()?;
+    let session_id = owner.session_id;
+
+    let job_id = match req {
+        JobRequest::Wasm(req) => {
+            wasm::authorize_execution(&state, &req, signature.as_ref())?;
+
+            state.jobs().submit(slot, owner, async move {
+                let resp = wasm::run_verifiable_execution(&job_state, req, signature, None).await?;
+                Ok(JobResult::Wasm(resp))
+            })
+        }
+        JobRequest::Python(req) => {
+            python::authorize_execution(&state, &req, signature.as_ref())?;
+
+            state.jobs().submit(slot, owner, async move {
+                let resp = python::run_verifiable_execution(&job_state, req, signature).await?;
+                Ok(JobResult::Python(resp))
+            })
+        }
+        JobRequest::Riscv(req) => {
+            riscv::authorize_execution(&state, &req, signature.as_ref())?;
+
+            state.jobs().submit(slot, owner, async move {
+                let resp = riscv::run_verifiable_execution(&job_state, req, signature).await?;
+                Ok(JobResult::Riscv(resp))
+            })
+        }
+    };
+
+    info!(%job_id, session_id = ?session_id, "job submitted");
+
+    Ok((StatusCode::ACCEPTED, Json(SubmitJobResponse { job_id })))
+}
+
+/// What the owner signs, in place of a body, to read or cancel a job
+pub fn job_request_message(method: &Method, job_id: Uuid) -> String {
+    format!("{method} /jobs/{job_id}")
+}
+
+/// Owner of a job along with the signature of the request, which needs to
+/// be checked against the owner key
+fn owner_signature(
+    state: &HypervisorState,
+    method: &Method,
+    job_id: Uuid,
+    headers: &HeaderMap,
+) -> Result<(JobOwner, RequestSignature), HypervisorError> {
+    let owner = { state.jobs().owner(job_id) }
+        .ok_or(anyhow!("job not found"))
+        .context(StatusCode::NOT_FOUND)?;
+
+    let message = job_request_message(method, job_id);
+    let signature = RequestSignature::from_message(headers, message.as_bytes())?
+        .ok_or(anyhow!("request signature required"))
+        .context(StatusCode::UNAUTHORIZED)?;
+
+    Ok((owner, signature))
+}
+
+async fn get_job(
+    State(state): State<HypervisorState>,
+    Path(job_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<JobStatus>, HypervisorError> {
+    // Reads change nothing, polling may repeat a signature
+    let (owner, signature) = owner_signature(&state, &Method::GET, job_id, &headers)?;
+    auth::verify(&state, &owner.public_key, &signature)?;
+
+    let status = { state.jobs().status(job_id) }
+        .ok_or(anyhow!("job not found"))
+        .context(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(status))
+}
+
+async fn cancel_job(
+    State(state): State<HypervisorState>,
+    Path(job_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<StatusCode, HypervisorError> {
+    let (owner, signature) = owner_signature(&state, &Method::DELETE, job_id, &headers)?;
+    auth::authenticate(&state, &owner.public_key, Some(&signature))?;
+
+    if !state.jobs().cancel(job_id) {
+        return Err(anyhow!("job not found")
+            .context(StatusCode::NOT_FOUND)
+            .into());
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::{signature::Signer, Signature, SigningKey};
+    use tokio::sync::oneshot;
+
+    use crate::{
+        api::RouterRegister,
+        session::SessionKeyPairs,
+        utils::{
+            auth::{request_digest, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+            commitment::ExecutorKind,
+            crypto::{self, EnvelopeVersion},
+        },
+    };
+
+    use super::*;
+
+    async fn wait_for(jobs: &Jobs, job_id: Uuid, done: fn(&JobStatus) -> bool) -> JobStatus {
+        let mut status = jobs.watch(job_id).expect("job not found");
+        let status = status.wait_for(done).await.expect("job forgotten");
+
+        status.clone()
+    }
+
+    async fn wait_finished(jobs: &Jobs, job_id: Uuid) -> JobStatus {
+        wait_for(jobs, job_id, JobStatus::is_finished).await
+    }
+
+    fn owner(sk: &SigningKey) -> JobOwner {
+        JobOwner {
+            public_key: *sk.verifying_key(),
+            session_id: None,
+        }
+    }
+
+    fn signed_headers(
+        sk: &SigningKey,
+        method: Method,
+        job_id: Uuid,
+    ) -> [(&'static str, String); 2] {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let message = job_request_message(&method, job_id);
+        let signature: Signature = sk.sign(&request_digest(timestamp, message.as_bytes()));
+
+        [
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (SIGNATURE_HEADER, const_hex::encode(signature.to_bytes())),
+        ]
+    }
+
+    fn python_result() -> JobResult {
+        JobResult::Python(python::VerifiableExecutionResponse {
+            execution: python::ExecutionResponse {
+                executor: ExecutorKind::Python,
+                runtime_version: "cpython 3.11.7".to_string(),
+                session_id: Uuid::now_v7(),
+                msg_nonce: "00".to_string(),
+                encrypted_result: "00".to_string(),
+                result_commitment: "00".to_string(),
+                exit_code: 0,
+                result_stream: python::ResultStream::Stdout,
+            },
+            result_quote: "00".to_string(),
+            result_signature: None,
+        })
+    }
+
+    #[test]
+    fn test_job_result_kind() {
+        let json = serde_json::to_value(python_result()).unwrap();
+        assert_eq!(json["kind"], "python");
+
+        let result: JobResult = serde_json::from_value(json).unwrap();
+        assert!(matches!(result, JobResult::Python(_)));
+    }
+
+    #[tokio::test]
+    async fn test_jobs() {
+        let jobs = Arc::new(Jobs::new(1, 1, Duration::ZERO));
+        let owner = owner(&SigningKey::random(&mut rand::rngs::OsRng));
+
+        let succeeded = jobs.submit(jobs.reserve().unwrap(), owner.clone(), async {
+            Ok(python_result())
+        });
+        assert!(matches!(
+            wait_finished(&jobs, succeeded).await,
+            JobStatus::Succeeded { .. }
+        ));
+
+        let failed = jobs.submit(jobs.reserve().unwrap(), owner.clone(), async {
+            Err(anyhow!("no").context(StatusCode::BAD_REQUEST).into())
+        });
+        assert!(matches!(
+            wait_finished(&jobs, failed).await,
+            JobStatus::Failed {
+                status_code: 400,
+                ..
+            }
+        ));
+
+        // One running, one queued, no room for a third
+        let (dropped_tx, dropped) = oneshot::channel::<()>();
+        let running = jobs.submit(jobs.reserve().unwrap(), owner.clone(), async move {
+            let _dropped = dropped_tx;
+            std::future::pending().await
+        });
+        wait_for(&jobs, running, |s| matches!(s, JobStatus::Running)).await;
+        let queued = jobs.submit(
+            jobs.reserve().unwrap(),
+            owner.clone(),
+            std::future::pending(),
+        );
+        assert!(jobs.reserve().is_err());
+
+        assert!(jobs.cancel(queued));
+        assert!(matches!(jobs.status(queued), Some(JobStatus::Cancelled)));
+        assert!(matches!(jobs.status(running), Some(JobStatus::Running)));
+
+        // Cancelled tasks give back their slot and worker once dropped
+        assert!(jobs.cancel(running));
+        assert!(dropped.await.is_err());
+        let next = jobs.submit(jobs.reserve().unwrap(), owner, async {
+            Ok(python_result())
+        });
+        assert!(matches!(
+            wait_finished(&jobs, next).await,
+            JobStatus::Succeeded { .. }
+        ));
+
+        assert!(jobs.cancel(next));
+        assert!(jobs.status(next).is_none());
+        assert!(!jobs.cancel(next));
+
+        assert_eq!(jobs.reap(), 4);
+        assert!(jobs.status(succeeded).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_api_jobs() {
+        let session_key_pairs = SessionKeyPairs::default();
+
+        let mut state = HypervisorState::default();
+        state.set_session_key_pairs(session_key_pairs.clone());
+
+        let server = axum_test::TestServer::new(
+            Router::new()
+                .register_api(api_register)
+                .with_state(state.clone()),
+        )
+        .unwrap();
+
+        let sk = SigningKey::random(&mut rand::rngs::OsRng);
+        let user_pk = sk.verifying_key();
+
+        // Authorized at submission, the unknown session fails the job
+        let response = server
+            .post("/test/jobs")
+            .json(&JobRequest::Python(python::ExecutionRequest {
+                version: EnvelopeVersion::Framed,
+                encrypted_python: "00".to_string(),
+                encrypted_arguments: vec![],
+                public_key: crypto::pk_to_hex(user_pk),
+                session_id: Some(Uuid::now_v7()),
+            }))
+            .await;
+        response.assert_status(StatusCode::ACCEPTED);
+        let job_id = response.json::<SubmitJobResponse>().job_id;
+
+        wait_finished(state.jobs(), job_id).await;
+        let path = format!("/jobs/{job_id}");
+
+        // Only the submitting key reads or cancels the job
+        server.get(&path).await.assert_status_unauthorized();
+        let other = SigningKey::random(&mut rand::rngs::OsRng);
+        let [(ts_name, ts), (sig_name, sig)] = signed_headers(&other, Method::GET, job_id);
+        server
+            .get(&path)
+            .add_header(ts_name, ts)
+            .add_header(sig_name, sig)
+            .await
+            .assert_status_unauthorized();
+
+        // A read signature doesn't cancel
+        let [(ts_name, ts), (sig_name, sig)] = signed_headers(&sk, Method::GET, job_id);
+        server
+            .delete(&path)
+            .add_header(ts_name, ts.clone())
+            .add_header(sig_name, sig.clone())
+            .await
+            .assert_status_unauthorized();
+
+        let status = server
+            .get(&path)
+            .add_header(ts_name, ts)
+            .add_header(sig_name, sig)
+            .await
+            .json::<JobStatus>();
+        assert!(matches!(
+            status,
+            JobStatus::Failed {
+                status_code: 401,
+                ..
+            }
+        ));
+
+        let [(ts_name, ts), (sig_name, sig)] = signed_headers(&sk, Method::DELETE, job_id);
+        server
+            .delete(&path)
+            .add_header(ts_name, ts)
+            .add_header(sig_name, sig)
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        let [(ts_name, ts), (sig_name, sig)] = signed_headers(&sk, Method::GET, job_id);
+        server
+            .get(&path)
+            .add_header(ts_name, ts)
+            .add_header(sig_name, sig)
+            .await
+            .assert_status_not_found();
+
+        // Invalid requests are refused before they're queued
+        server
+            .post("/test/jobs")
+            .json(&JobRequest::Python(python::ExecutionRequest {
+                version: EnvelopeVersion::Framed,
+                encrypted_python: String::new(),
+                encrypted_arguments: vec![],
+                public_key: crypto::pk_to_hex(user_pk),
+                session_id: None,
+            }))
+            .await
+            .assert_status_bad_request();
+    }
+}
@@ -1,7 +1,9 @@
 use axum::Router;
 
+pub mod attestation;
 pub mod encrypt;
 pub mod execute;
+pub mod jobs;
 pub mod ping;
 pub mod policy;
 pub mod search;
@@ -1,9 +1,10 @@
+use std::borrow::Cow;
+
 use aes_gcm_siv::aead::Aead;
 use anyhow::{anyhow, Context};
+use attest::{event_log::EventKind, report_data::ReportData};
 use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
 use serde::{Deserialize, Serialize};
-use tokio::io::AsyncWriteExt;
-use tokio::process::Command;
 use tracing::info;
 use uuid::Uuid;
 
@@ -11,14 +12,22 @@ use crate::{
     error::HypervisorError,
     types::HypervisorState,
     utils::{
-        self, crypto,
+        self,
+        attest::Measurement,
+        auth::{self, RequestSignature, SignedJson},
+        commitment::{self, CommittedRequest, CommittedResponse, ExecutorKind},
+        crypto::{self, EnvelopeVersion},
         x402::{self, X402_FIXED_PRICE_USDC},
     },
 };
 
+pub mod sandbox;
+
 pub(crate) fn api_register(router: Router<HypervisorState>) -> Router<HypervisorState> {
     router
-        .route("/test/policy/unsafe/python", post(execute_python))
+        .route("/test/policy/python", post(test_execute_python))
+        .route("/test/policy/python/attest", post(attest_execute_python))
+        .route("/test/policy/unsafe/python", post(test_execute_python))
         .route(
             "/test/policy/unsafe/python/attest",
             post(attest_execute_python),
@@ -29,7 +38,10 @@ pub(crate) fn api_x402_register(
     router: Router<HypervisorState>,
     state: HypervisorState,
 ) -> Router<HypervisorState> {
+    // `/unsafe` predates the sandbox, kept for existing clients
     let x402_router = Router::new()
+        .route("/python", post(execute_python))
+        .route("/python/attest", post(attest_execute_python))
         .route("/unsafe/python", post(execute_python))
         .route("/unsafe/python/attest", post(attest_execute_python))
         .layer(x402::create_x402_middleware(X402_FIXED_PRICE_USDC))
@@ -38,62 +50,201 @@ pub(crate) fn api_x402_register(
     router.nest("/x402_policy", x402_router)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VerifiableExecutionResponse {
-    pub session_id: Uuid,
-    pub msg_nonce: String,
-    pub encrypted_result: String,
-    pub result_commitment: String,
+    #[serde(flatten)]
+    pub execution: ExecutionResponse,
     pub result_quote: String,
+    /// Set with signed responses, `result_quote` then attests the response key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_signature: Option<String>,
 }
 
 async fn attest_execute_python(
-    state: State<HypervisorState>,
-    req: Json<ExecutionRequest>,
+    State(state): State<HypervisorState>,
+    SignedJson {
+        body: req,
+        signature,
+    }: SignedJson<ExecutionRequest>,
 ) -> Result<Json<VerifiableExecutionResponse>, HypervisorError> {
-    let Json(resp) = execute_python(state, req).await?;
+    authorize_execution(&state, &req, signature.as_ref())?;
+
+    run_verifiable_execution(&state, req, signature)
+        .await
+        .map(Json)
+}
+
+/// [`run_execution`] with the result attested
+pub(crate) async fn run_verifiable_execution(
+    state: &HypervisorState,
+    req: ExecutionRequest,
+    signature: Option<RequestSignature>,
+) -> Result<VerifiableExecutionResponse, HypervisorError> {
+    let resp = run_execution(state, req, signature, Measurement::Required).await?;
     let commitment: [u8; 32] =
         const_hex::decode_to_array(&resp.result_commitment).expect("impossible");
 
-    let quote = attest::get_quote(utils::attest::generate_raw_report_from_hash(commitment))
-        .context("get execute result quote")
+    let report = ReportData::ExecutionResult { commitment };
+    let attestation = utils::attest::attest_response(state, report)
+        .context("attest execute result")
         .context(StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let verifiable_resp = VerifiableExecutionResponse {
-        session_id: resp.session_id,
-        msg_nonce: resp.msg_nonce,
-        encrypted_result: resp.encrypted_result,
-        result_commitment: resp.result_commitment,
-        result_quote: const_hex::encode(quote.to_bytes()),
-    };
-
-    Ok(Json(verifiable_resp))
+    Ok(VerifiableExecutionResponse {
+        execution: resp,
+        result_quote: attestation.quote_hex(),
+        result_signature: attestation.signature_hex(),
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExecutionRequest {
+    /// Framing of the encrypted fields, legacy when left out
+    #[serde(default)]
+    pub version: EnvelopeVersion,
     pub encrypted_python: String,
     #[serde(default = "Vec::new")]
     pub encrypted_arguments: Vec<String>,
     pub public_key: String,
+    /// Session to use, the latest session of `public_key` when left out
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
 }
 
-#[derive(Debug, Serialize)]
+impl CommittedRequest for ExecutionRequest {
+    fn version(&self) -> EnvelopeVersion {
+        self.version
+    }
+
+    fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    fn committed_inputs(&self) -> Vec<Cow<'_, [u8]>> {
+        let mut inputs = vec![
+            self.encrypted_python.as_bytes().into(),
+            commitment::count(self.encrypted_arguments.len()),
+        ];
+        inputs.extend({ self.encrypted_arguments.iter() }.map(|a| a.as_bytes().into()));
+
+        inputs
+    }
+}
+
+/// What `encrypted_result` holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultStream {
+    Stdout,
+    Stderr,
+}
+
+impl From<ResultStream> for u8 {
+    fn from(stream: ResultStream) -> Self {
+        match stream {
+            ResultStream::Stdout => 0,
+            ResultStream::Stderr => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ExecutionResponse {
+    pub executor: ExecutorKind,
+    pub runtime_version: String,
     pub session_id: Uuid,
     pub msg_nonce: String,
     pub encrypted_result: String,
     pub result_commitment: String,
+    pub exit_code: i32,
+    /// Stdout of a policy exiting with 0, stderr otherwise
+    pub result_stream: ResultStream,
 }
 
-#[tracing::instrument(skip(state, req), err)]
+impl CommittedResponse for ExecutionResponse {
+    fn executor(&self) -> ExecutorKind {
+        self.executor
+    }
+
+    fn runtime_version(&self) -> &str {
+        &self.runtime_version
+    }
+
+    fn session_id(&self) -> Uuid {
+        self.session_id
+    }
+
+    fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+
+    fn committed_outputs(&self) -> Vec<Cow<'_, [u8]>> {
+        vec![
+            self.msg_nonce.as_bytes().into(),
+            self.encrypted_result.as_bytes().into(),
+            vec![u8::from(self.result_stream)].into(),
+        ]
+    }
+
+    fn result_commitment(&self) -> &str {
+        &self.result_commitment
+    }
+}
+
+#[tracing::instrument(skip(state, req, signature), err)]
 async fn execute_python(
     State(state): State<HypervisorState>,
-    Json(req): Json<ExecutionRequest>,
+    SignedJson {
+        body: req,
+        signature,
+    }: SignedJson<ExecutionRequest>,
+) -> Result<Json<ExecutionResponse>, HypervisorError> {
+    authorize_execution(&state, &req, signature.as_ref())?;
+
+    run_execution(&state, req, signature, Measurement::Required)
+        .await
+        .map(Json)
+}
+
+/// [`execute_python`] that runs unmeasured outside a TEE
+#[tracing::instrument(skip(state, req, signature), err)]
+async fn test_execute_python(
+    State(state): State<HypervisorState>,
+    SignedJson {
+        body: req,
+        signature,
+    }: SignedJson<ExecutionRequest>,
 ) -> Result<Json<ExecutionResponse>, HypervisorError> {
-    // Validate request
-    validate_execution_request(&req)?;
+    authorize_execution(&state, &req, signature.as_ref())?;
+
+    run_execution(&state, req, signature, Measurement::BestEffort)
+        .await
+        .map(Json)
+}
+
+/// Validate and authenticate a request, spending its signature
+pub(crate) fn authorize_execution(
+    state: &HypervisorState,
+    req: &ExecutionRequest,
+    signature: Option<&RequestSignature>,
+) -> Result<(), HypervisorError> {
+    validate_execution_request(req)?;
+
+    let user_pk = crypto::pk_from_hex(&req.public_key)
+        .context("decode request pubkey")
+        .context(StatusCode::BAD_REQUEST)?;
+
+    auth::authenticate(state, &user_pk, signature)?;
+
+    Ok(())
+}
 
+/// Run the policy of a request [`authorize_execution`] let through
+pub(crate) async fn run_execution(
+    state: &HypervisorState,
+    req: ExecutionRequest,
+    signature: Option<RequestSignature>,
+    measurement: Measurement,
+) -> Result<ExecutionResponse, HypervisorError> {
     let start_time = std::time::Instant::now();
 
     let user_pk = crypto::pk_from_hex(&req.public_key)
@@ -101,7 +252,7 @@ async fn execute_python(
         .context(StatusCode::BAD_REQUEST)?;
 
     let (session_sk, session_id) = state
-        .get_session_keypair(&user_pk)
+        .get_session_keypair(&user_pk, req.session_id)
         .ok_or(anyhow!("session not found"))
         .context(StatusCode::UNAUTHORIZED)?;
 
@@ -109,16 +260,12 @@ async fn execute_python(
         .context("create encrypt key")
         .context(StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let msg_nonce = crypto::derive_msg_nonce(session_id);
-
     let decrypted_python = {
         let encrypted_bytes = const_hex::decode(&req.encrypted_python)
             .context("invalid python binary hex")
             .context(StatusCode::BAD_REQUEST)?;
 
-        let decrypted = cipher
-            .decrypt(&msg_nonce, encrypted_bytes.as_slice())
-            .map_err(|e| anyhow!(e.to_string()))
+        let decrypted = { req.version.open(&cipher, session_id, &encrypted_bytes) }
             .context("decrypt python binary")
             .context(StatusCode::BAD_REQUEST)?;
 
@@ -127,14 +274,19 @@ async fn execute_python(
             .context(StatusCode::BAD_REQUEST)?
     };
 
+    utils::attest::measure_artifact(
+        measurement,
+        EventKind::PolicySet,
+        "python policy",
+        decrypted_python.as_bytes(),
+    )?;
+
     let decrypted_arguments = { req.encrypted_arguments.iter() }
         .map(|a| {
             let bytes = const_hex::decode(a).context("decode argument hex")?;
 
-            let decrypted = cipher
-                .decrypt(&msg_nonce, bytes.as_ref())
-                .map_err(|e| anyhow!(e.to_string()))
-                .context("decrypt argument")?;
+            let decrypted =
+                { req.version.open(&cipher, session_id, &bytes) }.context("decrypt argument")?;
 
             let a = String::from_utf8(decrypted).context("argument isn't string")?;
 
@@ -149,85 +301,51 @@ async fn execute_python(
         "processing python execution request"
     );
 
-    let mut child = Command::new("python")
-        .arg("-")
-        .args(decrypted_arguments)
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .context("execute python")
-        .context(StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if let Some(mut stdin) = child.stdin.take() {
-        { stdin.write_all(decrypted_python.as_bytes()).await }
-            .context("pass python script")
-            .context(StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        { stdin.shutdown().await }
-            .context("close stdin")
-            .context(StatusCode::INTERNAL_SERVER_ERROR)?;
-    }
+    // A cancelled job drops this future, the interpreter goes with it
+    let sandbox = &state.config.python_sandbox;
+    let output = sandbox.run(&decrypted_python, &decrypted_arguments).await?;
 
-    let output = child
-        .wait_with_output()
-        .await
-        .context("wait python output")
-        .context(StatusCode::BAD_REQUEST)?;
-
-    let app_output = if output.status.success() {
-        String::from_utf8_lossy(&output.stdout)
-    } else {
-        String::from_utf8_lossy(&output.stderr)
+    let exit_code = output.exit_code;
+    let (result_stream, app_output) = match exit_code {
+        0 => (ResultStream::Stdout, &output.stdout),
+        _ => (ResultStream::Stderr, &output.stderr),
     };
 
     info!(
         session_id = %session_id,
         public_key = req.public_key,
         execution_time_ms = start_time.elapsed().as_millis(),
-        status = "success",
-        msg = "python execution completed successfully"
+        request_timestamp = signature.as_ref().map(|s| s.timestamp),
+        request_signature = signature.as_ref().map(|s| s.signature_hex()),
+        exit_code,
+        status = if exit_code == 0 { "success" } else { "failed" },
+        msg = "python execution completed"
     );
 
-    let output_nonce = crypto::derive_msg_nonce(app_output.as_bytes());
+    let output_nonce = req.version.result_nonce(app_output);
     let encrypted_result = {
         let encrypted = cipher
-            .encrypt(&output_nonce, app_output.as_bytes())
+            .encrypt(&output_nonce, app_output.as_slice())
             .map_err(|e| anyhow!(e.to_string()))?;
 
         const_hex::encode(encrypted)
     };
 
-    let result_commitment = {
-        let mut entries = vec![
-            user_pk.to_encoded_point(true).to_bytes(),
-            session_sk.verifying_key().to_encoded_point(true).to_bytes(),
-            Box::new(*session_id.as_bytes()),
-            req.encrypted_python.as_bytes().into(),
-        ];
-
-        entries.extend(
-            { req.encrypted_arguments.iter().cloned() }
-                .map(|a| a.as_bytes().into())
-                .collect::<Vec<_>>(),
-        );
-
-        entries.extend([
-            output_nonce.to_vec().into(),
-            encrypted_result.as_bytes().into(),
-        ]);
-
-        const_hex::encode(utils::hasher::hash_multi(&entries))
-    };
-
-    let resp = ExecutionResponse {
+    let mut resp = ExecutionResponse {
+        executor: ExecutorKind::Python,
+        runtime_version: output.runtime_version,
         session_id,
         msg_nonce: const_hex::encode(output_nonce),
         encrypted_result,
-        result_commitment,
+        result_commitment: String::new(),
+        exit_code,
+        result_stream,
     };
+    let result_commitment =
+        commitment::build_result_commitment(&user_pk, session_sk.verifying_key(), &req, &resp);
+    resp.result_commitment = const_hex::encode(result_commitment);
 
-    Ok(axum::Json(resp))
+    Ok(resp)
 }
 
 /// Validate execution request
@@ -255,10 +373,8 @@ fn validate_execution_request(request: &ExecutionRequest) -> Result<(), Hypervis
 
 #[cfg(test)]
 mod tests {
-    use aes_gcm_siv::aead::Aead;
-
     use crate::utils::crypto;
-    use crate::{api::RouterRegister, types::SessionKeyPairs};
+    use crate::{api::RouterRegister, session::SessionKeyPairs};
 
     use super::*;
 
@@ -281,23 +397,55 @@ mod tests {
         let (session_pk, session_id) = session_key_pairs.create(user_pk);
         let cipher = crypto::create_encrypt_key(&sk, &session_pk, session_id).unwrap();
 
-        let nonce = crypto::derive_msg_nonce(session_id);
-        let encrypted_python = cipher.encrypt(&nonce, python.as_slice()).unwrap();
+        let version = EnvelopeVersion::Framed;
+        let encrypted_python = version.seal(&cipher, session_id, python).unwrap();
         let encrypted_arguments = { vec!["tress".to_string()].into_iter() }
-            .map(|a| cipher.encrypt(&nonce, a.as_bytes()).unwrap())
+            .map(|a| version.seal(&cipher, session_id, a.as_bytes()).unwrap())
             .map(const_hex::encode)
             .collect();
 
+        let req = ExecutionRequest {
+            version,
+            encrypted_python: const_hex::encode(encrypted_python),
+            encrypted_arguments,
+            public_key: crypto::pk_to_hex(user_pk),
+            session_id: Some(session_id),
+        };
+        let response = server.post("/test/policy/python").json(&req).await;
+
+        response.assert_status_ok();
+        println!("response {}", response.text());
+
+        let result: ExecutionResponse = response.json();
+        assert_eq!(result.executor, ExecutorKind::Python);
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.result_stream, ResultStream::Stdout);
+        let result_commitment =
+            commitment::build_result_commitment(user_pk, &session_pk, &req, &result);
+        assert_eq!(
+            const_hex::encode(result_commitment),
+            result.result_commitment
+        );
+
+        let encrypted_python = version.seal(&cipher, session_id, b"import socket").unwrap();
         let response = server
-            .post("/test/policy/unsafe/python")
+            .post("/test/policy/python")
             .json(&ExecutionRequest {
+                version,
                 encrypted_python: const_hex::encode(encrypted_python),
-                encrypted_arguments,
+                encrypted_arguments: vec![],
                 public_key: crypto::pk_to_hex(user_pk),
+                session_id: Some(session_id),
             })
             .await;
 
-        response.assert_status_ok();
-        println!("response {}", response.text());
+        response.assert_status_unprocessable_entity();
+        let error: crate::error::ErrorResponse = response.json();
+        assert_eq!(
+            error.violation,
+            Some(sandbox::PolicyViolation::Import {
+                module: "socket".to_string()
+            })
+        );
     }
 }
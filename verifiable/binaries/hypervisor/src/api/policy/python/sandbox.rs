@@ -0,0 +1,921 @@
+//! Isolated runs of python policies
+//!
+//! The interpreter gets fresh user, mount, PID, network, IPC and UTS
+//! namespaces: no network beyond a loopback that's down, no other process
+//! in sight, and a read-only root holding only `root_paths` and its own
+//! installation, with an empty tmpfs at `/tmp` as its working directory. A
+//! seccomp filter refuses it namespaces, mounts, tracing and sockets.
+//! Resource limits cap its memory, CPU time, file sizes, open files and
+//! child processes, and it's killed once the wall clock timeout passes.
+//!
+//! A prelude loads the modules in `module_allowlist` and drops every other
+//! from `sys.modules`, policies may import only those. `sys` isn't among
+//! them by default, `import sys` gets a stand-in with `argv`, the standard
+//! streams and `exit`. Allowed modules keep their own references to others,
+//! `random._os` is the real `os`, so an audit hook installed before the
+//! policy runs enforces the allowlist: it refuses imports of modules outside
+//! it, every `os` and `subprocess` call, and opening anything but a relative
+//! path below `/tmp`, whichever way the policy got there. A refused event
+//! counts as an import of its module, `io` for `open`. The allowlist keeps
+//! policies to an agreed surface, the namespaces and limits are what isolate
+//! them.
+
+use std::{
+    collections::HashSet,
+    ffi::{CStr, CString},
+    io,
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        process::ExitStatusExt,
+    },
+    path::{Path, PathBuf},
+    process::Stdio,
+    ptr,
+    time::Duration,
+};
+
+use anyhow::Context;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::Command,
+};
+
+use crate::error::HypervisorError;
+
+/// Exit code of the prelude when it reports a violation
+const VIOLATION_EXIT_CODE: i32 = 125;
+/// Marks the stderr line the prelude reports a violation on
+const VIOLATION_MARKER: &str = "x402-violation:";
+/// Marks the first stderr line, the prelude reports the interpreter on it
+const RUNTIME_MARKER: &str = "x402-runtime:";
+
+/// Id of the interpreter inside its user namespace, mapped to the
+/// hypervisor's own. Not 0, so it holds no capabilities once it execs
+const SANDBOX_ID: u32 = 1000;
+
+/// How far the CPU time `wait4` reports may trail the scheduler's count the
+/// CPU limit is checked against, a few ticks
+const CPU_LIMIT_SLACK: Duration = Duration::from_millis(100);
+
+/// Where the sandbox root is put together before it becomes `/`
+const STAGING_ROOT: &CStr = c"/tmp";
+
+/// Offsets of the syscall number and architecture in `seccomp_data`
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+/// Marks x32 syscalls, which x86_64 also takes
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_clone3,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_fsopen,
+    libc::SYS_fsmount,
+    libc::SYS_move_mount,
+    libc::SYS_open_tree,
+    libc::SYS_mount_setattr,
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_perf_event_open,
+    libc::SYS_bpf,
+    libc::SYS_userfaultfd,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_io_uring_setup,
+    libc::SYS_io_uring_enter,
+    libc::SYS_io_uring_register,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_name_to_handle_at,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_acct,
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+];
+
+const PRELUDE: &str = r#"
+import builtins, json, os, sys
+
+ALLOWED = frozenset(filter(None, os.environ.pop("X402_MODULE_ALLOWLIST", "").split(",")))
+
+runtime = "%s %d.%d.%d" % (sys.implementation.name, *sys.version_info[:3])
+print("x402-runtime:" + runtime, file=sys.stderr, flush=True)
+
+class PolicyViolation(ImportError):
+    pass
+
+def violation(kind, **fields):
+    sys.stdout.flush()
+    report = json.dumps(dict(kind=kind, **fields))
+    print("x402-violation:" + report, file=sys.__stderr__, flush=True)
+    os._exit(125)
+
+def confine(function, **names):
+    # Rebound to globals holding only `names`, all a policy finds through
+    # its __globals__
+    globals = dict(names, __builtins__={})
+    return type(function)(function.__code__, globals, None, function.__defaults__)
+
+source = sys.stdin.read()
+sys.argv[0] = "-"
+
+# What a policy may import is loaded up front, the rest leaves sys.modules
+for name in ALLOWED:
+    try:
+        __import__(name)
+    except ImportError:
+        pass
+# Text files look their codecs up in `encodings`, it stays with the common ones
+import encodings.ascii, encodings.latin_1, encodings.utf_8
+kept = ALLOWED | {"encodings"}
+for name in [name for name in sys.modules if name.partition(".")[0] not in kept]:
+    del sys.modules[name]
+loaded = dict(sys.modules)
+
+def exit(status=None):
+    raise SystemExit(status)
+
+# `import sys` without sys allowed gets the arguments and streams only
+policy_sys = type(sys)("sys")
+policy_sys.argv = list(sys.argv)
+policy_sys.stdin, policy_sys.stdout, policy_sys.stderr = sys.stdin, sys.stdout, sys.stderr
+policy_sys.exit = confine(exit, SystemExit=SystemExit)
+loaded.setdefault("sys", policy_sys)
+
+def guarded_import(name, globals=None, locals=None, fromlist=(), level=0):
+    if level != 0 or name not in loaded:
+        raise PolicyViolation(name)
+    return loaded[name if fromlist else name.partition(".")[0]]
+
+guarded_import = confine(guarded_import, loaded=loaded, PolicyViolation=PolicyViolation)
+builtins.__import__ = guarded_import
+
+# Catches what got past the guard, e.g. `random._os` or a loader found through
+# `object.__subclasses__()`, since loading a module or using os raises events
+def audit(event, args):
+    if event == "import":
+        module = args[0].partition(".")[0]
+        if module not in ALLOWED:
+            raise PolicyViolation(module)
+    elif event == "open":
+        path = args[0]
+        if not isinstance(path, str) or path.startswith("/") or ".." in path.split("/"):
+            raise PolicyViolation("io")
+    elif event.startswith(("os.", "subprocess.")):
+        raise PolicyViolation(event.partition(".")[0])
+
+policy_builtins = dict(vars(builtins))
+# The module loaders, and site's helpers that import on their own
+for name in ("__loader__", "__spec__", "breakpoint", "copyright", "credits", "exit", "help",
+             "license", "quit"):
+    policy_builtins.pop(name, None)
+policy = {"__name__": "__main__", "__builtins__": policy_builtins}
+
+code = compile(source, "<policy>", "exec")
+sys.addaudithook(confine(audit, ALLOWED=ALLOWED, PolicyViolation=PolicyViolation,
+                         isinstance=isinstance, str=str))
+
+try:
+    exec(code, policy)
+except PolicyViolation as e:
+    violation("import", module=e.args[0])
+except MemoryError:
+    violation("memory")
+"#;
+
+/// Interpreter and caps of python policies
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PythonSandbox {
+    pub interpreter: PathBuf,
+    /// Host paths the sandbox root holds, read-only, besides the
+    /// interpreter's installation. Missing ones are left out
+    pub root_paths: Vec<PathBuf>,
+    /// Top level modules a policy may import
+    pub module_allowlist: Vec<String>,
+    pub wall_clock_ms: u64,
+    pub cpu_secs: u64,
+    /// Address space of the interpreter
+    pub memory_bytes: u64,
+    /// Size of the `/tmp` tmpfs, and of any file written
+    pub tmpfs_bytes: u64,
+    /// Stdout past this is a violation, stderr is cut at it
+    pub output_limit_bytes: usize,
+}
+
+impl Default for PythonSandbox {
+    fn default() -> Self {
+        let module_allowlist = [
+            "base64",
+            "binascii",
+            "collections",
+            "copy",
+            "dataclasses",
+            "datetime",
+            "decimal",
+            "enum",
+            "fractions",
+            "functools",
+            "hashlib",
+            "hmac",
+            "itertools",
+            "json",
+            "math",
+            "operator",
+            "random",
+            "re",
+            "statistics",
+            "string",
+            "struct",
+            "textwrap",
+            "time",
+            "typing",
+            "unicodedata",
+        ];
+
+        PythonSandbox {
+            interpreter: PathBuf::from("python"),
+            root_paths: ["/usr", "/bin", "/lib", "/lib64"]
+                .map(PathBuf::from)
+                .to_vec(),
+            module_allowlist: module_allowlist.map(String::from).to_vec(),
+            wall_clock_ms: 10_000,
+            cpu_secs: 5,
+            memory_bytes: 512 * 1024 * 1024,
+            tmpfs_bytes: 16 * 1024 * 1024,
+            output_limit_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// Sandbox rule a policy broke
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PolicyViolation {
+    /// Imported a module outside the allowlist
+    Import {
+        module: String,
+    },
+    Memory,
+    Cpu,
+    WallClock,
+    Output,
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyViolation::Import { module } => write!(f, "import of {module} not allowed"),
+            PolicyViolation::Memory => f.write_str("memory limit exceeded"),
+            PolicyViolation::Cpu => f.write_str("cpu time limit exceeded"),
+            PolicyViolation::WallClock => f.write_str("wall clock limit exceeded"),
+            PolicyViolation::Output => f.write_str("output limit exceeded"),
+        }
+    }
+}
+
+/// How a policy that kept to the rules ended
+#[derive(Debug)]
+pub struct PolicyOutput {
+    /// Implementation and version of the interpreter, e.g. `cpython 3.11.7`
+    pub runtime_version: String,
+    pub exit_code: i32,
+    pub stdout: Vec<u8>,
+    /// Cut at `output_limit_bytes`
+    pub stderr: Vec<u8>,
+}
+
+impl PythonSandbox {
+    /// Run `script` with `args` as its `sys.argv[1:]`
+    pub async fn run(
+        &self,
+        script: &str,
+        args: &[String],
+    ) -> Result<PolicyOutput, HypervisorError> {
+        let isolation = Isolation::new(self)
+            .context("prepare python sandbox")
+            .context(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let mut command = Command::new(&isolation.interpreter);
+        command
+            .arg("-I")
+            .arg("-c")
+            .arg(PRELUDE)
+            .args(args)
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("X402_MODULE_ALLOWLIST", self.module_allowlist.join(","))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // A dropped run, cancelled or timed out, takes the interpreter
+            .kill_on_drop(true);
+
+        // SAFETY: `isolate` only makes async-signal-safe calls on buffers
+        // prepared before the fork
+        unsafe {
+            command.pre_exec(move || isolation.isolate());
+        }
+
+        let mut child = command
+            .spawn()
+            .context("start python sandbox")
+            .context(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let feed = async move {
+            // A policy that exits before reading its source closes the pipe
+            let _ = stdin.write_all(script.as_bytes()).await;
+        };
+        let stdout = read_capped(child.stdout.take(), self.output_limit_bytes);
+        let stderr = read_capped(child.stderr.take(), self.output_limit_bytes);
+
+        let run = async { tokio::join!(feed, child.wait(), stdout, stderr) };
+        let (_, status, stdout, stderr) =
+            match tokio::time::timeout(Duration::from_millis(self.wall_clock_ms), run).await {
+                Ok(run) => run,
+                Err(_) => return Err(HypervisorError::PolicyViolation(PolicyViolation::WallClock)),
+            };
+
+        let status = status.context("wait python")?;
+        let (stdout, stdout_truncated) = stdout.context("read python stdout")?;
+        let (stderr, _) = stderr.context("read python stderr")?;
+
+        if stdout_truncated {
+            return Err(HypervisorError::PolicyViolation(PolicyViolation::Output));
+        }
+        match status.signal() {
+            Some(libc::SIGXCPU) => {
+                return Err(HypervisorError::PolicyViolation(PolicyViolation::Cpu))
+            }
+            // The OOM killer, kills by the CPU limit come as SIGXCPU
+            Some(libc::SIGKILL) => {
+                return Err(HypervisorError::PolicyViolation(PolicyViolation::Memory))
+            }
+            _ => {}
+        }
+        if status.code() == Some(VIOLATION_EXIT_CODE) {
+            if let Some(violation) = reported_violation(&stderr) {
+                return Err(HypervisorError::PolicyViolation(violation));
+            }
+        }
+
+        let (runtime_version, stderr) = reported_runtime(&stderr)
+            .context("python reported no runtime")
+            .context(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        Ok(PolicyOutput {
+            runtime_version,
+            exit_code: status.code().unwrap_or(-1),
+            stdout,
+            stderr: stderr.to_vec(),
+        })
+    }
+}
+
+/// The runtime the prelude reported, and the stderr after it
+fn reported_runtime(stderr: &[u8]) -> Option<(String, &[u8])> {
+    let rest = stderr.strip_prefix(RUNTIME_MARKER.as_bytes())?;
+    let end = rest.iter().position(|&b| b == b'\n')?;
+    let runtime = String::from_utf8(rest[..end].to_vec()).ok()?;
+
+    Some((runtime, &rest[end + 1..]))
+}
+
+/// The violation the prelude reported last on stderr
+fn reported_violation(stderr: &[u8]) -> Option<PolicyViolation> {
+    let stderr = String::from_utf8_lossy(stderr);
+    let report = { stderr.lines().rev() }.find_map(|l| l.strip_prefix(VIOLATION_MARKER))?;
+
+    serde_json::from_str(report).ok()
+}
+
+/// Read all of `pipe`, keeping up to `limit` bytes. Returns whether any
+/// were dropped
+async fn read_capped(
+    pipe: Option<impl AsyncRead + Unpin>,
+    limit: usize,
+) -> io::Result<(Vec<u8>, bool)> {
+    let mut pipe = pipe.expect("piped output");
+    let mut kept = Vec::new();
+    let mut truncated = false;
+
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = pipe.read(&mut buf).await?;
+        if n == 0 {
+            return Ok((kept, truncated));
+        }
+
+        let room = limit.saturating_sub(kept.len()).min(n);
+        kept.extend_from_slice(&buf[..room]);
+        truncated |= room < n;
+    }
+}
+
+/// What the forked child applies to itself before it execs the interpreter,
+/// formatted beforehand since it can't allocate
+struct Isolation {
+    /// Absolute, the same inside the sandbox root as on the host
+    interpreter: PathBuf,
+    uid_map: Vec<u8>,
+    gid_map: Vec<u8>,
+    /// Built in order on the tmpfs at `STAGING_ROOT`
+    root: Vec<RootEntry>,
+    tmpfs_options: CString,
+    rlimits: Vec<(libc::__rlimit_resource_t, libc::rlim_t)>,
+    cpu_secs: u64,
+    seccomp: Vec<libc::sock_filter>,
+}
+
+/// Piece of the sandbox root, its paths below `STAGING_ROOT`
+enum RootEntry {
+    Dir(CString),
+    /// Host directory, bound with whatever is mounted below it
+    Bind {
+        source: CString,
+        path: CString,
+    },
+    /// Host symlink, e.g. `/bin` to `usr/bin`, kept as it is
+    Symlink {
+        target: CString,
+        path: CString,
+    },
+}
+
+impl Isolation {
+    fn new(sandbox: &PythonSandbox) -> anyhow::Result<Self> {
+        // SAFETY: both only read the credentials of the calling process
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+
+        let interpreter = resolve_interpreter(&sandbox.interpreter)?;
+        // The installation the interpreter comes with, its stdlib next to it
+        let install = match interpreter.parent() {
+            Some(bin) if bin.ends_with("bin") => bin.parent(),
+            dir => dir,
+        };
+        let install = install.filter(|install| {
+            !{ sandbox.root_paths.iter() }.any(|root_path| install.starts_with(root_path))
+        });
+
+        let mut root = Vec::new();
+        let mut dirs = HashSet::new();
+        let mut staged_paths: Vec<&Path> = Vec::new();
+        for path in { sandbox.root_paths.iter() }
+            .map(PathBuf::as_path)
+            .chain(install)
+        {
+            // Already there, and directories made below a bind would end up
+            // on the host
+            if staged_paths.iter().any(|staged| path.starts_with(staged)) {
+                continue;
+            }
+            let Ok(metadata) = std::fs::symlink_metadata(path) else {
+                continue;
+            };
+            staged_paths.push(path);
+
+            for dir in { path.ancestors().skip(1) }
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+            {
+                if dir.parent().is_some() && dirs.insert(dir) {
+                    root.push(RootEntry::Dir(staged(dir)?));
+                }
+            }
+
+            if metadata.is_symlink() {
+                root.push(RootEntry::Symlink {
+                    target: CString::new(std::fs::read_link(path)?.into_os_string().into_vec())?,
+                    path: staged(path)?,
+                });
+            } else {
+                dirs.insert(path);
+                root.push(RootEntry::Dir(staged(path)?));
+                root.push(RootEntry::Bind {
+                    source: CString::new(path.as_os_str().as_bytes())?,
+                    path: staged(path)?,
+                });
+            }
+        }
+        root.push(RootEntry::Dir(staged(Path::new("/tmp"))?));
+
+        Ok(Isolation {
+            interpreter,
+            uid_map: format!("{SANDBOX_ID} {uid} 1").into_bytes(),
+            gid_map: format!("{SANDBOX_ID} {gid} 1").into_bytes(),
+            root,
+            tmpfs_options: CString::new(format!("size={},mode=1777", sandbox.tmpfs_bytes))?,
+            rlimits: vec![
+                (libc::RLIMIT_AS, sandbox.memory_bytes),
+                (libc::RLIMIT_CPU, sandbox.cpu_secs),
+                (libc::RLIMIT_FSIZE, sandbox.tmpfs_bytes),
+                (libc::RLIMIT_NOFILE, 64),
+                (libc::RLIMIT_NPROC, 0),
+                (libc::RLIMIT_CORE, 0),
+            ],
+            cpu_secs: sandbox.cpu_secs,
+            seccomp: seccomp_filter(),
+        })
+    }
+
+    /// Runs in the child between fork and exec
+    fn isolate(&self) -> io::Result<()> {
+        // SAFETY: raw syscalls on C strings and buffers owned by `self`,
+        // which outlives the exec
+        unsafe {
+            check(libc::unshare(
+                libc::CLONE_NEWUSER
+                    | libc::CLONE_NEWNS
+                    | libc::CLONE_NEWPID
+                    | libc::CLONE_NEWNET
+                    | libc::CLONE_NEWIPC
+                    | libc::CLONE_NEWUTS,
+            ))?;
+
+            write_proc(c"/proc/self/setgroups", b"deny")?;
+            write_proc(c"/proc/self/uid_map", &self.uid_map)?;
+            write_proc(c"/proc/self/gid_map", &self.gid_map)?;
+
+            // Keep the mounts below out of the hypervisor's namespace
+            check(libc::mount(
+                ptr::null(),
+                c"/".as_ptr(),
+                ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                ptr::null(),
+            ))?;
+            self.pivot_root()?;
+
+            check(libc::mount(
+                c"tmpfs".as_ptr(),
+                c"/tmp".as_ptr(),
+                c"tmpfs".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                self.tmpfs_options.as_ptr().cast(),
+            ))?;
+            check(libc::chdir(c"/tmp".as_ptr()))?;
+
+            // Only children of this process join the PID namespace, the
+            // interpreter is one and this one waits it out
+            match libc::fork() {
+                -1 => return Err(io::Error::last_os_error()),
+                0 => {}
+                interpreter => relay(interpreter, self.cpu_secs),
+            }
+            check(libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL))?;
+
+            for (resource, limit) in &self.rlimits {
+                // The interpreter is the init of its PID namespace, which
+                // ignores SIGXCPU, so the CPU limit kills at the hard limit
+                let rlimit = libc::rlimit {
+                    rlim_cur: *limit,
+                    rlim_max: *limit,
+                };
+                check(libc::setrlimit(*resource, &rlimit))?;
+            }
+
+            check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+            let filter = libc::sock_fprog {
+                len: self.seccomp.len() as u16,
+                filter: self.seccomp.as_ptr().cast_mut(),
+            };
+            check(libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &filter as *const libc::sock_fprog,
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Make the tmpfs at `STAGING_ROOT`, with the host paths bound into it,
+    /// the read-only root of the mount namespace, the host's out of reach
+    ///
+    /// # Safety
+    ///
+    /// Only async-signal-safe calls, fit for a forked child
+    unsafe fn pivot_root(&self) -> io::Result<()> {
+        check(libc::mount(
+            c"tmpfs".as_ptr(),
+            STAGING_ROOT.as_ptr(),
+            c"tmpfs".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            c"mode=0755".as_ptr().cast(),
+        ))?;
+
+        for entry in &self.root {
+            match entry {
+                RootEntry::Dir(path) => check(libc::mkdir(path.as_ptr(), 0o755))?,
+                RootEntry::Bind { source, path } => check(libc::mount(
+                    source.as_ptr(),
+                    path.as_ptr(),
+                    ptr::null(),
+                    libc::MS_BIND | libc::MS_REC,
+                    ptr::null(),
+                ))?,
+                RootEntry::Symlink { target, path } => {
+                    check(libc::symlink(target.as_ptr(), path.as_ptr()))?
+                }
+            }
+        }
+
+        // The old root ends up stacked on the new one, and is detached
+        check(libc::chdir(STAGING_ROOT.as_ptr()))?;
+        check(libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) as libc::c_int)?;
+        check(libc::umount2(c".".as_ptr(), libc::MNT_DETACH))?;
+        check(libc::chdir(c"/".as_ptr()))?;
+
+        // Unlike a bind remount this reaches the mounts below the binds too
+        let attr = libc::mount_attr {
+            attr_set: libc::MOUNT_ATTR_RDONLY | libc::MOUNT_ATTR_NOSUID | libc::MOUNT_ATTR_NODEV,
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: 0,
+        };
+        let set = libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            c"/".as_ptr(),
+            libc::AT_RECURSIVE,
+            &attr as *const libc::mount_attr,
+            std::mem::size_of::<libc::mount_attr>(),
+        );
+        check(set as libc::c_int)
+    }
+}
+
+/// Waits out the interpreter in its PID namespace and ends the same way.
+/// The CPU limit kills with SIGKILL, as the OOM killer does, a kill after
+/// the CPU time is used up ends it with SIGXCPU instead
+///
+/// # Safety
+///
+/// Only async-signal-safe calls, fit for a forked child
+unsafe fn relay(interpreter: libc::pid_t, cpu_secs: u64) -> ! {
+    // Leave the pipes to the interpreter, std's exec error pipe included,
+    // or `spawn` only returns once it exits
+    libc::syscall(libc::SYS_close_range, 0, libc::c_uint::MAX, 0);
+
+    let mut status = 0;
+    let mut usage: libc::rusage = std::mem::zeroed();
+    while libc::wait4(interpreter, &mut status, 0, &mut usage) < 0 {
+        if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            libc::_exit(1);
+        }
+    }
+    if libc::WIFEXITED(status) {
+        libc::_exit(libc::WEXITSTATUS(status));
+    }
+
+    let cpu = { [usage.ru_utime, usage.ru_stime].iter() }
+        .map(|t| Duration::new(t.tv_sec as u64, t.tv_usec as u32 * 1000))
+        .sum::<Duration>();
+    let signal = match libc::WTERMSIG(status) {
+        libc::SIGKILL if cpu + CPU_LIMIT_SLACK >= Duration::from_secs(cpu_secs) => libc::SIGXCPU,
+        signal => signal,
+    };
+
+    let no_core = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+    libc::signal(signal, libc::SIG_DFL);
+    libc::kill(libc::getpid(), signal);
+    libc::_exit(1)
+}
+
+/// `interpreter` as the absolute path it execs from, found on `PATH` if
+/// it's a bare name
+fn resolve_interpreter(interpreter: &Path) -> anyhow::Result<PathBuf> {
+    let path = match interpreter.components().count() {
+        1 if interpreter.is_relative() => {
+            let paths = std::env::var_os("PATH").unwrap_or_default();
+            { std::env::split_paths(&paths) }
+                .map(|dir| dir.join(interpreter))
+                .find(|path| path.is_file())
+                .with_context(|| format!("{} not on PATH", interpreter.display()))?
+        }
+        _ => interpreter.to_path_buf(),
+    };
+
+    std::fs::canonicalize(&path).with_context(|| format!("resolve {}", path.display()))
+}
+
+/// `path` below `STAGING_ROOT`
+fn staged(path: &Path) -> anyhow::Result<CString> {
+    let mut staged = STAGING_ROOT.to_bytes().to_vec();
+    staged.extend_from_slice(path.as_os_str().as_bytes());
+
+    Ok(CString::new(staged)?)
+}
+
+/// Refuses with EPERM what the interpreter has no business with: other
+/// namespaces, mounts, tracing, kernel facilities and sockets. Syscalls of
+/// another ABI kill it
+fn seccomp_filter() -> Vec<libc::sock_filter> {
+    let stmt = |code: u32, k: u32| libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |code: u32, k: u32, jt: u8, jf: u8| libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    };
+
+    let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let equal = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+    let ret = libc::BPF_RET | libc::BPF_K;
+
+    let mut filter = vec![
+        stmt(load, SECCOMP_DATA_ARCH),
+        jump(equal, AUDIT_ARCH, 1, 0),
+        stmt(ret, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(load, SECCOMP_DATA_NR),
+    ];
+    #[cfg(target_arch = "x86_64")]
+    filter.extend([
+        jump(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            X32_SYSCALL_BIT,
+            0,
+            1,
+        ),
+        stmt(ret, libc::SECCOMP_RET_KILL_PROCESS),
+    ]);
+    for syscall in DENIED_SYSCALLS {
+        filter.extend([
+            jump(equal, *syscall as u32, 0, 1),
+            stmt(ret, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32),
+        ]);
+    }
+    filter.push(stmt(ret, libc::SECCOMP_RET_ALLOW));
+
+    filter
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    match ret {
+        0.. => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// # Safety
+///
+/// Only async-signal-safe calls, fit for a forked child
+unsafe fn write_proc(path: &std::ffi::CStr, data: &[u8]) -> io::Result<()> {
+    let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+    check(fd)?;
+
+    let written = libc::write(fd, data.as_ptr().cast(), data.len());
+    libc::close(fd);
+
+    match written {
+        n if n as usize == data.len() => Ok(()),
+        -1 => Err(io::Error::last_os_error()),
+        _ => Err(io::Error::from(io::ErrorKind::WriteZero)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn run(sandbox: &PythonSandbox, script: &str) -> Result<PolicyOutput, PolicyViolation> {
+        match sandbox.run(script, &["tress".to_string()]).await {
+            Ok(output) => Ok(output),
+            Err(HypervisorError::PolicyViolation(violation)) => Err(violation),
+            Err(e) => panic!("sandbox failed: {e}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_python_sandbox() {
+        let sandbox = PythonSandbox {
+            wall_clock_ms: 2_000,
+            cpu_secs: 1,
+            memory_bytes: 256 * 1024 * 1024,
+            output_limit_bytes: 1024,
+            ..Default::default()
+        };
+
+        let output = run(&sandbox, include_str!("./hello.py")).await.unwrap();
+        assert!(output.runtime_version.starts_with("cpython 3."));
+        assert_eq!(output.exit_code, 0);
+        assert_eq!(output.stdout, b"Hello tress!\n");
+
+        // Allowed modules import what they need
+        let output = run(&sandbox, "import json\nprint(json.dumps([1]))")
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, b"[1]\n");
+
+        // The writable tmpfs is the working directory
+        let script = "open('f', 'w').write('x')\nprint(open('f').read())";
+        let output = run(&sandbox, script).await.unwrap();
+        assert_eq!(output.stdout, b"x\n");
+
+        let output = run(&sandbox, "raise SystemExit(3)").await.unwrap();
+        assert_eq!(output.exit_code, 3);
+
+        // Neither the stand-in `sys` nor the import guard lead anywhere else
+        let output = run(&sandbox, "import sys\nsys.modules['os']")
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&output.stderr).contains("AttributeError"));
+        let output = run(&sandbox, "print(sorted(__import__.__globals__))")
+            .await
+            .unwrap();
+        assert_eq!(
+            output.stdout,
+            b"['PolicyViolation', '__builtins__', 'loaded']\n"
+        );
+
+        let violations = [
+            (
+                "import socket",
+                PolicyViolation::Import {
+                    module: "socket".to_string(),
+                },
+            ),
+            (
+                "from os import path",
+                PolicyViolation::Import {
+                    module: "os".to_string(),
+                },
+            ),
+            (
+                "len.__self__.__import__('os')",
+                PolicyViolation::Import {
+                    module: "os".to_string(),
+                },
+            ),
+            // Allowed modules' own references and the loaders are refused
+            // by the audit hook
+            (
+                "import random\nrandom._os.listdir('/')",
+                PolicyViolation::Import {
+                    module: "os".to_string(),
+                },
+            ),
+            (
+                "[c for c in ().__class__.__base__.__subclasses__() \
+                    if c.__name__ == 'BuiltinImporter'][0].load_module('posix').listdir('/')",
+                PolicyViolation::Import {
+                    module: "os".to_string(),
+                },
+            ),
+            (
+                "[c for c in ().__class__.__base__.__subclasses__() \
+                    if c.__name__ == 'FileLoader'][0]('os', '/usr/lib/os.py').get_data('/etc/passwd')",
+                PolicyViolation::Import {
+                    module: "io".to_string(),
+                },
+            ),
+            (
+                "open('../etc/passwd')",
+                PolicyViolation::Import {
+                    module: "io".to_string(),
+                },
+            ),
+            ("x = bytearray(1 << 30)", PolicyViolation::Memory),
+            ("while True: pass", PolicyViolation::Cpu),
+            ("import time\ntime.sleep(5)", PolicyViolation::WallClock),
+            ("print('x' * 2048)", PolicyViolation::Output),
+        ];
+        for (script, expected) in violations {
+            assert_eq!(
+                run(&sandbox, script).await.unwrap_err(),
+                expected,
+                "{script}"
+            );
+        }
+    }
+}
@@ -2,19 +2,191 @@ use std::{net::SocketAddr, path::PathBuf};
 
 use serde::Deserialize;
 
+use crate::{
+    api::{
+        execute::{
+            riscv::{limits::RiscvLimits, DEFAULT_RISCV_OUTPUT_LIMIT_BYTES},
+            wasm::{
+                bundle::DEFAULT_WASM_WORK_DIR, cache::DEFAULT_WASM_CACHE_BYTES, limits::WasmLimits,
+                output::DEFAULT_WASM_OUTPUT_LIMIT_BYTES,
+            },
+        },
+        jobs::{DEFAULT_JOB_QUEUE_DEPTH, DEFAULT_JOB_RETENTION_SECS, DEFAULT_JOB_WORKERS},
+        policy::python::sandbox::PythonSandbox,
+    },
+    session::{
+        DEFAULT_SESSION_IDLE_TIMEOUT_SECS, DEFAULT_SESSION_KV_LIMIT_BYTES, DEFAULT_SESSION_TTL_SECS,
+    },
+};
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub executor_path: PathBuf,
     pub app_path: PathBuf,
     pub listening: SocketAddr,
+    /// Cached PCS collateral served at `/attestation/collateral`
+    #[serde(default)]
+    pub collateral_dir: Option<PathBuf>,
+    /// Sign `/verifiable/*` responses with a key quoted once at startup,
+    /// instead of quoting every response
+    #[serde(default)]
+    pub signed_responses: bool,
+    /// Sessions expire this long after creation
+    #[serde(default = "default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+    /// Sessions expire this long after their last use
+    #[serde(default = "default_session_idle_timeout_secs")]
+    pub session_idle_timeout_secs: u64,
+    /// Keep sessions sealed in this file across restarts, needs a SEV-SNP
+    /// or mock sealing key. TDX has none, startup fails with it set
+    #[serde(default)]
+    pub session_store_path: Option<PathBuf>,
+    /// Seal `session_store_path` under the mock key when no TEE device is
+    /// found, mock-attest builds only. The key is public, anyone holding
+    /// the file can read its sessions
+    #[serde(default)]
+    pub mock_sealing_key: bool,
+    /// Caps of every WASM execution, requests may only lower them
+    #[serde(default)]
+    pub wasm_limits: WasmLimits,
+    /// Compiled code kept for repeated WASM executions, 0 disables caching
+    #[serde(default = "default_wasm_cache_bytes")]
+    pub wasm_cache_bytes: u64,
+    /// Captured stdout and stderr of a WASM execution, each
+    #[serde(default = "default_wasm_output_limit_bytes")]
+    pub wasm_output_limit_bytes: usize,
+    /// Parent of the per execution `/input`, `/scratch` and `/output`
+    /// directories, `/dev/shm` by default. Decrypted bundles are written
+    /// there, anything but a tmpfs puts them on disk
+    #[serde(default = "default_wasm_work_dir")]
+    pub wasm_work_dir: PathBuf,
+    /// Unpacked bundle, files the guest holds in `/scratch` and `/output`
+    /// while it runs, and returned output files of a WASM execution, each.
+    /// Guest files are measured every 10ms, not on write, a guest can
+    /// overshoot in between up to the free space of `wasm_work_dir`.
+    /// Executions are refused with 503 unless it has this much free
+    #[serde(default = "default_wasm_files_limit_bytes")]
+    pub wasm_files_limit_bytes: usize,
+    /// `host[:port]` origins a request may grant its guest `x402:host/http`
+    /// to, none when empty. A bare host is its default HTTP and HTTPS ports
+    #[serde(default)]
+    pub wasm_http_allowlist: Vec<String>,
+    /// Keys and values a session may keep through `x402:host/kv`
+    #[serde(default = "default_session_kv_limit_bytes")]
+    pub session_kv_limit_bytes: usize,
+    /// Caps of every RISC-V execution, requests may only lower them
+    #[serde(default)]
+    pub riscv_limits: RiscvLimits,
+    /// Captured stdout and stderr of a RISC-V execution, each
+    #[serde(default = "default_riscv_output_limit_bytes")]
+    pub riscv_output_limit_bytes: usize,
+    /// Interpreter, importable modules and caps of python policies
+    #[serde(default)]
+    pub python_sandbox: PythonSandbox,
+    /// Jobs running at once
+    #[serde(default = "default_job_workers")]
+    pub job_workers: usize,
+    /// Jobs waiting for a worker, submissions past them are refused with 429
+    #[serde(default = "default_job_queue_depth")]
+    pub job_queue_depth: usize,
+    /// Finished jobs can be polled this long
+    #[serde(default = "default_job_retention_secs")]
+    pub job_retention_secs: u64,
+    /// Largest accepted request body, sealed stdin included
+    #[serde(default = "default_request_body_limit_bytes")]
+    pub request_body_limit_bytes: usize,
+    /// Reject execute requests without a client signature
+    #[serde(default)]
+    pub require_signed_requests: bool,
+    /// Accepted clock skew of signed requests, either way
+    #[serde(default = "default_request_signature_window_secs")]
+    pub request_signature_window_secs: u64,
+}
+
+fn default_session_ttl_secs() -> u64 {
+    DEFAULT_SESSION_TTL_SECS
+}
+
+fn default_session_idle_timeout_secs() -> u64 {
+    DEFAULT_SESSION_IDLE_TIMEOUT_SECS
+}
+
+fn default_wasm_cache_bytes() -> u64 {
+    DEFAULT_WASM_CACHE_BYTES
+}
+
+fn default_wasm_output_limit_bytes() -> usize {
+    DEFAULT_WASM_OUTPUT_LIMIT_BYTES
+}
+
+fn default_riscv_output_limit_bytes() -> usize {
+    DEFAULT_RISCV_OUTPUT_LIMIT_BYTES
+}
+
+fn default_wasm_work_dir() -> PathBuf {
+    PathBuf::from(DEFAULT_WASM_WORK_DIR)
+}
+
+fn default_wasm_files_limit_bytes() -> usize {
+    DEFAULT_WASM_FILES_LIMIT_BYTES
+}
+
+fn default_session_kv_limit_bytes() -> usize {
+    DEFAULT_SESSION_KV_LIMIT_BYTES
+}
+
+fn default_job_workers() -> usize {
+    DEFAULT_JOB_WORKERS
+}
+
+fn default_job_queue_depth() -> usize {
+    DEFAULT_JOB_QUEUE_DEPTH
 }
 
+fn default_job_retention_secs() -> u64 {
+    DEFAULT_JOB_RETENTION_SECS
+}
+
+fn default_request_body_limit_bytes() -> usize {
+    DEFAULT_REQUEST_BODY_LIMIT_BYTES
+}
+
+fn default_request_signature_window_secs() -> u64 {
+    DEFAULT_REQUEST_SIGNATURE_WINDOW_SECS
+}
+
+const DEFAULT_REQUEST_SIGNATURE_WINDOW_SECS: u64 = 5 * 60;
+const DEFAULT_REQUEST_BODY_LIMIT_BYTES: usize = 64 * 1024 * 1024;
+const DEFAULT_WASM_FILES_LIMIT_BYTES: usize = 64 * 1024 * 1024;
+
 impl Default for Config {
     fn default() -> Self {
         Config {
             executor_path: "./data/executor".parse().expect("executor path"),
             app_path: "./data/apps".parse().expect("app path"),
             listening: "0.0.0.0:8080".parse().expect("hypervisor listen address"),
+            collateral_dir: None,
+            signed_responses: false,
+            session_ttl_secs: DEFAULT_SESSION_TTL_SECS,
+            session_idle_timeout_secs: DEFAULT_SESSION_IDLE_TIMEOUT_SECS,
+            session_store_path: None,
+            mock_sealing_key: false,
+            wasm_limits: WasmLimits::default(),
+            wasm_cache_bytes: DEFAULT_WASM_CACHE_BYTES,
+            wasm_output_limit_bytes: DEFAULT_WASM_OUTPUT_LIMIT_BYTES,
+            wasm_work_dir: default_wasm_work_dir(),
+            wasm_files_limit_bytes: DEFAULT_WASM_FILES_LIMIT_BYTES,
+            wasm_http_allowlist: vec![],
+            session_kv_limit_bytes: DEFAULT_SESSION_KV_LIMIT_BYTES,
+            riscv_limits: RiscvLimits::default(),
+            riscv_output_limit_bytes: DEFAULT_RISCV_OUTPUT_LIMIT_BYTES,
+            python_sandbox: PythonSandbox::default(),
+            job_workers: DEFAULT_JOB_WORKERS,
+            job_queue_depth: DEFAULT_JOB_QUEUE_DEPTH,
+            job_retention_secs: DEFAULT_JOB_RETENTION_SECS,
+            request_body_limit_bytes: DEFAULT_REQUEST_BODY_LIMIT_BYTES,
+            require_signed_requests: false,
+            request_signature_window_secs: DEFAULT_REQUEST_SIGNATURE_WINDOW_SECS,
         }
     }
 }
@@ -2,7 +2,9 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use crate::api::{execute::ExecutionLimit, policy::python::sandbox::PolicyViolation};
 
 #[derive(thiserror::Error, Debug)]
 pub enum HypervisorError {
@@ -14,10 +16,26 @@ pub enum HypervisorError {
 
     #[error("Invalid request: {0}")]
     InvalidRequest(String, StatusCode),
+
+    #[error("execution limit exceeded: {0}")]
+    LimitExceeded(ExecutionLimit),
+
+    #[error("policy violation: {0}")]
+    PolicyViolation(PolicyViolation),
 }
 
-impl IntoResponse for HypervisorError {
-    fn into_response(self) -> Response {
+impl HypervisorError {
+    /// Status and body the error answers with
+    pub fn into_parts(self) -> (StatusCode, ErrorResponse) {
+        let limit = match &self {
+            HypervisorError::LimitExceeded(limit) => Some(*limit),
+            _ => None,
+        };
+        let violation = match &self {
+            HypervisorError::PolicyViolation(violation) => Some(violation.clone()),
+            _ => None,
+        };
+
         let (status_code, err_msg) = match self {
             HypervisorError::Any(e) => {
                 let status_code = e
@@ -30,15 +48,36 @@ impl IntoResponse for HypervisorError {
             #[rustfmt::skip]
             HypervisorError::Io(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             HypervisorError::InvalidRequest(msg, status_code) => (status_code, msg),
+            e @ (HypervisorError::LimitExceeded(_) | HypervisorError::PolicyViolation(_)) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, e.to_string())
+            }
+        };
+
+        let err_resp = ErrorResponse {
+            msg: err_msg,
+            limit,
+            violation,
         };
 
-        let err_resp = ErrorResponse { msg: err_msg };
+        (status_code, err_resp)
+    }
+}
+
+impl IntoResponse for HypervisorError {
+    fn into_response(self) -> Response {
+        let (status_code, err_resp) = self.into_parts();
 
         (status_code, axum::Json(err_resp)).into_response()
     }
 }
 
-#[derive(Serialize)]
-struct ErrorResponse {
-    msg: String,
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub msg: String,
+    /// Set when an execution ran into one of its limits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<ExecutionLimit>,
+    /// Set when a python policy broke a sandbox rule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub violation: Option<PolicyViolation>,
 }
@@ -4,6 +4,7 @@ mod agent;
 mod config;
 mod error;
 mod server;
+mod session;
 mod types;
 mod utils;
 
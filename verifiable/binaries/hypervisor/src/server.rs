@@ -1,11 +1,20 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
 use axum::http::HeaderValue;
-use axum::{http::Method, Router};
+use axum::{extract::DefaultBodyLimit, http::Method, Router};
 use tower_http::cors::CorsLayer;
 
-use crate::api::{self, RouterRegister};
+use crate::api::{self, execute::wasm::bundle, RouterRegister};
+use crate::session::SealedFileStore;
 use crate::types::{HypervisorState, ServerContext};
+use crate::utils::attest::ResponseKey;
 use crate::Config;
 
+/// How often expired sessions, seen request digests and finished jobs are
+/// dropped
+const SESSION_REAP_INTERVAL: Duration = Duration::from_secs(30);
+
 pub struct Server {
     app: Router,
     ctx: ServerContext,
@@ -13,26 +22,54 @@ pub struct Server {
 
 impl Server {
     pub fn build(config: Config) -> anyhow::Result<Self> {
-        let state = HypervisorState::new(config);
+        let mut state = HypervisorState::new(config);
+        if state.config.signed_responses {
+            state = state.with_response_key(ResponseKey::generate()?);
+        }
+        if let Some(path) = state.config.session_store_path.clone() {
+            // TDX derives no sealing key, the store is refused there
+            let sealing_key = attest::seal::get_sealing_key(state.config.mock_sealing_key)
+                .context(
+                    "session_store_path needs a sev-snp guest built with the sev-guest feature, \
+                     or mock_sealing_key on a mock-attest build",
+                )?;
+            let store = SealedFileStore::open(path, &sealing_key).context("open session store")?;
+            state = state.with_session_store(Arc::new(store));
+        }
+
+        if !bundle::is_memory_backed(&state.config.wasm_work_dir) {
+            tracing::warn!(
+                "wasm_work_dir {} isn't a tmpfs, decrypted bundles reach its disk",
+                state.config.wasm_work_dir.display()
+            );
+        }
 
         let ctx = ServerContext {
             state: state.clone(),
         };
 
+        let body_limit = DefaultBodyLimit::max(state.config.request_body_limit_bytes);
+
         let app = Router::new()
             .register_api(api::ping::api_register)
+            .register_api(api::attestation::api_register)
             .register_api(api::encrypt::api_register)
             .register_api(api::execute::wasm::api_register)
+            .register_api(api::execute::riscv::api_register)
             .register_api(api::policy::python::api_register)
             .register_api(api::search::api_register)
             .register_api(api::agent::api_register)
+            .register_api(api::jobs::api_register)
             .register_x402_api(state.clone(), api::execute::wasm::api_x402_register)
+            .register_x402_api(state.clone(), api::execute::riscv::api_x402_register)
             .register_x402_api(state.clone(), api::policy::python::api_x402_register)
+            .register_x402_api(state.clone(), api::jobs::api_x402_register)
             .with_state(state)
+            .layer(body_limit)
             .layer(
                 CorsLayer::new()
                     .allow_origin("*".parse::<HeaderValue>()?)
-                    .allow_methods([Method::GET, Method::POST]),
+                    .allow_methods([Method::GET, Method::POST, Method::DELETE]),
             );
 
         Ok(Server { app, ctx })
@@ -44,6 +81,21 @@ impl Server {
         let listener = tokio::net::TcpListener::bind(config.listening).await?;
         tracing::info!("listening on {}", config.listening);
 
+        let state = self.ctx.state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SESSION_REAP_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                let reaped = state.reap_sessions();
+                if reaped > 0 {
+                    tracing::debug!(reaped, "expired sessions dropped");
+                }
+                state.seen_requests().reap();
+                state.jobs().reap();
+            }
+        });
+
         axum::serve(listener, self.app).await?;
 
         Ok(())
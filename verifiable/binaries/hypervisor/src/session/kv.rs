@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use dashmap::DashMap;
+use uuid::Uuid;
+
+pub const DEFAULT_SESSION_KV_LIMIT_BYTES: usize = 1024 * 1024;
+
+/// Key value stores of the sessions, behind the `x402:host/kv` import
+///
+/// Kept in memory only, a store goes with its session and on restart.
+#[derive(Default)]
+pub struct SessionKv {
+    stores: DashMap<Uuid, Store>,
+}
+
+#[derive(Default)]
+struct Store {
+    entries: BTreeMap<String, Vec<u8>>,
+    /// Keys and values summed
+    size_bytes: usize,
+}
+
+impl SessionKv {
+    pub fn get(&self, session_id: Uuid, key: &str) -> Option<Vec<u8>> {
+        let store = self.stores.get(&session_id)?;
+
+        store.entries.get(key).cloned()
+    }
+
+    /// Set `key`, refused when the store would grow past `limit` bytes
+    pub fn set(
+        &self,
+        session_id: Uuid,
+        key: String,
+        value: Vec<u8>,
+        limit: usize,
+    ) -> anyhow::Result<()> {
+        let mut store = self.stores.entry(session_id).or_default();
+
+        let replaced = { store.entries.get(&key) }.map_or(0, |v| key.len() + v.len());
+        let size_bytes = store.size_bytes - replaced + key.len() + value.len();
+        anyhow::ensure!(
+            size_bytes <= limit,
+            "session store larger than {limit} bytes"
+        );
+
+        store.size_bytes = size_bytes;
+        store.entries.insert(key, value);
+
+        Ok(())
+    }
+
+    pub fn delete(&self, session_id: Uuid, key: &str) {
+        if let Some(mut store) = self.stores.get_mut(&session_id) {
+            if let Some(value) = store.entries.remove(key) {
+                store.size_bytes -= key.len() + value.len();
+            }
+        }
+    }
+
+    /// Drop the store of a closed session
+    pub fn remove(&self, session_id: Uuid) {
+        self.stores.remove(&session_id);
+    }
+
+    /// Keep the stores of the sessions `live` accepts
+    pub fn retain(&self, live: impl Fn(Uuid) -> bool) {
+        self.stores.retain(|session_id, _| live(*session_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_kv() {
+        let kv = SessionKv::default();
+        let (session, other) = (Uuid::now_v7(), Uuid::now_v7());
+
+        kv.set(session, "key".to_string(), b"value".to_vec(), 16)
+            .unwrap();
+        assert_eq!(kv.get(session, "key").unwrap(), b"value");
+        assert!(kv.get(other, "key").is_none());
+
+        // Replacing a value only counts the difference
+        kv.set(session, "key".to_string(), vec![0; 13], 16).unwrap();
+        assert!(kv.set(session, "k".to_string(), vec![], 16).is_err());
+
+        kv.delete(session, "key");
+        assert!(kv.get(session, "key").is_none());
+        kv.set(session, "k".to_string(), vec![0; 15], 16).unwrap();
+
+        kv.retain(|session_id| session_id != session);
+        assert!(kv.get(session, "k").is_none());
+    }
+}
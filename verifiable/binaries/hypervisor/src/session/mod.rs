@@ -0,0 +1,179 @@
+mod kv;
+mod sealed;
+mod store;
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use k256::ecdsa::{SigningKey, VerifyingKey};
+use uuid::Uuid;
+
+pub use kv::{SessionKv, DEFAULT_SESSION_KV_LIMIT_BYTES};
+pub use sealed::SealedFileStore;
+pub use store::{MemorySessionStore, Session, SessionStore};
+
+pub const DEFAULT_SESSION_TTL_SECS: u64 = 24 * 60 * 60;
+pub const DEFAULT_SESSION_IDLE_TIMEOUT_SECS: u64 = 30 * 60;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SessionLimits {
+    /// Lifetime from creation
+    pub ttl: Duration,
+    /// Lifetime from last use
+    pub idle_timeout: Duration,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        SessionLimits {
+            ttl: Duration::from_secs(DEFAULT_SESSION_TTL_SECS),
+            idle_timeout: Duration::from_secs(DEFAULT_SESSION_IDLE_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl SessionLimits {
+    fn is_expired(&self, session: &Session, now: SystemTime) -> bool {
+        let age = |since: SystemTime| now.duration_since(since).unwrap_or_default();
+
+        age(session.created_at) > self.ttl || age(session.last_used) > self.idle_timeout
+    }
+}
+
+/// Session key pairs by session id
+///
+/// A client may hold several sessions at once. Expired sessions are
+/// refused on lookup and dropped by [`SessionKeyPairs::reap`], dropping a
+/// `SigningKey` zeroizes it.
+#[derive(Clone)]
+pub struct SessionKeyPairs {
+    store: Arc<dyn SessionStore>,
+    limits: SessionLimits,
+}
+
+impl Default for SessionKeyPairs {
+    fn default() -> Self {
+        SessionKeyPairs::new(SessionLimits::default())
+    }
+}
+
+impl SessionKeyPairs {
+    pub fn new(limits: SessionLimits) -> Self {
+        SessionKeyPairs {
+            store: Arc::new(MemorySessionStore::default()),
+            limits,
+        }
+    }
+
+    pub fn with_store(self, store: Arc<dyn SessionStore>) -> Self {
+        SessionKeyPairs { store, ..self }
+    }
+
+    pub fn create(&self, pubkey: &VerifyingKey) -> (VerifyingKey, Uuid) {
+        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
+        let pk = sk.verifying_key().to_owned();
+        let uuid = Uuid::now_v7();
+
+        let now = SystemTime::now();
+        let session = Session {
+            user_pk: pubkey.to_encoded_point(true),
+            signing_key: sk,
+            created_at: now,
+            last_used: now,
+        };
+        self.store.insert(uuid, session);
+
+        (pk, uuid)
+    }
+
+    /// Look up a live session of `pubkey` and mark it used
+    ///
+    /// Clients that don't send a session id get their latest session.
+    pub fn get(
+        &self,
+        pubkey: &VerifyingKey,
+        session_id: Option<Uuid>,
+    ) -> Option<(SigningKey, Uuid)> {
+        let owner = pubkey.to_encoded_point(true);
+        let now = SystemTime::now();
+
+        let session_id = match session_id {
+            Some(session_id) => session_id,
+            None => self
+                .store
+                .latest(&owner, &|s| !self.limits.is_expired(s, now))?,
+        };
+
+        let session = self.store.get(session_id)?;
+        if session.user_pk != owner || self.limits.is_expired(&session, now) {
+            return None;
+        }
+        self.store.touch(session_id, now);
+
+        Some((session.signing_key, session_id))
+    }
+
+    /// Drop a session of `pubkey`, returns whether it existed
+    pub fn close(&self, pubkey: &VerifyingKey, session_id: Uuid) -> bool {
+        let owner = pubkey.to_encoded_point(true);
+
+        self.store.remove_if(session_id, &|s| s.user_pk == owner)
+    }
+
+    pub fn contains(&self, session_id: Uuid) -> bool {
+        self.store.get(session_id).is_some()
+    }
+
+    /// Drop expired sessions, returns how many were dropped
+    pub fn reap(&self) -> usize {
+        let now = SystemTime::now();
+
+        self.store.retain(&|s| !self.limits.is_expired(s, now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_pk() -> VerifyingKey {
+        *k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng).verifying_key()
+    }
+
+    #[test]
+    fn test_sessions() {
+        let sessions = SessionKeyPairs::default();
+        let (user, other) = (user_pk(), user_pk());
+
+        let (_, first) = sessions.create(&user);
+        let (_, second) = sessions.create(&user);
+
+        assert_eq!(sessions.get(&user, Some(first)).unwrap().1, first);
+        assert_eq!(sessions.get(&user, None).unwrap().1, second);
+        assert!(sessions.get(&other, Some(first)).is_none());
+
+        assert!(!sessions.close(&other, first));
+        assert!(sessions.close(&user, first));
+        assert!(sessions.get(&user, Some(first)).is_none());
+        assert_eq!(sessions.get(&user, None).unwrap().1, second);
+    }
+
+    #[test]
+    fn test_sessions_expiry() {
+        let sessions = SessionKeyPairs::new(SessionLimits {
+            ttl: Duration::from_secs(60),
+            idle_timeout: Duration::ZERO,
+        });
+        let user = user_pk();
+
+        let (_, session_id) = sessions.create(&user);
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert!(sessions.get(&user, Some(session_id)).is_none());
+        assert!(sessions.get(&user, None).is_none());
+        assert_eq!(sessions.reap(), 1);
+        assert_eq!(sessions.reap(), 0);
+    }
+}
@@ -0,0 +1,340 @@
+//! Sessions persisted to a file only the same measured image can read
+//!
+//! The file holds `nonce || ciphertext`, the sessions as JSON encrypted
+//! under a key derived from [`attest::seal::get_sealing_key`]. Lookups are
+//! served from memory. Changes and reaps mark the store dirty, a writer
+//! thread then rewrites the file, at most once per [`PERSIST_DEBOUNCE`], so
+//! request handlers never wait on the disk. Rewrites also save the last use
+//! times, dropping the store writes what's left.
+
+use std::{
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use aes_gcm_siv::{
+    aead::{Aead, Payload},
+    Aes256GcmSiv, KeyInit, Nonce,
+};
+use anyhow::{anyhow, Context};
+use k256::{
+    ecdsa::SigningKey,
+    elliptic_curve::zeroize::{Zeroize, Zeroizing},
+    EncodedPoint,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::store::{MemorySessionStore, Session, SessionStore};
+use crate::utils::crypto::NONCE_LEN;
+
+const SEALED_FILE_AAD: &[u8] = b"x402-hypervisor sessions v1";
+
+/// Shortest time between two rewrites, changes in between are written
+/// together
+pub const PERSIST_DEBOUNCE: Duration = Duration::from_millis(200);
+
+pub struct SealedFileStore {
+    sealed: Arc<SealedFile>,
+    writer: Option<JoinHandle<()>>,
+}
+
+/// What the store shares with its writer thread
+struct SealedFile {
+    sessions: MemorySessionStore,
+    path: PathBuf,
+    cipher: Aes256GcmSiv,
+    state: Mutex<WriterState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct WriterState {
+    dirty: bool,
+    closed: bool,
+}
+
+impl SealedFileStore {
+    /// Load the sessions sealed at `path`, a file sealed by another image
+    /// or host can't be read and is replaced
+    pub fn open(path: impl Into<PathBuf>, sealing_key: &[u8; 32]) -> anyhow::Result<Self> {
+        let sealed = SealedFile {
+            sessions: MemorySessionStore::default(),
+            path: path.into(),
+            cipher: sealing_cipher(sealing_key)?,
+            state: Mutex::default(),
+            changed: Condvar::new(),
+        };
+
+        match std::fs::read(&sealed.path) {
+            Ok(bytes) => match sealed.unseal(&bytes) {
+                Ok(sessions) => {
+                    tracing::info!(count = sessions.len(), "sealed sessions restored");
+
+                    for (session_id, session) in sessions {
+                        sealed.sessions.insert(session_id, session);
+                    }
+                }
+                Err(e) => tracing::warn!("sealed sessions dropped, {e:#}"),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context(format!("read {}", sealed.path.display())),
+        }
+
+        let sealed = Arc::new(sealed);
+        let writer = std::thread::Builder::new()
+            .name("session-store".to_string())
+            .spawn({
+                let sealed = sealed.clone();
+                move || sealed.write_behind()
+            })
+            .context("spawn session store writer")?;
+
+        Ok(SealedFileStore {
+            sealed,
+            writer: Some(writer),
+        })
+    }
+}
+
+impl Drop for SealedFileStore {
+    fn drop(&mut self) {
+        self.sealed.lock_state().closed = true;
+        self.sealed.changed.notify_one();
+
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl SealedFile {
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, WriterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Have the writer rewrite the file
+    fn mark_dirty(&self) {
+        self.lock_state().dirty = true;
+        self.changed.notify_one();
+    }
+
+    /// Rewrite the file whenever marked dirty, until closed and clean
+    fn write_behind(&self) {
+        let mut state = self.lock_state();
+        loop {
+            state = self
+                .changed
+                .wait_while(state, |s| !s.dirty && !s.closed)
+                .unwrap_or_else(|e| e.into_inner());
+            if !state.dirty {
+                return;
+            }
+
+            state.dirty = false;
+            drop(state);
+            self.persist();
+
+            // Changes of the next moment are written together, closing
+            // writes them right away
+            let debounce =
+                self.changed
+                    .wait_timeout_while(self.lock_state(), PERSIST_DEBOUNCE, |s| !s.closed);
+            state = debounce.map_or_else(|e| e.into_inner().0, |(state, _)| state);
+        }
+    }
+
+    fn unseal(&self, sealed: &[u8]) -> anyhow::Result<Vec<(Uuid, Session)>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow!("sealed file too short"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+        let payload = Payload {
+            msg: ciphertext,
+            aad: SEALED_FILE_AAD,
+        };
+        let plaintext = { self.cipher.decrypt(Nonce::from_slice(nonce), payload) }
+            .map(Zeroizing::new)
+            .map_err(|_| anyhow!("unseal failed, sealed by another image or host"))?;
+
+        let stored: Vec<StoredSession> =
+            serde_json::from_slice(&plaintext).context("parse sealed sessions")?;
+
+        stored.iter().map(StoredSession::to_session).collect()
+    }
+
+    fn persist(&self) {
+        if let Err(e) = self.try_persist() {
+            tracing::error!("persist sessions to {}: {e:#}", self.path.display());
+        }
+    }
+
+    fn try_persist(&self) -> anyhow::Result<()> {
+        let stored: Vec<_> = { self.sessions.snapshot().iter() }
+            .map(|(session_id, session)| StoredSession::new(*session_id, session))
+            .collect();
+        let plaintext = Zeroizing::new(serde_json::to_vec(&stored)?);
+
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+        let payload = Payload {
+            msg: &plaintext,
+            aad: SEALED_FILE_AAD,
+        };
+        let ciphertext = { self.cipher.encrypt(Nonce::from_slice(&nonce), payload) }
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        // Written aside and renamed, a crash never leaves a torn file
+        let tmp = self.path.with_extension("tmp");
+        let mut file = std::fs::File::options()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?;
+        file.write_all(&nonce)?;
+        file.write_all(&ciphertext)?;
+        file.sync_all()?;
+
+        std::fs::rename(&tmp, &self.path)?;
+
+        Ok(())
+    }
+}
+
+impl SessionStore for SealedFileStore {
+    fn insert(&self, session_id: Uuid, session: Session) {
+        self.sealed.sessions.insert(session_id, session);
+        self.sealed.mark_dirty();
+    }
+
+    fn get(&self, session_id: Uuid) -> Option<Session> {
+        self.sealed.sessions.get(session_id)
+    }
+
+    fn latest(&self, owner: &EncodedPoint, live: &dyn Fn(&Session) -> bool) -> Option<Uuid> {
+        self.sealed.sessions.latest(owner, live)
+    }
+
+    fn touch(&self, session_id: Uuid, at: SystemTime) {
+        self.sealed.sessions.touch(session_id, at);
+    }
+
+    fn remove_if(&self, session_id: Uuid, f: &dyn Fn(&Session) -> bool) -> bool {
+        let removed = self.sealed.sessions.remove_if(session_id, f);
+        if removed {
+            self.sealed.mark_dirty();
+        }
+
+        removed
+    }
+
+    fn retain(&self, f: &dyn Fn(&Session) -> bool) -> usize {
+        let dropped = self.sealed.sessions.retain(f);
+        self.sealed.mark_dirty();
+
+        dropped
+    }
+}
+
+fn sealing_cipher(sealing_key: &[u8; 32]) -> anyhow::Result<Aes256GcmSiv> {
+    let hkdf = hkdf::Hkdf::<k256::sha2::Sha256>::new(None, sealing_key);
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    hkdf.expand(b"x402-hypervisor session store", key.as_mut())
+        .map_err(|e| anyhow!(e.to_string()))?;
+
+    Ok(Aes256GcmSiv::new(
+        aes_gcm_siv::Key::<Aes256GcmSiv>::from_slice(key.as_ref()),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredSession {
+    session_id: Uuid,
+    user_pk: String,
+    signing_key: String,
+    /// Unix seconds
+    created_at: u64,
+    last_used: u64,
+}
+
+impl Drop for StoredSession {
+    fn drop(&mut self) {
+        self.signing_key.zeroize();
+    }
+}
+
+impl StoredSession {
+    fn new(session_id: Uuid, session: &Session) -> Self {
+        let unix_secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        StoredSession {
+            session_id,
+            user_pk: const_hex::encode(session.user_pk.as_bytes()),
+            signing_key: const_hex::encode(session.signing_key.to_bytes()),
+            created_at: unix_secs(session.created_at),
+            last_used: unix_secs(session.last_used),
+        }
+    }
+
+    fn to_session(&self) -> anyhow::Result<(Uuid, Session)> {
+        let user_pk = EncodedPoint::from_bytes(const_hex::decode(&self.user_pk)?)?;
+        let signing_key = Zeroizing::new(const_hex::decode(&self.signing_key)?);
+        let from_unix_secs = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+
+        let session = Session {
+            user_pk,
+            signing_key: SigningKey::from_slice(&signing_key)?,
+            created_at: from_unix_secs(self.created_at),
+            last_used: from_unix_secs(self.last_used),
+        };
+
+        Ok((self.session_id, session))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_file_store() {
+        let path = std::env::temp_dir().join(format!("sessions-{}.sealed", std::process::id()));
+
+        let signing_key = SigningKey::random(&mut rand::rngs::OsRng);
+        let session_id = Uuid::now_v7();
+        let now = SystemTime::now();
+
+        let store = SealedFileStore::open(&path, &[1u8; 32]).unwrap();
+        store.insert(
+            session_id,
+            Session {
+                user_pk: signing_key.verifying_key().to_encoded_point(true),
+                signing_key: signing_key.clone(),
+                created_at: now,
+                last_used: now,
+            },
+        );
+        drop(store);
+
+        // Restarted same image
+        let store = SealedFileStore::open(&path, &[1u8; 32]).unwrap();
+        let session = store.get(session_id).unwrap();
+        assert_eq!(session.signing_key, signing_key);
+        assert_eq!(
+            session.user_pk,
+            signing_key.verifying_key().to_encoded_point(true)
+        );
+
+        // Other image, sessions can't be unsealed
+        let store = SealedFileStore::open(&path, &[2u8; 32]).unwrap();
+        assert!(store.get(session_id).is_none());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+use k256::{ecdsa::SigningKey, EncodedPoint};
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct Session {
+    /// Compressed public key of the client owning the session
+    pub user_pk: EncodedPoint,
+    pub signing_key: SigningKey,
+    pub created_at: SystemTime,
+    pub last_used: SystemTime,
+}
+
+/// Where [`super::SessionKeyPairs`] keeps its sessions, expiry is decided
+/// by the caller
+pub trait SessionStore: Send + Sync {
+    fn insert(&self, session_id: Uuid, session: Session);
+
+    fn get(&self, session_id: Uuid) -> Option<Session>;
+
+    /// Latest session of `owner` accepted by `live`
+    fn latest(&self, owner: &EncodedPoint, live: &dyn Fn(&Session) -> bool) -> Option<Uuid>;
+
+    fn touch(&self, session_id: Uuid, at: SystemTime);
+
+    /// Remove a session if `f` accepts it, returns whether it was removed
+    fn remove_if(&self, session_id: Uuid, f: &dyn Fn(&Session) -> bool) -> bool;
+
+    /// Keep the sessions `f` accepts, returns how many were dropped
+    fn retain(&self, f: &dyn Fn(&Session) -> bool) -> usize;
+}
+
+/// Sessions of this process only, lost on restart
+#[derive(Default)]
+pub struct MemorySessionStore(DashMap<Uuid, Session>);
+
+impl MemorySessionStore {
+    pub(super) fn snapshot(&self) -> Vec<(Uuid, Session)> {
+        { self.0.iter() }
+            .map(|s| (*s.key(), s.value().clone()))
+            .collect()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn insert(&self, session_id: Uuid, session: Session) {
+        self.0.insert(session_id, session);
+    }
+
+    fn get(&self, session_id: Uuid) -> Option<Session> {
+        self.0.get(&session_id).map(|s| s.value().clone())
+    }
+
+    fn latest(&self, owner: &EncodedPoint, live: &dyn Fn(&Session) -> bool) -> Option<Uuid> {
+        { self.0.iter() }
+            .filter(|s| &s.user_pk == owner && live(s))
+            .max_by_key(|s| (s.created_at, *s.key()))
+            .map(|s| *s.key())
+    }
+
+    fn touch(&self, session_id: Uuid, at: SystemTime) {
+        if let Some(mut session) = self.0.get_mut(&session_id) {
+            session.last_used = at;
+        }
+    }
+
+    fn remove_if(&self, session_id: Uuid, f: &dyn Fn(&Session) -> bool) -> bool {
+        self.0.remove_if(&session_id, |_, s| f(s)).is_some()
+    }
+
+    fn retain(&self, f: &dyn Fn(&Session) -> bool) -> usize {
+        let before = self.0.len();
+
+        self.0.retain(|_, s| f(s));
+
+        before.saturating_sub(self.0.len())
+    }
+}
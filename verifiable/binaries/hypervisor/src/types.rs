@@ -1,29 +1,81 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use k256::{
-    ecdsa::{SigningKey, VerifyingKey},
-    EncodedPoint,
-};
+use k256::ecdsa::{SigningKey, VerifyingKey};
 use tokio::{sync::Mutex, task::JoinHandle};
 use uuid::Uuid;
 
-use crate::Config;
+use crate::{
+    api::{execute::wasm::cache::ComponentCache, jobs::Jobs},
+    session::{SessionKeyPairs, SessionKv, SessionLimits, SessionStore},
+    utils::{attest::ResponseKey, auth::SeenRequests},
+    Config,
+};
 
 #[derive(Clone, Default)]
 pub struct HypervisorState {
     pub config: Config,
     session_key_pairs: SessionKeyPairs,
     agent_service: Arc<Mutex<Option<AgentService>>>,
+    response_key: Option<Arc<ResponseKey>>,
+    seen_requests: SeenRequests,
+    components: Arc<ComponentCache>,
+    session_kv: Arc<SessionKv>,
+    jobs: Arc<Jobs>,
 }
 
 impl HypervisorState {
     pub fn new(config: Config) -> Self {
+        let limits = SessionLimits {
+            ttl: Duration::from_secs(config.session_ttl_secs),
+            idle_timeout: Duration::from_secs(config.session_idle_timeout_secs),
+        };
+        let components = ComponentCache::new(config.wasm_cache_bytes).expect("wasm engine");
+        let jobs = Jobs::new(
+            config.job_workers,
+            config.job_queue_depth,
+            Duration::from_secs(config.job_retention_secs),
+        );
+
         HypervisorState {
             config,
+            session_key_pairs: SessionKeyPairs::new(limits),
+            components: Arc::new(components),
+            jobs: Arc::new(jobs),
             ..Default::default()
         }
     }
 
+    pub fn with_response_key(mut self, response_key: ResponseKey) -> Self {
+        self.response_key = Some(Arc::new(response_key));
+        self
+    }
+
+    pub fn response_key(&self) -> Option<&ResponseKey> {
+        self.response_key.as_deref()
+    }
+
+    /// Keep sessions in `store` instead of memory only
+    pub fn with_session_store(mut self, store: Arc<dyn SessionStore>) -> Self {
+        self.session_key_pairs = self.session_key_pairs.with_store(store);
+        self
+    }
+
+    pub fn seen_requests(&self) -> &SeenRequests {
+        &self.seen_requests
+    }
+
+    pub fn components(&self) -> &ComponentCache {
+        &self.components
+    }
+
+    pub fn session_kv(&self) -> Arc<SessionKv> {
+        self.session_kv.clone()
+    }
+
+    pub fn jobs(&self) -> &Arc<Jobs> {
+        &self.jobs
+    }
+
     pub async fn set_agent(&self, agent_name: String, handle: JoinHandle<anyhow::Result<()>>) {
         let mut agent_service = self.agent_service.lock().await;
 
@@ -53,37 +105,40 @@ impl HypervisorState {
         self.session_key_pairs = session_key_pairs;
     }
 
-    pub fn create_session_keypair(self, pubkey: &VerifyingKey) -> (VerifyingKey, Uuid) {
+    pub fn create_session_keypair(&self, pubkey: &VerifyingKey) -> (VerifyingKey, Uuid) {
         self.session_key_pairs.create(pubkey)
     }
 
-    pub fn get_session_keypair(self, pubkey: &VerifyingKey) -> Option<(SigningKey, Uuid)> {
-        self.session_key_pairs
-            .0
-            .get(&pubkey.to_encoded_point(true))
-            .map(|i| i.to_owned())
+    pub fn get_session_keypair(
+        &self,
+        pubkey: &VerifyingKey,
+        session_id: Option<Uuid>,
+    ) -> Option<(SigningKey, Uuid)> {
+        self.session_key_pairs.get(pubkey, session_id)
     }
-}
-
-pub struct ServerContext {
-    pub state: HypervisorState,
-}
 
-#[derive(Clone, Default)]
-pub struct SessionKeyPairs(Arc<dashmap::DashMap<EncodedPoint, (SigningKey, Uuid)>>);
+    pub fn close_session(&self, pubkey: &VerifyingKey, session_id: Uuid) -> bool {
+        let closed = self.session_key_pairs.close(pubkey, session_id);
+        if closed {
+            self.session_kv.remove(session_id);
+        }
 
-impl SessionKeyPairs {
-    pub fn create(self, pubkey: &VerifyingKey) -> (VerifyingKey, Uuid) {
-        let sk = k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng);
-        let pk = sk.verifying_key().to_owned();
-        let uuid = Uuid::now_v7();
+        closed
+    }
 
-        self.0.insert(pubkey.to_encoded_point(true), (sk, uuid));
+    pub fn reap_sessions(&self) -> usize {
+        let reaped = self.session_key_pairs.reap();
+        self.session_kv
+            .retain(|session_id| self.session_key_pairs.contains(session_id));
 
-        (pk, uuid)
+        reaped
     }
 }
 
+pub struct ServerContext {
+    pub state: HypervisorState,
+}
+
 struct AgentService {
     name: String,
     service_handle: JoinHandle<anyhow::Result<()>>,
@@ -1,23 +1,101 @@
-use attest::types::RawReport;
+use anyhow::Context;
+use attest::{event_log::EventKind, report_data::ReportData, types::Quote};
+use axum::http::StatusCode;
+use k256::ecdsa::{signature::Signer, Signature, SigningKey, VerifyingKey};
 
-pub fn generate_raw_report(data: &[impl AsRef<[u8]>]) -> RawReport {
-    let mut hasher = blake3::Hasher::new();
+use crate::{error::HypervisorError, types::HypervisorState};
 
-    for d in data {
-        hasher.update(d.as_ref());
+/// What a failed RTMR3 extension does to the request measuring an artifact
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Measurement {
+    /// Fails it with 500, a quote must not attest a result whose artifact
+    /// RTMR3 never recorded
+    Required,
+    /// Only logs it, so the `/test/*` routes keep working outside a TEE.
+    /// Verifiers replaying the event log will miss the artifact then
+    BestEffort,
+}
+
+/// Extend RTMR3 with an artifact about to run
+pub fn measure_artifact(
+    measurement: Measurement,
+    kind: EventKind,
+    description: &str,
+    artifact: &[u8],
+) -> Result<(), HypervisorError> {
+    let Err(err) = attest::event_log::measure(kind, description, artifact) else {
+        return Ok(());
+    };
+
+    match measurement {
+        Measurement::Required => Err(anyhow::Error::new(err)
+            .context(format!("measure {description}"))
+            .context(StatusCode::INTERNAL_SERVER_ERROR)
+            .into()),
+        Measurement::BestEffort => {
+            tracing::warn!(%err, "measure {description}");
+            Ok(())
+        }
     }
+}
 
-    let h: [u8; 32] = hasher.finalize().into();
+/// Long lived key attesting responses, quoted once when generated
+pub struct ResponseKey {
+    signing_key: SigningKey,
+    quote: Quote,
+}
 
-    let mut report = [0u8; 64];
-    report[..32].copy_from_slice(&h);
+impl ResponseKey {
+    pub fn generate() -> anyhow::Result<Self> {
+        let signing_key = SigningKey::random(&mut rand::rngs::OsRng);
+        let report = ReportData::ResponseKey {
+            public_key: *signing_key.verifying_key(),
+        };
+        let quote = attest::get_quote(report.to_raw()).context("get response key quote")?;
+
+        Ok(ResponseKey { signing_key, quote })
+    }
+
+    pub fn public_key(&self) -> &VerifyingKey {
+        self.signing_key.verifying_key()
+    }
 
-    RawReport::new(report)
+    pub fn quote(&self) -> &Quote {
+        &self.quote
+    }
+}
+
+/// Evidence for the report data of a response
+///
+/// Either a quote over the report data itself, or the response key quote
+/// plus a signature over the encoded report data.
+pub struct Attestation {
+    pub quote: Quote,
+    pub signature: Option<Signature>,
 }
 
-pub fn generate_raw_report_from_hash(h: [u8; 32]) -> RawReport {
-    let mut report = [0u8; 64];
-    report[..32].copy_from_slice(&h);
+impl Attestation {
+    pub fn quote_hex(&self) -> String {
+        const_hex::encode(self.quote.to_bytes())
+    }
+
+    pub fn signature_hex(&self) -> Option<String> {
+        self.signature.map(|s| const_hex::encode(s.to_bytes()))
+    }
+}
 
-    RawReport::new(report)
+pub fn attest_response(
+    state: &HypervisorState,
+    report_data: ReportData,
+) -> anyhow::Result<Attestation> {
+    match state.response_key() {
+        Some(key) => Ok(Attestation {
+            quote: key.quote().clone(),
+            signature: Some(key.signing_key.sign(&report_data.encode())),
+        }),
+        None => Ok(Attestation {
+            quote: attest::get_quote(report_data.to_raw())?,
+            signature: None,
+        }),
+    }
 }
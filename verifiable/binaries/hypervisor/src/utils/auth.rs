@@ -0,0 +1,334 @@
+//! Optional client signatures on execute requests
+//!
+//! A client signs with the key it registered its session with:
+//!
+//! ```text
+//! X-Request-Timestamp: <unix seconds>
+//! X-Request-Signature: hex(ecdsa(request_digest(timestamp, body)))
+//! ```
+//!
+//! The body is hashed exactly as sent, so the client signs the bytes it
+//! puts on the wire. A digest is accepted once while its timestamp is in
+//! the window, kept signatures let the operator prove who requested an
+//! execution.
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+use k256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use serde::de::DeserializeOwned;
+
+use crate::{error::HypervisorError, types::HypervisorState, utils::hasher};
+
+pub const TIMESTAMP_HEADER: &str = "x-request-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-request-signature";
+
+const DIGEST_DOMAIN: &[u8] = b"x402-hypervisor request v1";
+
+/// Message a client signs for a request body sent at `timestamp`
+pub fn request_digest(timestamp: u64, body: &[u8]) -> [u8; 32] {
+    hasher::hash_multi(&[DIGEST_DOMAIN, &timestamp.to_be_bytes(), body])
+}
+
+/// Request signature as sent, checked by [`authenticate`]
+#[derive(Debug, Clone)]
+pub struct RequestSignature {
+    pub timestamp: u64,
+    pub signature: Signature,
+    digest: [u8; 32],
+}
+
+impl RequestSignature {
+    fn from_headers(headers: &HeaderMap) -> Result<Option<(u64, Signature)>, HypervisorError> {
+        let (timestamp, signature) =
+            match (headers.get(TIMESTAMP_HEADER), headers.get(SIGNATURE_HEADER)) {
+                (None, None) => return Ok(None),
+                (Some(timestamp), Some(signature)) => (timestamp, signature),
+                _ => {
+                    return Err(anyhow!("request timestamp and signature go together")
+                        .context(StatusCode::BAD_REQUEST)
+                        .into())
+                }
+            };
+
+        let timestamp = { timestamp.to_str().ok() }
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| anyhow!("invalid request timestamp"))
+            .context(StatusCode::BAD_REQUEST)?;
+
+        let signature = { signature.to_str().ok() }
+            .and_then(|s| const_hex::decode(s).ok())
+            .and_then(|raw| Signature::from_slice(&raw).ok())
+            .ok_or_else(|| anyhow!("invalid request signature"))
+            .context(StatusCode::BAD_REQUEST)?;
+
+        Ok(Some((timestamp, signature)))
+    }
+
+    /// Signature of a request without a body, made over `message` in its
+    /// place
+    pub fn from_message(
+        headers: &HeaderMap,
+        message: &[u8],
+    ) -> Result<Option<Self>, HypervisorError> {
+        let header = RequestSignature::from_headers(headers)?;
+
+        Ok(header.map(|(timestamp, signature)| RequestSignature {
+            timestamp,
+            signature,
+            digest: request_digest(timestamp, message),
+        }))
+    }
+
+    pub fn signature_hex(&self) -> String {
+        const_hex::encode(self.signature.to_bytes())
+    }
+}
+
+/// JSON body along with its request signature, if any
+pub struct SignedJson<T> {
+    pub body: T,
+    pub signature: Option<RequestSignature>,
+}
+
+impl<T, S> FromRequest<S> for SignedJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let header =
+            RequestSignature::from_headers(req.headers()).map_err(|e| e.into_response())?;
+
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| e.into_response())?;
+        let Json(body) = Json::<T>::from_bytes(&bytes).map_err(|e| e.into_response())?;
+
+        let signature = header.map(|(timestamp, signature)| RequestSignature {
+            timestamp,
+            signature,
+            digest: request_digest(timestamp, &bytes),
+        });
+
+        Ok(SignedJson { body, signature })
+    }
+}
+
+/// Check a request signature was made by the session owner and isn't a
+/// replay, unsigned requests pass unless signatures are required
+pub fn authenticate(
+    state: &HypervisorState,
+    user_pk: &VerifyingKey,
+    signature: Option<&RequestSignature>,
+) -> Result<(), HypervisorError> {
+    let Some(signature) = signature else {
+        if state.config.require_signed_requests {
+            return Err(anyhow!("request signature required")
+                .context(StatusCode::UNAUTHORIZED)
+                .into());
+        }
+
+        return Ok(());
+    };
+
+    verify(state, user_pk, signature)?;
+
+    let window = state.config.request_signature_window_secs;
+    if !state
+        .seen_requests()
+        .insert(signature.digest, signature.timestamp + window)
+    {
+        return Err(anyhow!("request replayed")
+            .context(StatusCode::CONFLICT)
+            .into());
+    }
+
+    Ok(())
+}
+
+/// Check a request signature was made by `user_pk` within the window,
+/// replays included
+pub fn verify(
+    state: &HypervisorState,
+    user_pk: &VerifyingKey,
+    signature: &RequestSignature,
+) -> Result<(), HypervisorError> {
+    let window = state.config.request_signature_window_secs;
+    if unix_now().abs_diff(signature.timestamp) > window {
+        return Err(anyhow!("request timestamp outside the {window}s window")
+            .context(StatusCode::UNAUTHORIZED)
+            .into());
+    }
+
+    user_pk
+        .verify(&signature.digest, &signature.signature)
+        .context("request not signed by session owner")
+        .context(StatusCode::UNAUTHORIZED)?;
+
+    Ok(())
+}
+
+/// Digests of accepted signed requests, kept until their timestamp leaves
+/// the window
+#[derive(Clone, Default)]
+pub struct SeenRequests(Arc<DashMap<[u8; 32], u64>>);
+
+impl SeenRequests {
+    /// Returns false if `digest` was already seen
+    fn insert(&self, digest: [u8; 32], expires_at: u64) -> bool {
+        match self.0.entry(digest) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(expires_at);
+                true
+            }
+        }
+    }
+
+    /// Drop digests whose timestamp left the window, returns how many
+    pub fn reap(&self) -> usize {
+        let now = unix_now();
+        let before = self.0.len();
+
+        self.0.retain(|_, expires_at| *expires_at >= now);
+
+        before.saturating_sub(self.0.len())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock before unix epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::State, routing::post, Router};
+    use k256::ecdsa::{signature::Signer, SigningKey};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{utils::crypto, Config};
+
+    #[derive(Serialize, Deserialize)]
+    struct Body {
+        public_key: String,
+    }
+
+    async fn handler(
+        State(state): State<HypervisorState>,
+        req: SignedJson<Body>,
+    ) -> Result<(), HypervisorError> {
+        let user_pk = crypto::pk_from_hex(&req.body.public_key).unwrap();
+        authenticate(&state, &user_pk, req.signature.as_ref())
+    }
+
+    fn test_server(config: Config) -> axum_test::TestServer {
+        let router = Router::new()
+            .route("/signed", post(handler))
+            .with_state(HypervisorState::new(config));
+
+        axum_test::TestServer::new(router).unwrap()
+    }
+
+    fn signed_headers(sk: &SigningKey, timestamp: u64, body: &[u8]) -> [(&'static str, String); 2] {
+        let signature: Signature = sk.sign(&request_digest(timestamp, body));
+
+        [
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (SIGNATURE_HEADER, const_hex::encode(signature.to_bytes())),
+        ]
+    }
+
+    async fn post_signed(
+        server: &axum_test::TestServer,
+        headers: &[(&'static str, String)],
+        body: &[u8],
+    ) -> axum_test::TestResponse {
+        let mut request = server
+            .post("/signed")
+            .content_type("application/json")
+            .bytes(Bytes::copy_from_slice(body));
+        for (name, value) in headers {
+            request = request.add_header(*name, value);
+        }
+
+        request.await
+    }
+
+    #[tokio::test]
+    async fn test_signed_requests() {
+        let server = test_server(Config::default());
+
+        let sk = SigningKey::random(&mut rand::rngs::OsRng);
+        let body = serde_json::to_vec(&Body {
+            public_key: crypto::pk_to_hex(sk.verifying_key()),
+        })
+        .unwrap();
+
+        // Unsigned requests stay allowed by default
+        post_signed(&server, &[], &body).await.assert_status_ok();
+
+        let headers = signed_headers(&sk, unix_now(), &body);
+        post_signed(&server, &headers, &body)
+            .await
+            .assert_status_ok();
+        post_signed(&server, &headers, &body)
+            .await
+            .assert_status(StatusCode::CONFLICT);
+
+        let stale = signed_headers(&sk, unix_now() - 3600, &body);
+        post_signed(&server, &stale, &body)
+            .await
+            .assert_status_unauthorized();
+
+        let other = SigningKey::random(&mut rand::rngs::OsRng);
+        let forged = signed_headers(&other, unix_now(), &body);
+        post_signed(&server, &forged, &body)
+            .await
+            .assert_status_unauthorized();
+
+        post_signed(&server, &headers[..1], &body)
+            .await
+            .assert_status_bad_request();
+    }
+
+    #[tokio::test]
+    async fn test_signed_requests_required() {
+        let server = test_server(Config {
+            require_signed_requests: true,
+            ..Default::default()
+        });
+
+        let sk = SigningKey::random(&mut rand::rngs::OsRng);
+        let body = serde_json::to_vec(&Body {
+            public_key: crypto::pk_to_hex(sk.verifying_key()),
+        })
+        .unwrap();
+
+        post_signed(&server, &[], &body)
+            .await
+            .assert_status_unauthorized();
+
+        let headers = signed_headers(&sk, unix_now(), &body);
+        post_signed(&server, &headers, &body)
+            .await
+            .assert_status_ok();
+    }
+}
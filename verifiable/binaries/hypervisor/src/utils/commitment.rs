@@ -1,35 +1,178 @@
-use aes_gcm_siv::Nonce;
+//! Result commitments, one scheme for every executor
+//!
+//! ```text
+//! domain, envelope version, executor, runtime version,
+//! user pk, session pk, session id,
+//! input count, inputs..., exit code, output count, outputs...
+//! ```
+//!
+//! Every entry is length prefixed. What goes into the inputs and outputs is
+//! up to the executor, through [`CommittedRequest`] and
+//! [`CommittedResponse`] on its request and response types. The hypervisor
+//! commits and a verifier recomputes with the same
+//! [`build_result_commitment`], knowing only those traits.
+
+use std::borrow::Cow;
+
 use k256::ecdsa::VerifyingKey;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::utils;
+use crate::utils::{crypto::EnvelopeVersion, hasher};
+
+/// Hashed first, so a commitment can't pass for any other hash
+pub const RESULT_COMMITMENT_DOMAIN: &[u8] = b"x402:result";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutorKind {
+    Wasm,
+    Python,
+    Riscv,
+}
+
+impl From<ExecutorKind> for u8 {
+    fn from(kind: ExecutorKind) -> Self {
+        match kind {
+            ExecutorKind::Wasm => 1,
+            ExecutorKind::Python => 2,
+            ExecutorKind::Riscv => 3,
+        }
+    }
+}
+
+/// Request of an executor, as committed
+pub trait CommittedRequest {
+    fn version(&self) -> EnvelopeVersion;
+
+    /// Hex of the user public key
+    fn public_key(&self) -> &str;
+
+    /// Executor specific inputs, sealed fields as sent
+    fn committed_inputs(&self) -> Vec<Cow<'_, [u8]>>;
+}
+
+/// Response of an executor, as committed
+pub trait CommittedResponse {
+    fn executor(&self) -> ExecutorKind;
+
+    /// Runtime that ran the execution, reported by the executor
+    fn runtime_version(&self) -> &str;
+
+    fn session_id(&self) -> Uuid;
+
+    fn exit_code(&self) -> i32;
 
+    /// Executor specific outputs, sealed fields as returned
+    fn committed_outputs(&self) -> Vec<Cow<'_, [u8]>>;
+
+    /// Hex of the commitment the response carries, not committed itself
+    fn result_commitment(&self) -> &str;
+}
+
+/// Commit to `resp` answering `req`
 pub fn build_result_commitment(
     user_pk: &VerifyingKey,
     session_pk: &VerifyingKey,
-    session_id: Uuid,
-    encrypted_wasm: &str,
-    encrypted_arguments: &[String],
-    output_nonce: Nonce,
-    encrypted_result: &String,
+    req: &impl CommittedRequest,
+    resp: &impl CommittedResponse,
 ) -> [u8; 32] {
-    let mut entries = vec![
-        user_pk.to_encoded_point(true).to_bytes(),
-        session_pk.to_encoded_point(true).to_bytes(),
-        Box::new(*session_id.as_bytes()),
-        encrypted_wasm.as_bytes().into(),
-    ];
-
-    entries.extend(
-        { encrypted_arguments.iter().cloned() }
-            .map(|a| a.as_bytes().into())
-            .collect::<Vec<_>>(),
-    );
+    let inputs = req.committed_inputs();
+    let outputs = resp.committed_outputs();
 
+    let mut entries: Vec<Cow<'_, [u8]>> = vec![
+        RESULT_COMMITMENT_DOMAIN.into(),
+        vec![u8::from(req.version())].into(),
+        vec![u8::from(resp.executor())].into(),
+        resp.runtime_version().as_bytes().into(),
+        user_pk.to_encoded_point(true).as_bytes().to_vec().into(),
+        session_pk.to_encoded_point(true).as_bytes().to_vec().into(),
+        resp.session_id().as_bytes().to_vec().into(),
+        count(inputs.len()),
+    ];
+    entries.extend(inputs);
     entries.extend([
-        output_nonce.to_vec().into(),
-        encrypted_result.as_bytes().into(),
+        resp.exit_code().to_be_bytes().to_vec().into(),
+        count(outputs.len()),
     ]);
+    entries.extend(outputs);
+
+    hasher::hash_prefixed(&entries)
+}
+
+/// Committed length of a list of entries
+pub fn count(len: usize) -> Cow<'static, [u8]> {
+    (len as u64).to_be_bytes().to_vec().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Request(Vec<&'static str>);
+
+    impl CommittedRequest for Request {
+        fn version(&self) -> EnvelopeVersion {
+            EnvelopeVersion::Framed
+        }
+
+        fn public_key(&self) -> &str {
+            ""
+        }
+
+        fn committed_inputs(&self) -> Vec<Cow<'_, [u8]>> {
+            { self.0.iter() }.map(|i| i.as_bytes().into()).collect()
+        }
+    }
+
+    struct Response(Vec<&'static str>, i32);
+
+    impl CommittedResponse for Response {
+        fn executor(&self) -> ExecutorKind {
+            ExecutorKind::Python
+        }
+
+        fn runtime_version(&self) -> &str {
+            "CPython 3.11.7"
+        }
+
+        fn session_id(&self) -> Uuid {
+            Uuid::nil()
+        }
+
+        fn exit_code(&self) -> i32 {
+            self.1
+        }
+
+        fn committed_outputs(&self) -> Vec<Cow<'_, [u8]>> {
+            { self.0.iter() }.map(|o| o.as_bytes().into()).collect()
+        }
+
+        fn result_commitment(&self) -> &str {
+            ""
+        }
+    }
+
+    #[test]
+    fn test_result_commitment() {
+        let pk = *k256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng).verifying_key();
+        let commit = |req: Request, resp: Response| build_result_commitment(&pk, &pk, &req, &resp);
+
+        let committed = commit(Request(vec!["ab", "c"]), Response(vec!["d"], 0));
+        assert_eq!(
+            committed,
+            commit(Request(vec!["ab", "c"]), Response(vec!["d"], 0))
+        );
 
-    utils::hasher::hash_multi(&entries)
+        // Entries don't shift between each other or the lists
+        let shifted = [
+            (Request(vec!["a", "bc"]), Response(vec!["d"], 0)),
+            (Request(vec!["abc"]), Response(vec!["d"], 0)),
+            (Request(vec!["ab"]), Response(vec!["c", "d"], 0)),
+            (Request(vec!["ab", "c"]), Response(vec!["d"], 1)),
+        ];
+        for (req, resp) in shifted {
+            assert_ne!(committed, commit(req, resp));
+        }
+    }
 }
@@ -1,12 +1,96 @@
-use aes_gcm_siv::{Aes256GcmSiv, KeyInit, Nonce};
-use anyhow::anyhow;
+use aes_gcm_siv::{aead::Aead, Aes256GcmSiv, KeyInit, Nonce};
+use anyhow::{anyhow, Context};
 use k256::{
     ecdh::diffie_hellman,
     ecdsa::{SigningKey, VerifyingKey},
 };
 use secrecy::{ExposeSecret, ExposeSecretMut, SecretSlice};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+pub const NONCE_LEN: usize = 12;
+
+/// Ciphertext framing of execution requests
+///
+/// `Legacy` encrypts every field under `derive_msg_nonce(session_id)`, so
+/// all ciphertexts of a session share one nonce. `Framed` prefixes each
+/// ciphertext with its own random nonce, `nonce || ciphertext`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum EnvelopeVersion {
+    #[default]
+    Legacy,
+    Framed,
+}
+
+impl TryFrom<u8> for EnvelopeVersion {
+    type Error = String;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            0 => Ok(EnvelopeVersion::Legacy),
+            1 => Ok(EnvelopeVersion::Framed),
+            v => Err(format!("unknown envelope version {v}")),
+        }
+    }
+}
+
+impl From<EnvelopeVersion> for u8 {
+    fn from(version: EnvelopeVersion) -> Self {
+        version as u8
+    }
+}
+
+impl EnvelopeVersion {
+    pub fn seal(
+        self,
+        cipher: &Aes256GcmSiv,
+        session_id: Uuid,
+        plaintext: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let nonce = match self {
+            EnvelopeVersion::Legacy => derive_msg_nonce(session_id),
+            EnvelopeVersion::Framed => random_nonce(),
+        };
+
+        let ciphertext =
+            { cipher.encrypt(&nonce, plaintext) }.map_err(|e| anyhow!(e.to_string()))?;
+
+        match self {
+            EnvelopeVersion::Legacy => Ok(ciphertext),
+            EnvelopeVersion::Framed => Ok([nonce.as_slice(), &ciphertext].concat()),
+        }
+    }
+
+    pub fn open(
+        self,
+        cipher: &Aes256GcmSiv,
+        session_id: Uuid,
+        envelope: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let (nonce, ciphertext) = match self {
+            EnvelopeVersion::Legacy => (derive_msg_nonce(session_id), envelope),
+            EnvelopeVersion::Framed => {
+                anyhow::ensure!(envelope.len() >= NONCE_LEN, "envelope shorter than nonce");
+                let (nonce, ciphertext) = envelope.split_at(NONCE_LEN);
+                (*Nonce::from_slice(nonce), ciphertext)
+            }
+        };
+
+        { cipher.decrypt(&nonce, ciphertext) }
+            .map_err(|e| anyhow!(e.to_string()))
+            .context("decrypt envelope")
+    }
+
+    /// Nonce encrypting the execution result, returned next to it
+    pub fn result_nonce(self, output: &[u8]) -> Nonce {
+        match self {
+            EnvelopeVersion::Legacy => derive_msg_nonce(output),
+            EnvelopeVersion::Framed => random_nonce(),
+        }
+    }
+}
+
 pub fn create_encrypt_key(
     sk: &SigningKey,
     pk: &VerifyingKey,
@@ -30,6 +114,10 @@ pub fn derive_msg_nonce(data: impl AsRef<[u8]>) -> Nonce {
     Nonce::from_iter(hash[..12].iter().map(|u| *u))
 }
 
+fn random_nonce() -> Nonce {
+    *Nonce::from_slice(&rand::random::<[u8; NONCE_LEN]>())
+}
+
 pub fn pk_to_hex(pk: &VerifyingKey) -> String {
     pk.to_encoded_point(true).to_string()
 }
@@ -42,3 +130,43 @@ pub fn pk_from_hex(pk_hex: &str) -> anyhow::Result<VerifyingKey> {
 
     Ok(pk)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_version() {
+        let sk = SigningKey::random(&mut rand::rngs::OsRng);
+        let session_id = Uuid::now_v7();
+        let cipher = create_encrypt_key(&sk, sk.verifying_key(), session_id).unwrap();
+
+        for version in [EnvelopeVersion::Legacy, EnvelopeVersion::Framed] {
+            let envelope = version.seal(&cipher, session_id, b"hello").unwrap();
+            assert_eq!(
+                version.open(&cipher, session_id, &envelope).unwrap(),
+                b"hello"
+            );
+        }
+
+        // Framed envelopes never repeat a nonce for the same plaintext
+        let a = EnvelopeVersion::Framed
+            .seal(&cipher, session_id, b"hello")
+            .unwrap();
+        let b = EnvelopeVersion::Framed
+            .seal(&cipher, session_id, b"hello")
+            .unwrap();
+        assert_ne!(a[..NONCE_LEN], b[..NONCE_LEN]);
+
+        assert!(EnvelopeVersion::Framed
+            .open(&cipher, session_id, &a[..NONCE_LEN - 1])
+            .is_err());
+        assert!(EnvelopeVersion::Legacy
+            .open(&cipher, session_id, &a)
+            .is_err());
+
+        let version: EnvelopeVersion = serde_json::from_str("1").unwrap();
+        assert_eq!(version, EnvelopeVersion::Framed);
+        assert!(serde_json::from_str::<EnvelopeVersion>("2").is_err());
+    }
+}
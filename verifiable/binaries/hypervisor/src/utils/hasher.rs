@@ -7,3 +7,17 @@ pub fn hash_multi(data: &[impl AsRef<[u8]>]) -> [u8; 32] {
 
     hasher.finalize().into()
 }
+
+/// Like [`hash_multi`], with each item prefixed by its length so items
+/// can't run into each other
+pub fn hash_prefixed(data: &[impl AsRef<[u8]>]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+
+    for d in data {
+        let d = d.as_ref();
+        hasher.update(&(d.len() as u64).to_be_bytes());
+        hasher.update(d);
+    }
+
+    hasher.finalize().into()
+}
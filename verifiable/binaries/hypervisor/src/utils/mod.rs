@@ -1,4 +1,5 @@
 pub mod attest;
+pub mod auth;
 pub mod commitment;
 pub mod crypto;
 pub mod hasher;
@@ -0,0 +1,307 @@
+"""Regenerate the TDX verification fixtures of this directory
+
+A V4 TDX quote, its PCK chain and the TCB info, QE identity and TCB signing
+chain it verifies against, laid out like Intel PCS serves them. Everything
+chains to a test root instead of the Intel SGX Root CA, `root_pubkey.bin`
+holds its `x || y` for `Verifier::with_root_pubkey`.
+
+Keys are derived from fixed labels, rerunning only changes the ECDSA
+signatures. Needs the `cryptography` package.
+"""
+
+import datetime
+import hashlib
+import json
+import struct
+from pathlib import Path
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec
+from cryptography.hazmat.primitives.asymmetric.utils import decode_dss_signature
+from cryptography.x509.oid import NameOID
+
+OUT = Path(__file__).parent
+
+NOT_BEFORE = datetime.datetime(2025, 1, 1, tzinfo=datetime.timezone.utc)
+NOT_AFTER = datetime.datetime(2035, 1, 1, tzinfo=datetime.timezone.utc)
+ISSUE_DATE = "2026-06-01T00:00:00Z"
+NEXT_UPDATE = "2026-07-01T00:00:00Z"
+
+FMSPC = bytes.fromhex("00806f050000")
+OTHER_FMSPC = bytes.fromhex("00906ed50000")
+PCESVN = 13
+CPUSVN = bytes([2, 2, 2, 2, 3, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0])
+TEE_TCB_SVN = bytes([3, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+
+QE_MRSIGNER = bytes.fromhex("dc9e2a7c6f948f17474e34a7fc43ed030f7c1563f1babddf6340c82e0e54a8c5")
+QE_ATTRIBUTES = bytes.fromhex("11000000000000000000000000000000")
+QE_ISVPRODID = 2
+QE_ISVSVN = 4
+MR_SIGNER_SEAM = bytes(48)
+SEAM_ATTRIBUTES = bytes(8)
+
+INTEL_QE_VENDOR_ID = bytes.fromhex("939a7233f79c4ca9940a0db3957f0607")
+
+
+def key(label):
+    scalar = int.from_bytes(hashlib.sha256(label.encode()).digest(), "big")
+    return ec.derive_private_key(scalar, ec.SECP256R1())
+
+
+def raw_pubkey(key):
+    return key.public_key().public_bytes(
+        serialization.Encoding.X962, serialization.PublicFormat.UncompressedPoint
+    )[1:]
+
+
+def raw_sign(key, msg):
+    r, s = decode_dss_signature(key.sign(msg, ec.ECDSA(hashes.SHA256())))
+    return r.to_bytes(32, "big") + s.to_bytes(32, "big")
+
+
+def der(tag, content):
+    if len(content) < 0x80:
+        length = bytes([len(content)])
+    else:
+        n = (len(content).bit_length() + 7) // 8
+        length = bytes([0x80 | n]) + len(content).to_bytes(n, "big")
+    return bytes([tag]) + length + content
+
+
+def der_oid(oid):
+    arcs = [int(a) for a in oid.split(".")]
+    body = bytes([40 * arcs[0] + arcs[1]])
+    for arc in arcs[2:]:
+        chunk = [arc & 0x7F]
+        arc >>= 7
+        while arc:
+            chunk.append(0x80 | (arc & 0x7F))
+            arc >>= 7
+        body += bytes(reversed(chunk))
+    return der(0x06, body)
+
+
+def der_int(value):
+    return der(0x02, value.to_bytes(value.bit_length() // 8 + 1, "big"))
+
+
+def der_pair(oid, value):
+    return der(0x30, der_oid(oid) + value)
+
+
+def sgx_extension():
+    sgx = "1.2.840.113741.1.13.1"
+    tcb = [der_pair(f"{sgx}.2.{i + 1}", der_int(svn)) for i, svn in enumerate(CPUSVN)]
+    tcb.append(der_pair(f"{sgx}.2.17", der_int(PCESVN)))
+    tcb.append(der_pair(f"{sgx}.2.18", der(0x04, CPUSVN)))
+
+    return der(
+        0x30,
+        der_pair(f"{sgx}.1", der(0x04, bytes(16)))
+        + der_pair(f"{sgx}.2", der(0x30, b"".join(tcb)))
+        + der_pair(f"{sgx}.3", der(0x04, bytes(2)))
+        + der_pair(f"{sgx}.4", der(0x04, FMSPC))
+        + der_pair(f"{sgx}.5", der(0x0A, b"\x00")),
+    )
+
+
+def name(cn):
+    return x509.Name(
+        [
+            x509.NameAttribute(NameOID.COMMON_NAME, cn),
+            x509.NameAttribute(NameOID.ORGANIZATION_NAME, "attest test fixtures"),
+        ]
+    )
+
+
+def cert(subject, key, issuer, issuer_key, ca, extensions=()):
+    builder = (
+        x509.CertificateBuilder()
+        .subject_name(name(subject))
+        .issuer_name(name(issuer))
+        .public_key(key.public_key())
+        .serial_number(int.from_bytes(hashlib.sha256(subject.encode()).digest()[:8], "big"))
+        .not_valid_before(NOT_BEFORE)
+        .not_valid_after(NOT_AFTER)
+        .add_extension(x509.BasicConstraints(ca=ca, path_length=None), critical=True)
+    )
+    for ext in extensions:
+        builder = builder.add_extension(ext, critical=False)
+
+    return builder.sign(issuer_key, hashes.SHA256()).public_bytes(serialization.Encoding.PEM)
+
+
+def signed_json(field, body, key):
+    raw = json.dumps(body, separators=(",", ":"))
+    signature = raw_sign(key, raw.encode()).hex()
+    return f'{{"{field}":{raw},"signature":"{signature}"}}'
+
+
+def svns(values):
+    return [{"svn": svn} for svn in values]
+
+
+def tcb_info(fmspc):
+    return {
+        "id": "TDX",
+        "version": 3,
+        "issueDate": ISSUE_DATE,
+        "nextUpdate": NEXT_UPDATE,
+        "fmspc": fmspc.hex(),
+        "pceId": "0000",
+        "tcbType": 0,
+        "tcbEvaluationDataNumber": 17,
+        "tdxModule": {
+            "mrsigner": MR_SIGNER_SEAM.hex(),
+            "attributes": SEAM_ATTRIBUTES.hex(),
+            "attributesMask": "ffffffffffffffff",
+        },
+        "tdxModuleIdentities": [
+            {
+                "id": "TDX_01",
+                "mrsigner": MR_SIGNER_SEAM.hex(),
+                "attributes": SEAM_ATTRIBUTES.hex(),
+                "attributesMask": "ffffffffffffffff",
+                "tcbLevels": [
+                    {
+                        "tcb": {"isvsvn": 3},
+                        "tcbDate": "2026-03-01T00:00:00Z",
+                        "tcbStatus": "UpToDate",
+                    },
+                    {
+                        "tcb": {"isvsvn": 2},
+                        "tcbDate": "2025-06-01T00:00:00Z",
+                        "tcbStatus": "OutOfDate",
+                    },
+                ],
+            }
+        ],
+        "tcbLevels": [
+            {
+                "tcb": {
+                    "sgxtcbcomponents": svns(CPUSVN),
+                    "pcesvn": PCESVN,
+                    "tdxtcbcomponents": svns(TEE_TCB_SVN),
+                },
+                "tcbDate": "2026-03-01T00:00:00Z",
+                "tcbStatus": "UpToDate",
+            },
+            {
+                "tcb": {
+                    "sgxtcbcomponents": svns([2] * 4 + [0] * 12),
+                    "pcesvn": 11,
+                    "tdxtcbcomponents": svns([0] * 16),
+                },
+                "tcbDate": "2025-06-01T00:00:00Z",
+                "tcbStatus": "OutOfDate",
+                "advisoryIDs": ["INTEL-SA-00837"],
+            },
+        ],
+    }
+
+
+def qe_identity():
+    return {
+        "id": "TD_QE",
+        "version": 2,
+        "issueDate": ISSUE_DATE,
+        "nextUpdate": NEXT_UPDATE,
+        "tcbEvaluationDataNumber": 17,
+        "miscselect": "00000000",
+        "miscselectMask": "ffffffff",
+        "attributes": QE_ATTRIBUTES.hex(),
+        "attributesMask": "fbffffffffffffff0000000000000000",
+        "mrsigner": QE_MRSIGNER.hex(),
+        "isvprodid": QE_ISVPRODID,
+        "tcbLevels": [
+            {
+                "tcb": {"isvsvn": QE_ISVSVN},
+                "tcbDate": "2026-03-01T00:00:00Z",
+                "tcbStatus": "UpToDate",
+            }
+        ],
+    }
+
+
+def td_report():
+    body = bytearray(584)
+    body[0:16] = TEE_TCB_SVN
+    body[16:64] = bytes([0x5E]) * 48
+    body[64:112] = MR_SIGNER_SEAM
+    body[112:120] = SEAM_ATTRIBUTES
+    body[128:136] = bytes.fromhex("e702060000000000")
+    body[136:184] = bytes([0xA1]) * 48
+    for i in range(4):
+        body[328 + 48 * i : 376 + 48 * i] = bytes([0xB0 + i]) * 48
+    body[520:584] = bytes([0x42]) * 64
+    return bytes(body)
+
+
+def qe_report(report_data):
+    report = bytearray(384)
+    report[48:64] = QE_ATTRIBUTES
+    report[128:160] = QE_MRSIGNER
+    report[256:258] = struct.pack("<H", QE_ISVPRODID)
+    report[258:260] = struct.pack("<H", QE_ISVSVN)
+    report[320:384] = report_data
+    return bytes(report)
+
+
+def quote(attestation_key, pck_key, pck_chain):
+    header = struct.pack("<HHI", 4, 2, 0x81) + bytes(4) + INTEL_QE_VENDOR_ID + bytes(20)
+    signed = header + td_report()
+
+    ak = raw_pubkey(attestation_key)
+    auth_data = bytes(range(32))
+    report = qe_report(hashlib.sha256(ak + auth_data).digest() + bytes(32))
+
+    qe_cert_data = (
+        report
+        + raw_sign(pck_key, report)
+        + struct.pack("<H", len(auth_data))
+        + auth_data
+        + struct.pack("<HI", 5, len(pck_chain))
+        + pck_chain
+    )
+    signature = (
+        raw_sign(attestation_key, signed)
+        + ak
+        + struct.pack("<HI", 6, len(qe_cert_data))
+        + qe_cert_data
+    )
+
+    return signed + struct.pack("<I", len(signature)) + signature
+
+
+def main():
+    root = key("root ca")
+    platform = key("platform ca")
+    pck = key("pck")
+    signing = key("tcb signing")
+    attestation = key("attestation")
+
+    root_pem = cert("Test SGX Root CA", root, "Test SGX Root CA", root, True)
+    platform_pem = cert("Test SGX PCK Platform CA", platform, "Test SGX Root CA", root, True)
+    pck_pem = cert(
+        "Test SGX PCK Certificate",
+        pck,
+        "Test SGX PCK Platform CA",
+        platform,
+        False,
+        [x509.UnrecognizedExtension(x509.ObjectIdentifier("1.2.840.113741.1.13.1"), sgx_extension())],
+    )
+    signing_pem = cert("Test SGX TCB Signing", signing, "Test SGX Root CA", root, False)
+
+    (OUT / "root_pubkey.bin").write_bytes(raw_pubkey(root))
+    (OUT / "quote_v4.bin").write_bytes(quote(attestation, pck, pck_pem + platform_pem + root_pem))
+    (OUT / "tcb_signing_chain.pem").write_bytes(signing_pem + root_pem)
+    (OUT / "tcb_info.json").write_text(signed_json("tcbInfo", tcb_info(FMSPC), signing))
+    (OUT / "tcb_info_other_fmspc.json").write_text(
+        signed_json("tcbInfo", tcb_info(OTHER_FMSPC), signing)
+    )
+    (OUT / "qe_identity.json").write_text(signed_json("enclaveIdentity", qe_identity(), signing))
+
+
+if __name__ == "__main__":
+    main()
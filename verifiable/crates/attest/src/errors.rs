@@ -8,6 +8,9 @@ pub enum QuoteError {
 
     #[error("report data {0}")]
     ReportData(String),
+
+    #[error("snp report {0}")]
+    SnpReport(String),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -18,9 +21,51 @@ pub enum AttestationError {
     #[error("coco {0}")]
     Coco(#[from] tdx_attestation_sdk::error::TdxError),
 
+    #[error("mock {0}")]
+    Mock(String),
+
+    #[error("rtmr {0}")]
+    Rtmr(String),
+
+    #[error("tsm {0}")]
+    Tsm(String),
+
+    #[error("sealing {0}")]
+    Sealing(String),
+
     #[error("quote {0}")]
     Quote(#[from] QuoteError),
 
     #[error("no provider available, should run inside guest vm")]
     NoProviderAvailable,
 }
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("malformed quote {0}")]
+    MalformedQuote(String),
+
+    #[error("unsupported {0}")]
+    Unsupported(String),
+
+    #[error("signature {0}")]
+    Signature(String),
+
+    #[error("certificate {0}")]
+    Certificate(String),
+
+    #[error("collateral {0}")]
+    Collateral(String),
+
+    #[error("policy {0}")]
+    Policy(String),
+
+    #[error("measurement {}", .0.join("; "))]
+    Measurement(Vec<String>),
+
+    #[error("event log {0}")]
+    EventLog(String),
+
+    #[error("io {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,255 @@
+//! Workload measurements extended into RTMR3
+//!
+//! Firmware and kernel own RTMR0 to RTMR2, RTMR3 is left to the guest. Each
+//! artifact the hypervisor executes is hashed with SHA-384 and extended into
+//! RTMR3, and a matching event is appended to an in-memory log, in the
+//! spirit of the CC event log (CCEL). Replaying the log from an all zero
+//! register yields the RTMR3 value reported by quotes.
+//!
+//! The digest covers the kind of the artifact too, so a replayed log vouches
+//! for it: `SHA-384(tag || 0x00 || artifact)`, with `tag` the snake_case
+//! name of the [`EventKind`] in ASCII, e.g. `wasm_component`. See
+//! [`digest`].
+//!
+//! The log lives in process memory. A hypervisor restarted inside the same
+//! TD can't be replayed anymore, verifiers should reject it.
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha384};
+
+use crate::{
+    errors::{AttestationError, VerifyError},
+    provider,
+    types::Quote,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    WasmComponent,
+    PolicySet,
+    AgentBinary,
+    RiscvProgram,
+}
+
+impl EventKind {
+    /// Prefix of the measured artifact, the serialized name
+    pub fn tag(&self) -> &'static str {
+        match self {
+            EventKind::WasmComponent => "wasm_component",
+            EventKind::PolicySet => "policy_set",
+            EventKind::AgentBinary => "agent_binary",
+            EventKind::RiscvProgram => "riscv_program",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    /// Position in the log, starting at zero
+    pub seq: u64,
+    pub kind: EventKind,
+    /// [`digest`] of the kind and artifact, the value extended into RTMR3
+    #[serde(with = "const_hex")]
+    pub digest: [u8; 48],
+    /// Informational only, not covered by the measurement
+    pub description: String,
+}
+
+static EVENT_LOG: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+
+/// `SHA-384(tag || 0x00 || artifact)`, the measurement of `artifact` run as
+/// `kind`
+pub fn digest(kind: EventKind, artifact: &[u8]) -> [u8; 48] {
+    Sha384::new()
+        .chain_update(kind.tag())
+        .chain_update([0u8])
+        .chain_update(artifact)
+        .finalize()
+        .into()
+}
+
+/// Extend RTMR3 with the [`digest`] of `artifact` and record it
+///
+/// Artifacts already in the log as the same kind aren't extended again, the
+/// existing event is returned instead.
+pub fn measure(
+    kind: EventKind,
+    description: impl Into<String>,
+    artifact: &[u8],
+) -> Result<Event, AttestationError> {
+    let digest = digest(kind, artifact);
+
+    // Hold the lock across the extension so the log order matches the
+    // register
+    let mut log = EVENT_LOG.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(event) = { log.iter() }.find(|e| e.digest == digest) {
+        return Ok(event.clone());
+    }
+
+    extend_rtmr3(&digest)?;
+
+    let event = Event {
+        seq: log.len() as u64,
+        kind,
+        digest,
+        description: description.into(),
+    };
+    log.push(event.clone());
+
+    Ok(event)
+}
+
+/// Snapshot of the events recorded so far
+pub fn events() -> Vec<Event> {
+    EVENT_LOG.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// RTMR3 value after extending an all zero register with `events` in order
+pub fn replay(events: &[Event]) -> [u8; 48] {
+    { events.iter() }.fold([0u8; 48], |rtmr, event| extend(&rtmr, &event.digest))
+}
+
+/// Find the prefix of `events` whose replay matches the RTMR3 of `quote`
+///
+/// The log may have grown since the quote was taken, so the number of
+/// events covered by the quote is returned. Anything after that prefix
+/// wasn't loaded when the quote was produced.
+pub fn replay_quote(quote: &Quote, events: &[Event]) -> Result<usize, VerifyError> {
+    let Some(report) = quote.quote_report().filter(|r| r.is_td()) else {
+        return Err(VerifyError::Unsupported(
+            "event log on non td quote".to_string(),
+        ));
+    };
+
+    if let Some((idx, event)) = { events.iter().enumerate() }.find(|(i, e)| e.seq != *i as u64) {
+        return Err(VerifyError::EventLog(format!(
+            "event {idx} has seq {}",
+            event.seq
+        )));
+    }
+
+    let expected = report.rtmr3();
+    let mut rtmr = [0u8; 48];
+    if rtmr == expected {
+        return Ok(0);
+    }
+
+    for (idx, event) in events.iter().enumerate() {
+        rtmr = extend(&rtmr, &event.digest);
+        if rtmr == expected {
+            return Ok(idx + 1);
+        }
+    }
+
+    Err(VerifyError::EventLog(format!(
+        "no prefix of {} events replays to rtmr3 {}",
+        events.len(),
+        const_hex::encode(expected)
+    )))
+}
+
+fn extend(rtmr: &[u8; 48], digest: &[u8; 48]) -> [u8; 48] {
+    Sha384::new_with_prefix(rtmr)
+        .chain_update(digest)
+        .finalize()
+        .into()
+}
+
+fn extend_rtmr3(digest: &[u8; 48]) -> Result<(), AttestationError> {
+    if provider::coco::rtmr_available(3) {
+        return provider::coco::extend_rtmr(3, digest);
+    }
+
+    #[cfg(feature = "mock")]
+    {
+        provider::mock::extend_rtmr3(digest);
+        Ok(())
+    }
+    #[cfg(not(feature = "mock"))]
+    {
+        Err(AttestationError::Rtmr(
+            "no rtmr interface available".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(seq: u64, artifact: &[u8]) -> Event {
+        Event {
+            seq,
+            kind: EventKind::WasmComponent,
+            digest: digest(EventKind::WasmComponent, artifact),
+            description: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_digest() {
+        let mut expected = b"policy_set\0".to_vec();
+        expected.extend_from_slice(b"artifact");
+        assert_eq!(
+            digest(EventKind::PolicySet, b"artifact"),
+            <[u8; 48]>::from(Sha384::digest(&expected))
+        );
+
+        // The same bytes run as another kind are another measurement
+        assert_ne!(
+            digest(EventKind::WasmComponent, b"artifact"),
+            digest(EventKind::PolicySet, b"artifact")
+        );
+        for kind in [
+            EventKind::WasmComponent,
+            EventKind::PolicySet,
+            EventKind::AgentBinary,
+            EventKind::RiscvProgram,
+        ] {
+            let name = serde_json::to_value(kind).unwrap();
+            assert_eq!(name.as_str(), Some(kind.tag()));
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        assert_eq!(replay(&[]), [0u8; 48]);
+
+        let events = vec![event(0, b"a"), event(1, b"b")];
+        let first = extend(&[0u8; 48], &events[0].digest);
+        assert_eq!(replay(&events[..1]), first);
+        assert_eq!(replay(&events), extend(&first, &events[1].digest));
+        assert_ne!(
+            replay(&[events[1].clone(), events[0].clone()]),
+            replay(&events)
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_measure_replay_quote() {
+        use crate::{get_quote_with_provider, types::RawReport, Provider};
+
+        let artifact = b"test_measure_replay_quote";
+        let measured = measure(EventKind::WasmComponent, "test", artifact).unwrap();
+        let again = measure(EventKind::WasmComponent, "test", artifact).unwrap();
+        assert_eq!(measured, again);
+        let policy = measure(EventKind::PolicySet, "test", artifact).unwrap();
+        assert_ne!(policy.seq, measured.seq);
+
+        let quote = get_quote_with_provider(RawReport::new([0u8; 64]), Provider::Mock).unwrap();
+
+        // Other tests may extend concurrently, only the covered prefix has to
+        // include this artifact
+        let events = events();
+        let covered = replay_quote(&quote, &events).unwrap();
+        assert!(events[..covered].contains(&measured));
+
+        let mut tampered = events.clone();
+        tampered[measured.seq as usize].digest[0] ^= 1;
+        assert!(replay_quote(&quote, &tampered).is_err());
+    }
+}
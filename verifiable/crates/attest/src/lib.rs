@@ -1,19 +1,29 @@
 pub mod errors;
+pub mod event_log;
 pub mod provider;
+pub mod report_data;
+pub mod seal;
+pub mod snp;
 pub mod types;
+pub mod verify;
 
 use std::path::Path;
 
 use errors::AttestationError;
-use types::{K256PkReport, Quote, RawReport};
+use types::{K256PkReport, Quote, RawReport, SnpReport};
 
 #[derive(Debug)]
 pub enum Provider {
     Ioctl,
     Coco,
+    /// AMD SEV-SNP through configfs-tsm
+    SevSnp,
+    /// Software quotes for development and CI, see [`verify::MockVerifier`]
+    Mock,
 }
 
 const IOCTL_DEVICE_PATH: &str = "/dev/tdx_guest";
+const SEV_GUEST_DEVICE_PATH: &str = "/dev/sev-guest";
 
 pub fn get_quote(report: RawReport) -> Result<Quote, AttestationError> {
     let provider = match tdx_attestation_sdk::device::Device::default() {
@@ -21,6 +31,11 @@ pub fn get_quote(report: RawReport) -> Result<Quote, AttestationError> {
         // Fallback to legacy /dev/tdx_guest, which is available on
         // patched kernel 5.x. For example, alinux3 from aliyun
         Err(_) if Path::new(IOCTL_DEVICE_PATH).exists() => Provider::Ioctl,
+        Err(_) if Path::new(SEV_GUEST_DEVICE_PATH).exists() => Provider::SevSnp,
+        Err(_) if cfg!(feature = "mock") => {
+            tracing::warn!("no tee device found, falling back to mock quote");
+            Provider::Mock
+        }
         Err(_) => return Err(AttestationError::NoProviderAvailable),
     };
 
@@ -33,7 +48,7 @@ pub fn get_quote_for_k256_pk(report: K256PkReport) -> Result<Quote, AttestationE
     get_quote(report.to_raw())
 }
 
-fn get_quote_with_provider(
+pub fn get_quote_with_provider(
     report: RawReport,
     provider: Provider,
 ) -> Result<Quote, AttestationError> {
@@ -49,6 +64,21 @@ fn get_quote_with_provider(
             }
         }
         Provider::Coco => provider::coco::get_raw_quote(report)?,
+        Provider::SevSnp => {
+            let raw_report =
+                provider::tsm::get_raw_report(report, provider::tsm::PROVIDER_SEV_GUEST)?;
+            return Ok(Quote::SevSnp(SnpReport::from_bytes(&raw_report)?));
+        }
+        Provider::Mock => {
+            #[cfg(feature = "mock")]
+            {
+                provider::mock::get_raw_quote(report)?
+            }
+            #[cfg(not(feature = "mock"))]
+            {
+                return Err(AttestationError::Mock("feature isn't enabled".to_string()));
+            }
+        }
     };
 
     let quote = Quote::from_bytes(&raw_quote)?;
@@ -1,3 +1,5 @@
+use std::{fs, path::PathBuf};
+
 use tdx_attestation_sdk::{device::DeviceOptions, Tdx};
 
 use crate::{errors::AttestationError, types::RawReport};
@@ -12,3 +14,22 @@ pub fn get_raw_quote(report: RawReport) -> Result<Vec<u8>, AttestationError> {
 
     Ok(quote)
 }
+
+/// Runtime measurement registers exposed by the TSM measurement sysfs
+/// interface, kernel 6.16 and later
+const MEASUREMENTS_DIR: &str = "/sys/class/misc/tdx_guest/measurements";
+
+fn rtmr_path(index: u8) -> PathBuf {
+    PathBuf::from(MEASUREMENTS_DIR).join(format!("rtmr{index}:sha384"))
+}
+
+pub fn rtmr_available(index: u8) -> bool {
+    rtmr_path(index).exists()
+}
+
+/// Extend RTMR `index` with `digest`, the kernel computes
+/// `sha384(rtmr || digest)`
+pub fn extend_rtmr(index: u8, digest: &[u8; 48]) -> Result<(), AttestationError> {
+    fs::write(rtmr_path(index), digest)
+        .map_err(|e| AttestationError::Rtmr(format!("extend rtmr{index} {e}")))
+}
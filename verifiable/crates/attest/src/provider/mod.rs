@@ -1,3 +1,8 @@
 pub mod coco;
 #[cfg(feature = "ioctl")]
 pub mod ioctl;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "sev-guest")]
+pub mod sev_guest;
+pub mod tsm;
@@ -0,0 +1,154 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{errors::AttestationError, types::RawReport};
+
+/// configfs-tsm report interface, kernel 6.7 and later
+pub const TSM_REPORT_DIR: &str = "/sys/kernel/config/tsm/report";
+
+/// `provider` attribute of reports produced by the SEV guest driver
+pub const PROVIDER_SEV_GUEST: &str = "sev_guest";
+
+static ENTRY_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Report entry, removed from configfs on drop
+struct Entry(PathBuf);
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir(&self.0) {
+            tracing::warn!(%err, "remove tsm report entry {}", self.0.display());
+        }
+    }
+}
+
+pub fn get_raw_report(report: RawReport, provider: &str) -> Result<Vec<u8>, AttestationError> {
+    get_raw_report_in(Path::new(TSM_REPORT_DIR), report, provider)
+}
+
+fn get_raw_report_in(
+    root: &Path,
+    report: RawReport,
+    provider: &str,
+) -> Result<Vec<u8>, AttestationError> {
+    let tsm_err = |what: &str, e: std::io::Error| AttestationError::Tsm(format!("{what} {e}"));
+
+    let name = format!(
+        "attest-{}-{}",
+        std::process::id(),
+        ENTRY_SEQ.fetch_add(1, Ordering::Relaxed)
+    );
+    let entry = root.join(name);
+    fs::create_dir(&entry).map_err(|e| tsm_err("create report entry", e))?;
+    let entry = Entry(entry);
+
+    read_report(&entry.0, report, provider)
+}
+
+/// Request a report through the attributes configfs creates in `entry`
+fn read_report(
+    entry: &Path,
+    report: RawReport,
+    provider: &str,
+) -> Result<Vec<u8>, AttestationError> {
+    let tsm_err = |what: &str, e: std::io::Error| AttestationError::Tsm(format!("{what} {e}"));
+
+    fs::write(entry.join("inblob"), report.to_bytes()).map_err(|e| tsm_err("write inblob", e))?;
+    let generation = read_attr(entry, "generation")?;
+
+    let outblob = fs::read(entry.join("outblob")).map_err(|e| tsm_err("read outblob", e))?;
+
+    // Entries are shared configfs objects, another writer in between means
+    // the outblob may not belong to our inblob
+    if read_attr(entry, "generation")? != generation {
+        return Err(AttestationError::Tsm(
+            "report entry written concurrently".to_string(),
+        ));
+    }
+
+    let actual = read_attr(entry, "provider")?;
+    if actual != provider {
+        return Err(AttestationError::Tsm(format!(
+            "unexpected provider {actual}, want {provider}"
+        )));
+    }
+
+    Ok(outblob)
+}
+
+fn read_attr(entry: &Path, attr: &str) -> Result<String, AttestationError> {
+    let value = fs::read_to_string(entry.join(attr))
+        .map_err(|e| AttestationError::Tsm(format!("read {attr} {e}")))?;
+
+    Ok(value.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CString, io::Write, os::unix::ffi::OsStrExt};
+
+    use super::*;
+
+    /// Entry dir with the attributes configfs would create, `outblob` left
+    /// to the test
+    fn fake_entry(name: &str) -> PathBuf {
+        let entry = std::env::temp_dir().join(format!("attest-tsm-{name}-{}", std::process::id()));
+        // Left behind by an earlier failed run
+        let _ = fs::remove_dir_all(&entry);
+        fs::create_dir_all(&entry).unwrap();
+        fs::write(entry.join("generation"), "1\n").unwrap();
+        fs::write(entry.join("provider"), "sev_guest\n").unwrap();
+
+        entry
+    }
+
+    #[test]
+    fn test_read_report() {
+        let entry = fake_entry("report");
+        fs::write(entry.join("outblob"), [0xab; 1184]).unwrap();
+
+        let outblob = read_report(&entry, RawReport::new([7u8; 64]), PROVIDER_SEV_GUEST).unwrap();
+        assert_eq!(outblob, [0xab; 1184]);
+        assert_eq!(fs::read(entry.join("inblob")).unwrap(), [7u8; 64]);
+
+        let err = read_report(&entry, RawReport::new([7u8; 64]), "tdx_guest").unwrap_err();
+        assert!(err.to_string().contains("unexpected provider"), "{err}");
+
+        fs::remove_dir_all(entry).unwrap();
+    }
+
+    #[test]
+    fn test_read_report_generation_race() {
+        let entry = fake_entry("race");
+        let outblob = entry.join("outblob");
+        let fifo = CString::new(outblob.as_os_str().as_bytes()).unwrap();
+        // SAFETY: `fifo` is a valid C string
+        assert_eq!(unsafe { libc::mkfifo(fifo.as_ptr(), 0o600) }, 0);
+
+        // Opening the fifo waits for the reader, which has read the
+        // generation by then. Another inblob write lands before the outblob
+        let generation = entry.join("generation");
+        let writer = std::thread::spawn(move || {
+            let mut outblob = fs::File::options().write(true).open(outblob).unwrap();
+            fs::write(generation, "2\n").unwrap();
+            outblob.write_all(&[0xab; 1184]).unwrap();
+        });
+
+        let err = read_report(&entry, RawReport::new([0u8; 64]), PROVIDER_SEV_GUEST).unwrap_err();
+        assert!(err.to_string().contains("written concurrently"), "{err}");
+
+        writer.join().unwrap();
+        fs::remove_dir_all(entry).unwrap();
+    }
+
+    #[test]
+    fn test_missing_tsm_dir() {
+        let root = std::env::temp_dir().join("attest-tsm-missing");
+
+        let err = get_raw_report_in(&root, RawReport::new([0u8; 64]), PROVIDER_SEV_GUEST);
+        assert!(matches!(err, Err(AttestationError::Tsm(_))));
+    }
+}
@@ -0,0 +1,68 @@
+//! Sealing keys, derivable again only by the same measured guest
+//!
+//! SEV-SNP firmware derives them from the chip key, the launch measurement
+//! and the guest policy, so a restarted guest of the same image gets the
+//! same key on the same host. TDX has no sealing key of its own, a TD
+//! would need a key broker releasing one against its quote.
+
+use std::path::Path;
+
+use crate::{errors::AttestationError, Provider, IOCTL_DEVICE_PATH, SEV_GUEST_DEVICE_PATH};
+
+pub const SEALING_KEY_LEN: usize = 32;
+
+/// Sealing key of this guest
+///
+/// Without a TEE device the well known mock key is only handed out with
+/// `allow_mock` set, anyone can derive it and unseal what it sealed.
+pub fn get_sealing_key(allow_mock: bool) -> Result<[u8; SEALING_KEY_LEN], AttestationError> {
+    let provider = if Path::new(SEV_GUEST_DEVICE_PATH).exists() {
+        Provider::SevSnp
+    } else if Path::new(IOCTL_DEVICE_PATH).exists()
+        || tdx_attestation_sdk::device::Device::default().is_ok()
+    {
+        // Never falls back to the mock key, a TD would seal under a known key
+        Provider::Coco
+    } else if cfg!(feature = "mock") && allow_mock {
+        tracing::warn!("no sev-guest device found, falling back to mock sealing key");
+        Provider::Mock
+    } else {
+        return Err(AttestationError::Sealing(
+            "only sev-snp guests derive sealing keys".to_string(),
+        ));
+    };
+
+    get_sealing_key_with_provider(provider)
+}
+
+pub fn get_sealing_key_with_provider(
+    provider: Provider,
+) -> Result<[u8; SEALING_KEY_LEN], AttestationError> {
+    match provider {
+        Provider::SevSnp => {
+            #[cfg(feature = "sev-guest")]
+            {
+                crate::provider::sev_guest::get_derived_key()
+            }
+            #[cfg(not(feature = "sev-guest"))]
+            {
+                Err(AttestationError::Sealing(
+                    "sev-guest feature isn't enabled".to_string(),
+                ))
+            }
+        }
+        Provider::Ioctl | Provider::Coco => Err(AttestationError::Sealing(
+            "tdx has no sealing key, sealed storage is unsupported on tdx".to_string(),
+        )),
+        Provider::Mock => {
+            #[cfg(feature = "mock")]
+            {
+                Ok(crate::provider::mock::sealing_key())
+            }
+            #[cfg(not(feature = "mock"))]
+            {
+                Err(AttestationError::Mock("feature isn't enabled".to_string()))
+            }
+        }
+    }
+}
@@ -3,17 +3,40 @@ use std::fmt::Display;
 use dcap_rs::{
     constants::HEADER_LEN,
     types::quotes::{
-        body::QuoteBody, version_3::QuoteV3, version_4::QuoteV4, version_5::QuoteV5, QuoteHeader,
+        body::{QuoteBody, TD10ReportBody, TD15ReportBody},
+        version_3::QuoteV3,
+        version_4::QuoteV4,
+        version_5::QuoteV5,
+        QuoteHeader,
     },
 };
 use k256::ecdsa::VerifyingKey;
+use serde::{Deserialize, Serialize};
 
-use crate::errors::QuoteError;
+pub use crate::snp::SnpReport;
+use crate::{errors::QuoteError, report_data::ReportData};
 
+/// Attestation evidence, tagged by the TEE that produced it
 #[derive(Clone, Debug)]
-pub struct Quote {
+pub enum Quote {
+    /// Intel DCAP quote, SGX or TDX
+    Dcap(DcapQuote),
+    /// AMD SEV-SNP attestation report
+    SevSnp(SnpReport),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TeeType {
+    Sgx,
+    Tdx,
+    SevSnp,
+}
+
+#[derive(Clone, Debug)]
+pub struct DcapQuote {
     raw: Vec<u8>,
-    report: QuoteReport,
+    report: Box<QuoteReport>,
 }
 
 #[derive(Clone, Debug)]
@@ -24,7 +47,64 @@ pub enum QuoteReport {
 }
 
 impl Quote {
+    /// Parse raw evidence, telling SEV-SNP reports and DCAP quotes apart by
+    /// size and header
     pub fn from_bytes(bytes: &[u8]) -> Result<Quote, QuoteError> {
+        if SnpReport::is_snp_report(bytes) {
+            return Ok(Quote::SevSnp(SnpReport::from_bytes(bytes)?));
+        }
+
+        Ok(Quote::Dcap(DcapQuote::from_bytes(bytes)?))
+    }
+
+    pub fn tee_type(&self) -> TeeType {
+        match self {
+            Quote::Dcap(quote) => quote.tee_type(),
+            Quote::SevSnp(_) => TeeType::SevSnp,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Quote::Dcap(quote) => quote.to_bytes(),
+            Quote::SevSnp(report) => report.to_bytes(),
+        }
+    }
+
+    pub fn k256_pk_report(&self) -> Result<K256PkReport, QuoteError> {
+        let report_data = self.report_data();
+
+        let point = k256::EncodedPoint::from_bytes(&report_data[0..33])
+            .map_err(|e| QuoteError::ReportData(format!("invalid secp pk {e}")))?;
+        let pk = k256::ecdsa::VerifyingKey::from_encoded_point(&point)
+            .map_err(|e| QuoteError::ReportData(format!("invalid secp pk {e}")))?;
+
+        Ok(K256PkReport { pk })
+    }
+
+    /// DCAP quote body, `None` for SEV-SNP reports
+    pub fn quote_report(&self) -> Option<&QuoteReport> {
+        match self {
+            Quote::Dcap(quote) => Some(quote.quote_report()),
+            Quote::SevSnp(_) => None,
+        }
+    }
+
+    pub fn report_data(&self) -> [u8; 64] {
+        match self {
+            Quote::Dcap(quote) => quote.report_data(),
+            Quote::SevSnp(report) => report.report_data(),
+        }
+    }
+
+    /// Decode the report data as [`ReportData`]
+    pub fn decode_report_data(&self) -> Result<ReportData, QuoteError> {
+        ReportData::decode(&self.report_data())
+    }
+}
+
+impl DcapQuote {
+    pub fn from_bytes(bytes: &[u8]) -> Result<DcapQuote, QuoteError> {
         if bytes.len() < HEADER_LEN {
             return Err(QuoteError::InvalidHeaderSize(bytes.len()));
         }
@@ -37,25 +117,21 @@ impl Quote {
             _ => return Err(QuoteError::UnknownQuote(header.version)),
         };
 
-        Ok(Quote {
+        Ok(DcapQuote {
             raw: bytes.to_vec(),
-            report,
+            report: Box::new(report),
         })
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        self.raw.clone()
+    pub fn tee_type(&self) -> TeeType {
+        match self.report.is_td() {
+            true => TeeType::Tdx,
+            false => TeeType::Sgx,
+        }
     }
 
-    pub fn k256_pk_report(&self) -> Result<K256PkReport, QuoteError> {
-        let report_data = self.report_data();
-
-        let point = k256::EncodedPoint::from_bytes(&report_data[0..33])
-            .map_err(|e| QuoteError::ReportData(format!("invalid secp pk {e}")))?;
-        let pk = k256::ecdsa::VerifyingKey::from_encoded_point(&point)
-            .map_err(|e| QuoteError::ReportData(format!("invalid secp pk {e}")))?;
-
-        Ok(K256PkReport { pk })
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.raw.clone()
     }
 
     pub fn quote_report(&self) -> &QuoteReport {
@@ -63,11 +139,11 @@ impl Quote {
     }
 
     pub fn report_data(&self) -> [u8; 64] {
-        if let QuoteReport::V3(ref quote) = self.report {
+        if let QuoteReport::V3(ref quote) = *self.report {
             return quote.isv_enclave_report.report_data;
         }
 
-        let body = match &self.report {
+        let body = match &*self.report {
             QuoteReport::V4(quote) => quote.quote_body,
             QuoteReport::V5(quote) => quote.quote_body,
             _ => unreachable!(),
@@ -82,21 +158,71 @@ impl Quote {
 }
 
 impl QuoteReport {
+    /// Whether the quote carries a TD report, SGX quotes read as all zero
+    /// measurements
+    pub fn is_td(&self) -> bool {
+        self.td_field(|_| (), |_| ()).is_some()
+    }
+
+    pub fn mrtd(&self) -> [u8; 48] {
+        self.td_field(|r| r.mrtd, |r| r.mrtd).unwrap_or([0u8; 48])
+    }
+
+    pub fn rtmr0(&self) -> [u8; 48] {
+        self.td_field(|r| r.rtmr0, |r| r.rtmr0).unwrap_or([0u8; 48])
+    }
+
+    pub fn rtmr1(&self) -> [u8; 48] {
+        self.td_field(|r| r.rtmr1, |r| r.rtmr1).unwrap_or([0u8; 48])
+    }
+
+    pub fn rtmr2(&self) -> [u8; 48] {
+        self.td_field(|r| r.rtmr2, |r| r.rtmr2).unwrap_or([0u8; 48])
+    }
+
     pub fn rtmr3(&self) -> [u8; 48] {
-        if let QuoteReport::V3(_) = self {
-            return [0u8; 48];
-        }
+        self.td_field(|r| r.rtmr3, |r| r.rtmr3).unwrap_or([0u8; 48])
+    }
+
+    /// RTMR0 to RTMR3 in index order
+    pub fn rtmrs(&self) -> [[u8; 48]; 4] {
+        [self.rtmr0(), self.rtmr1(), self.rtmr2(), self.rtmr3()]
+    }
 
+    /// TDATTRIBUTES, little endian as in the report
+    pub fn td_attributes(&self) -> [u8; 8] {
+        { self.td_field(|r| r.td_attributes, |r| r.td_attributes) }
+            .unwrap_or_default()
+            .to_le_bytes()
+    }
+
+    /// XFAM, little endian as in the report
+    pub fn xfam(&self) -> [u8; 8] {
+        { self.td_field(|r| r.xfam, |r| r.xfam) }
+            .unwrap_or_default()
+            .to_le_bytes()
+    }
+
+    pub fn tee_tcb_svn(&self) -> [u8; 16] {
+        { self.td_field(|r| r.tee_tcb_svn, |r| r.tee_tcb_svn) }.unwrap_or_default()
+    }
+
+    /// Read a TD report field, `None` for SGX quotes
+    fn td_field<T>(
+        &self,
+        td10: impl FnOnce(&TD10ReportBody) -> T,
+        td15: impl FnOnce(&TD15ReportBody) -> T,
+    ) -> Option<T> {
         let body = match self {
-            QuoteReport::V4(quote) => quote.quote_body,
-            QuoteReport::V5(quote) => quote.quote_body,
-            _ => unreachable!(),
+            QuoteReport::V3(_) => return None,
+            QuoteReport::V4(quote) => &quote.quote_body,
+            QuoteReport::V5(quote) => &quote.quote_body,
         };
 
         match body {
-            QuoteBody::SGXQuoteBody(_) => [0u8; 48],
-            QuoteBody::TD10QuoteBody(report) => report.rtmr3,
-            QuoteBody::TD15QuoteBody(report) => report.rtmr3,
+            QuoteBody::SGXQuoteBody(_) => None,
+            QuoteBody::TD10QuoteBody(report) => Some(td10(report)),
+            QuoteBody::TD15QuoteBody(report) => Some(td15(report)),
         }
     }
 }
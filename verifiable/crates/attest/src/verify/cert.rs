@@ -0,0 +1,246 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use p256::ecdsa::{signature::Verifier as _, Signature, VerifyingKey};
+use x509_parser::{
+    certificate::X509Certificate,
+    der_parser::{ber::BerObject, der::parse_der},
+    pem::Pem,
+    time::ASN1Time,
+};
+
+use crate::errors::VerifyError;
+
+const SGX_EXTENSIONS_OID: &str = "1.2.840.113741.1.13.1";
+const SGX_TCB_OID: &str = "1.2.840.113741.1.13.1.2";
+const SGX_PCESVN_OID: &str = "1.2.840.113741.1.13.1.2.17";
+const SGX_FMSPC_OID: &str = "1.2.840.113741.1.13.1.4";
+
+/// DER encoded certificates ordered from leaf to root
+pub(crate) struct CertChain(Vec<Vec<u8>>);
+
+impl CertChain {
+    pub fn from_pem(pem: &[u8]) -> Result<Self, VerifyError> {
+        let certs = Pem::iter_from_buffer(pem)
+            .map(|p| p.map(|p| p.contents))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| VerifyError::Certificate(format!("invalid pem {e}")))?;
+
+        if certs.is_empty() {
+            return Err(VerifyError::Certificate("empty chain".to_string()));
+        }
+
+        Ok(CertChain(certs))
+    }
+
+    /// Check every certificate is signed by its successor, valid at `now`,
+    /// and that the chain terminates in a self signed root holding `root_pk`
+    pub fn verify(&self, root_pk: &[u8; 64], now: SystemTime) -> Result<(), VerifyError> {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| VerifyError::Certificate(e.to_string()))?;
+        let now = ASN1Time::from_timestamp(now.as_secs() as i64)
+            .map_err(|e| VerifyError::Certificate(e.to_string()))?;
+
+        let certs = { self.0.iter() }
+            .map(|der| parse_cert(der))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (i, cert) in certs.iter().enumerate() {
+            if !cert.validity().is_valid_at(now) {
+                return Err(VerifyError::Certificate(format!(
+                    "{} isn't valid now",
+                    cert.subject()
+                )));
+            }
+
+            let issuer = certs.get(i + 1).unwrap_or(cert);
+            if cert.issuer() != issuer.subject() {
+                return Err(VerifyError::Certificate(format!(
+                    "{} isn't issued by {}",
+                    cert.subject(),
+                    issuer.subject()
+                )));
+            }
+
+            verify_cert_signature(cert, &public_key(issuer)?)?;
+        }
+
+        let root = certs.last().expect("non-empty chain");
+        if public_key(root)?.to_encoded_point(false).as_bytes()[1..] != root_pk[..] {
+            return Err(VerifyError::Certificate(format!(
+                "untrusted root {}",
+                root.subject()
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn leaf_public_key(&self) -> Result<VerifyingKey, VerifyError> {
+        public_key(&parse_cert(&self.0[0])?)
+    }
+
+    pub fn pck_extension(&self) -> Result<PckExtension, VerifyError> {
+        PckExtension::from_cert(&parse_cert(&self.0[0])?)
+    }
+}
+
+/// Platform TCB fields carried by the SGX extension of a PCK certificate
+#[derive(Debug)]
+pub(crate) struct PckExtension {
+    pub fmspc: [u8; 6],
+    pub pcesvn: u16,
+    pub cpusvn_components: [u8; 16],
+}
+
+impl PckExtension {
+    fn from_cert(cert: &X509Certificate) -> Result<Self, VerifyError> {
+        let invalid = |msg: &str| VerifyError::Certificate(format!("sgx extension {msg}"));
+
+        let ext = { cert.extensions().iter() }
+            .find(|e| e.oid.to_id_string() == SGX_EXTENSIONS_OID)
+            .ok_or_else(|| invalid("not found"))?;
+
+        let (_, obj) = parse_der(ext.value).map_err(|e| invalid(&e.to_string()))?;
+
+        let mut fmspc = None;
+        let mut pcesvn = None;
+        let mut cpusvn_components = [0u8; 16];
+
+        for (oid, value) in oid_value_pairs(&obj).ok_or_else(|| invalid("malformed"))? {
+            match oid.as_str() {
+                SGX_FMSPC_OID => {
+                    let bytes = value.as_slice().map_err(|e| invalid(&e.to_string()))?;
+                    fmspc = Some(bytes.try_into().map_err(|_| invalid("fmspc length"))?);
+                }
+                SGX_TCB_OID => {
+                    for (oid, value) in oid_value_pairs(value).ok_or_else(|| invalid("tcb"))? {
+                        let Some(idx) = oid.strip_prefix(SGX_TCB_OID).and_then(|s| {
+                            s.strip_prefix('.').and_then(|n| n.parse::<usize>().ok())
+                        }) else {
+                            continue;
+                        };
+
+                        match idx {
+                            1..=16 => {
+                                let svn = value.as_u32().map_err(|e| invalid(&e.to_string()))?;
+                                cpusvn_components[idx - 1] = svn as u8;
+                            }
+                            _ if oid == SGX_PCESVN_OID => {
+                                let svn = value.as_u32().map_err(|e| invalid(&e.to_string()))?;
+                                pcesvn = Some(svn as u16);
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(PckExtension {
+            fmspc: fmspc.ok_or_else(|| invalid("fmspc missing"))?,
+            pcesvn: pcesvn.ok_or_else(|| invalid("pcesvn missing"))?,
+            cpusvn_components,
+        })
+    }
+}
+
+/// Verify a raw `r || s` P-256 signature over `msg`
+pub(crate) fn verify_raw_signature(
+    pk: &VerifyingKey,
+    msg: &[u8],
+    sig: &[u8],
+) -> Result<(), VerifyError> {
+    let sig = Signature::from_slice(sig).map_err(|e| VerifyError::Signature(e.to_string()))?;
+
+    pk.verify(msg, &sig)
+        .map_err(|e| VerifyError::Signature(e.to_string()))
+}
+
+/// Decode a raw `x || y` P-256 public key
+pub(crate) fn raw_public_key(xy: &[u8]) -> Result<VerifyingKey, VerifyError> {
+    let mut sec1 = Vec::with_capacity(65);
+    sec1.push(0x04);
+    sec1.extend_from_slice(xy);
+
+    VerifyingKey::from_sec1_bytes(&sec1).map_err(|e| VerifyError::Signature(e.to_string()))
+}
+
+fn parse_cert(der: &[u8]) -> Result<X509Certificate<'_>, VerifyError> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der)
+        .map_err(|e| VerifyError::Certificate(e.to_string()))?;
+
+    Ok(cert)
+}
+
+fn public_key(cert: &X509Certificate) -> Result<VerifyingKey, VerifyError> {
+    VerifyingKey::from_sec1_bytes(&cert.public_key().subject_public_key.data)
+        .map_err(|e| VerifyError::Certificate(format!("{} public key {e}", cert.subject())))
+}
+
+fn verify_cert_signature(cert: &X509Certificate, pk: &VerifyingKey) -> Result<(), VerifyError> {
+    let sig = Signature::from_der(&cert.signature_value.data)
+        .map_err(|e| VerifyError::Certificate(format!("{} signature {e}", cert.subject())))?;
+
+    pk.verify(cert.tbs_certificate.as_ref(), &sig)
+        .map_err(|e| VerifyError::Certificate(format!("{} signature {e}", cert.subject())))
+}
+
+fn oid_value_pairs<'a, 'b>(obj: &'b BerObject<'a>) -> Option<Vec<(String, &'b BerObject<'a>)>> {
+    { obj.as_sequence().ok()?.iter() }
+        .map(|pair| {
+            let pair = pair.as_sequence().ok()?;
+            let oid = pair.first()?.as_oid().ok()?.to_id_string();
+
+            Some((oid, pair.get(1)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::verify::quote::QuoteParts;
+
+    const QUOTE_V4: &[u8] = include_bytes!("../../fixtures/tdx/quote_v4.bin");
+    const ROOT_PUBKEY: &[u8; 64] = include_bytes!("../../fixtures/tdx/root_pubkey.bin");
+
+    // 2026-06-15
+    const NOW: Duration = Duration::from_secs(1_781_481_600);
+
+    fn pck_chain() -> CertChain {
+        CertChain::from_pem(QuoteParts::parse(QUOTE_V4).unwrap().pck_chain).unwrap()
+    }
+
+    #[test]
+    fn test_pck_chain() {
+        let chain = pck_chain();
+        assert_eq!(chain.0.len(), 3);
+        chain.verify(ROOT_PUBKEY, UNIX_EPOCH + NOW).unwrap();
+
+        let pck = chain.pck_extension().unwrap();
+        assert_eq!(pck.fmspc, [0x00, 0x80, 0x6f, 0x05, 0x00, 0x00]);
+        assert_eq!(pck.pcesvn, 13);
+        assert_eq!(pck.cpusvn_components[..8], [2, 2, 2, 2, 3, 1, 0, 3]);
+    }
+
+    #[test]
+    fn test_pck_chain_out_of_order() {
+        let mut chain = pck_chain();
+        chain.0.swap(0, 1);
+
+        let err = chain.verify(ROOT_PUBKEY, UNIX_EPOCH + NOW).unwrap_err();
+        assert!(err.to_string().contains("isn't issued by"), "{err}");
+    }
+
+    #[test]
+    fn test_empty_chain() {
+        assert!(matches!(
+            CertChain::from_pem(b""),
+            Err(VerifyError::Certificate(_))
+        ));
+    }
+}
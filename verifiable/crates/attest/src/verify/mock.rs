@@ -0,0 +1,60 @@
+use super::{
+    cert::{raw_public_key, verify_raw_signature},
+    dcap_bytes,
+    quote::QuoteParts,
+    verify_attestation_key, QuoteVerifier, TcbStatus, Verdict,
+};
+use crate::{errors::VerifyError, types::Quote};
+
+/// Verifier for quotes produced by [`crate::Provider::Mock`]
+///
+/// Mock quotes carry no certificate chain, the QE report is signed by the
+/// attestation key itself. Without a pinned key, any self consistent mock
+/// quote passes, so this is only meant for development and CI.
+#[derive(Clone, Debug, Default)]
+pub struct MockVerifier {
+    attestation_key: Option<[u8; 64]>,
+}
+
+impl MockVerifier {
+    pub fn new() -> Self {
+        MockVerifier::default()
+    }
+
+    /// Only accept quotes signed by `attestation_key` (`x || y`)
+    pub fn with_attestation_key(mut self, attestation_key: [u8; 64]) -> Self {
+        self.attestation_key = Some(attestation_key);
+        self
+    }
+}
+
+impl QuoteVerifier for MockVerifier {
+    fn verify(&self, quote: &Quote) -> Result<Verdict, VerifyError> {
+        let raw = dcap_bytes(quote)?;
+        let parts = QuoteParts::parse(&raw)?;
+
+        if let Some(pinned) = &self.attestation_key {
+            if parts.attestation_key != pinned {
+                return Err(VerifyError::Signature(
+                    "unexpected mock attestation key".to_string(),
+                ));
+            }
+        }
+
+        verify_attestation_key(&parts)?;
+        verify_raw_signature(
+            &raw_public_key(parts.attestation_key)?,
+            parts.qe_report,
+            parts.qe_report_signature,
+        )?;
+
+        Ok(Verdict {
+            status: TcbStatus::UpToDate,
+            platform_status: TcbStatus::UpToDate,
+            qe_status: TcbStatus::UpToDate,
+            advisory_ids: vec![],
+            fmspc: [0u8; 6],
+            collateral_expired: false,
+        })
+    }
+}
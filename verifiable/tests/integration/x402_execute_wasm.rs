@@ -1,6 +1,10 @@
 use aes_gcm_siv::{aead::Aead, Nonce};
 use alloy::signers::local::PrivateKeySigner;
-use hypervisor::{commitment, crypto, Config};
+use hypervisor::{
+    commitment,
+    crypto::{self, EnvelopeVersion},
+    Config,
+};
 use reqwest::StatusCode;
 use x402_reqwest::{MaxTokenAmountFromAmount, ReqwestWithPayments, ReqwestWithPaymentsBuild};
 use x402_rs::network::{Network, USDCDeployment};
@@ -37,10 +41,10 @@ async fn test_x402_execute_wasm() {
 
     let cipher = crypto::create_encrypt_key(&sk, &session_pk, session_id).unwrap();
 
-    let nonce = crypto::derive_msg_nonce(session_id);
-    let encrypted_wasm = cipher.encrypt(&nonce, wasm.as_slice()).unwrap();
+    let version = EnvelopeVersion::Framed;
+    let encrypted_wasm = version.seal(&cipher, session_id, wasm).unwrap();
     let encrypted_arguments = { vec!["tress".to_string()].into_iter() }
-        .map(|a| cipher.encrypt(&nonce, a.as_bytes()).unwrap())
+        .map(|a| version.seal(&cipher, session_id, a.as_bytes()).unwrap())
         .map(const_hex::encode)
         .collect::<Vec<_>>();
 
@@ -57,13 +61,22 @@ async fn test_x402_execute_wasm() {
         .max(payment.amount(0.02).unwrap())
         .build();
 
+    let req = hypervisor::api::execute::wasm::ExecutionRequest {
+        version,
+        encrypted_wasm: const_hex::encode(&encrypted_wasm),
+        function_name: None,
+        encrypted_arguments,
+        typed_arguments: vec![],
+        encrypted_stdin: None,
+        encrypted_bundle: None,
+        public_key: crypto::pk_to_hex(user_pk),
+        session_id: Some(session_id),
+        limits: Default::default(),
+        capabilities: Default::default(),
+    };
     let response = x402_client
         .post("http://localhost:3000/x402_execute/test/wasm")
-        .json(&hypervisor::api::execute::wasm::ExecutionRequest {
-            encrypted_wasm: const_hex::encode(&encrypted_wasm),
-            encrypted_arguments: encrypted_arguments.clone(),
-            public_key: crypto::pk_to_hex(user_pk),
-        })
+        .json(&req)
         .send()
         .await
         .unwrap();
@@ -74,16 +87,9 @@ async fn test_x402_execute_wasm() {
         .await
         .unwrap();
 
-    let result_nonce = *Nonce::from_slice(&const_hex::decode(result.result_nonce).unwrap());
-    let result_commitment = commitment::build_result_commitment(
-        user_pk,
-        &session_pk,
-        session_id,
-        &const_hex::encode(encrypted_wasm),
-        &encrypted_arguments,
-        result_nonce,
-        &result.encrypted_result,
-    );
+    let result_nonce = *Nonce::from_slice(&const_hex::decode(&result.result_nonce).unwrap());
+    let result_commitment =
+        commitment::build_result_commitment(user_pk, &session_pk, &req, &result);
     assert_eq!(
         result_commitment.as_slice(),
         &const_hex::decode(result.result_commitment).unwrap(),